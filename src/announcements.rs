@@ -0,0 +1,150 @@
+//! Observed BGP announcements, read from MRT RIB dumps, BIRD route dumps or lists of prefixes and origins.
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use cidr_utils::cidr::IpCidr;
+use crate::import::{parse_asn, parse_prefix};
+use crate::inflate::gunzip;
+use crate::vrp::prefix_string;
+use crate::{io_error, mrt, BoxResult};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Announcement {
+    pub prefix: IpCidr,
+    pub origin: u32,
+}
+
+impl fmt::Display for Announcement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} AS{}", prefix_string(&self.prefix), self.origin)
+    }
+}
+
+/// Reads announcements in one of the formats recognized by `parse_announcements`.
+pub fn read_announcements(path: &Path) -> BoxResult<Vec<Announcement>> {
+    let data = fs::read(path).map_err(|e| io_error(e, format!("Unable to read announcements {}", path.display())))?;
+    parse_announcements(&data).map_err(|e| format!("Unable to parse announcements {}: {}", path.display(), e).into())
+}
+
+/// Parses an MRT RIB dump, the output of BIRD's `show route [all]` or a list of `<prefix> <origin>`
+/// lines, optionally gzip compressed. The result is sorted and free of duplicates.
+pub fn parse_announcements(data: &[u8]) -> BoxResult<Vec<Announcement>> {
+    if data.starts_with(&[0x1f, 0x8b]) {
+        return parse_announcements(&gunzip(data, usize::MAX)?);
+    }
+    let announcements: BTreeSet<Announcement> = match mrt::is_mrt(data) {
+        true => mrt::read_routes(data)?.into_iter().map(|(prefix, origin)| Announcement { prefix, origin }).collect(),
+        false => {
+            let content = std::str::from_utf8(data).map_err(|_| "neither an MRT file nor text")?;
+            let is_bird = content.lines().any(|l| l.starts_with("Table ") || l.starts_with("BIRD ") || l.contains("[AS"));
+            match is_bird {
+                true => parse_bird_routes(content)?,
+                false => parse_list(content)?,
+            }
+        }
+    };
+    Ok(announcements.into_iter().collect())
+}
+
+/// Parses lines of a prefix and an origin AS separated by whitespace or a comma.
+fn parse_list(content: &str) -> BoxResult<BTreeSet<Announcement>> {
+    let mut result: BTreeSet<Announcement> = BTreeSet::new();
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let parse_line = || -> BoxResult<Announcement> {
+            let fields: Vec<&str> = line.split(|c: char| c == ',' || c.is_whitespace()).filter(|f| !f.is_empty()).collect();
+            match fields.as_slice() {
+                [prefix, origin] => Ok(Announcement { prefix: parse_prefix(prefix)?, origin: parse_asn(origin)? }),
+                _ => Err("expected '<prefix> <origin>'".into()),
+            }
+        };
+        result.insert(parse_line().map_err(|e| format!("line {}: {}", index + 1, e))?);
+    }
+    Ok(result)
+}
+
+/// Parses the output of `birdc show route [all]` of BIRD 1 or 2. The origin is taken from the
+/// `[AS<asn><i|e|?>]` suffix of a route or from its `BGP.as_path` attribute. Other routes are skipped.
+fn parse_bird_routes(content: &str) -> BoxResult<BTreeSet<Announcement>> {
+    let mut result: BTreeSet<Announcement> = BTreeSet::new();
+    let mut prefix: Option<IpCidr> = None;
+    for (index, line) in content.lines().enumerate() {
+        // Routes for another prefix start at the beginning of the line
+        if !line.starts_with(char::is_whitespace) {
+            let first = line.split_whitespace().next().unwrap_or_default();
+            if first.contains('/') {
+                prefix = Some(parse_prefix(first).map_err(|e| format!("line {}: {}", index + 1, e))?);
+            } else {
+                prefix = None;
+            }
+        }
+        let Some(prefix) = prefix else {
+            continue;
+        };
+        let origin = match line.trim().strip_prefix("BGP.as_path:") {
+            // A path ending in an AS set has no definite origin
+            Some(path) => path.split_whitespace().last().and_then(|asn| asn.parse::<u32>().ok()),
+            None => line.rfind("[AS").and_then(|start| {
+                let digits: String = line[start + 3..].chars().take_while(char::is_ascii_digit).collect();
+                digits.parse::<u32>().ok()
+            }),
+        };
+        if let Some(origin) = origin {
+            result.insert(Announcement { prefix, origin });
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(content: &str) -> Vec<String> {
+        parse_announcements(content.as_bytes()).unwrap().iter().map(Announcement::to_string).collect()
+    }
+
+    #[test]
+    fn bird2_routes() {
+        let content = "BIRD 2.0.12 ready.\n\
+            Table master4:\n\
+            172.20.0.0/24        unicast [peer1 2026-10-01] * (100) [AS4242420000i]\n\
+            \tvia 172.20.16.1 on eth0\n\
+            \x20                    unicast [peer2 2026-10-01] (100) [AS4242420001i]\n\
+            \tvia 172.20.16.2 on eth1\n\
+            172.20.1.0/24        unicast [peer1 2026-10-01] * (100) [?]\n\
+            \tvia 172.20.16.1 on eth0\n\
+            \tType: BGP univ\n\
+            \tBGP.origin: IGP\n\
+            \tBGP.as_path: 4242420003 4242420002\n\
+            172.20.2.0/24        unicast [peer1 2026-10-01] * (100) [?]\n\
+            \tBGP.as_path: 4242420003 {4242420004 4242420005}\n\
+            172.20.3.0/24        blackhole [static1 2026-10-01] * (200)\n\
+            \n\
+            Table master6:\n\
+            fd00::/48            unicast [peer1 2026-10-01] * (100) [AS4242420006i]\n";
+        assert_eq!(parse(content), ["172.20.0.0/24 AS4242420000", "172.20.0.0/24 AS4242420001", "172.20.1.0/24 AS4242420002", "fd00::/48 AS4242420006"]);
+    }
+
+    #[test]
+    fn bird1_routes() {
+        let content = "172.20.0.0/24      via 172.20.16.1 on eth0 [peer1 2026-10-01] * (100) [AS4242420000e]\n\
+            \x20                  via 172.20.16.2 on eth1 [peer2 2026-10-01] (100) [AS4242420001?]\n\
+            172.20.1.0/24      dev eth0 [direct1 2026-10-01] * (240)\n";
+        assert_eq!(parse(content), ["172.20.0.0/24 AS4242420000", "172.20.0.0/24 AS4242420001"]);
+    }
+
+    #[test]
+    fn lists() {
+        let content = "# prefix origin\nfd00::/48 AS4242420001\n172.20.0.0/24,4242420000\n172.20.0.0/24 as4242420000\n";
+        assert_eq!(parse(content), ["172.20.0.0/24 AS4242420000", "fd00::/48 AS4242420001"]);
+        let error = parse_announcements(b"172.20.0.0/24\n").unwrap_err().to_string();
+        assert_eq!(error, "line 1: expected '<prefix> <origin>'");
+        assert_eq!(parse_announcements(b"\xff\xfe").unwrap_err().to_string(), "neither an MRT file nor text");
+    }
+}
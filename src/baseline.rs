@@ -0,0 +1,98 @@
+//! Baseline files list accepted warnings, so that strict mode only fails on new problems.
+//! Each line holds the code of a warning and the file or entry it was found in, separated by
+//! whitespace. Lines starting with `#` are comments.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+use crate::{io_error, BoxResult, Warning, Warnings};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Baseline {
+    entries: BTreeSet<(String, String)>,
+}
+
+impl Baseline {
+    pub fn read(path: &Path) -> BoxResult<Self> {
+        let content = fs::read_to_string(path).map_err(|e| io_error(e, format!("Unable to read baseline file {}", path.display())))?;
+        Self::parse(&content).map_err(|e| format!("Invalid baseline file {}: {}", path.display(), e).into())
+    }
+
+    pub fn parse(content: &str) -> BoxResult<Self> {
+        let mut entries = BTreeSet::new();
+        for (index, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (code, object) = line.split_once(char::is_whitespace).ok_or_else(|| format!("line {}: missing file", index + 1))?;
+            entries.insert((code.to_owned(), object.trim_start().to_owned()));
+        }
+        Ok(Baseline { entries })
+    }
+
+    /// A baseline accepting all of `warnings`.
+    pub fn from_warnings(warnings: &[Warning]) -> Self {
+        Baseline { entries: warnings.iter().map(|w| (w.code.to_owned(), w.object.clone())).collect() }
+    }
+
+    pub fn render(&self) -> String {
+        let mut result = String::from("# Accepted warnings: <code> <file>\n");
+        for (code, object) in &self.entries {
+            result.push_str(&format!("{} {}\n", code, object));
+        }
+        result
+    }
+
+    pub fn contains(&self, warning: &Warning) -> bool {
+        self.entries.contains(&(warning.code.to_owned(), warning.object.clone()))
+    }
+
+    /// Removes the accepted warnings and returns the entries that did not match any warning.
+    pub fn filter(&self, warnings: &mut Warnings) -> Vec<String> {
+        let found: BTreeSet<(String, String)> = Self::from_warnings(warnings).entries;
+        warnings.retain(|w| !self.contains(w));
+        self.entries.difference(&found).map(|(code, object)| format!("{} {}", code, object)).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn warning(code: &'static str, object: &str) -> Warning {
+        Warning::new(code, object, format!("{} in {}", code, object))
+    }
+
+    #[test]
+    fn parse_and_render() {
+        let baseline = Baseline::parse("# comment\n\ninvalid-origin  data/route/10.1.0.0_16\r\nkey-mismatch data/person/FOO3-DN42\n").unwrap();
+        assert_eq!(baseline.len(), 2);
+        assert!(baseline.contains(&warning("invalid-origin", "data/route/10.1.0.0_16")));
+        assert_eq!(Baseline::parse(&baseline.render()).unwrap(), baseline);
+        assert!(Baseline::parse("invalid-origin\n").is_err());
+    }
+
+    #[test]
+    fn filter() {
+        let baseline = Baseline::parse("invalid-origin data/route/10.1.0.0_16\nhost-bits data/route/10.2.0.1_16\n").unwrap();
+        let mut warnings = vec![
+            warning("invalid-origin", "data/route/10.1.0.0_16"),
+            // Same file with a different code, and same code in a different file
+            warning("host-bits", "data/route/10.1.0.0_16"),
+            warning("invalid-origin", "data/route/10.3.0.0_16"),
+        ];
+        let stale = baseline.filter(&mut warnings);
+        assert_eq!(stale, ["host-bits data/route/10.2.0.1_16"]);
+        let remaining: Vec<(&str, &str)> = warnings.iter().map(|w| (w.code, w.object.as_str())).collect();
+        assert_eq!(remaining, [("host-bits", "data/route/10.1.0.0_16"), ("invalid-origin", "data/route/10.3.0.0_16")]);
+    }
+}
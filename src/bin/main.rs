@@ -1,47 +1,942 @@
+use std::error::Error;
+use cidr_utils::cidr::IpCidr;
+use std::path::{Path, PathBuf};
 use std::process::exit;
-use roa_wizard_lib::{check_and_output, generate_bird, generate_json, PACKAGE_NAME, VERSION};
+use std::time::{Duration, Instant, SystemTime};
+use roa_wizard_lib::{try_check_and_write, exit_code_for_error, get_vrps, is_io_error, get_roa_objects_for_family, prefix_string, render, render_with_commit, validate, write_output, Family, FilterOverrides, OutputFormat, ReportFormat, Warning, EXIT_DIFFERENCES, EXIT_HOOK_FAILURE, EXIT_IO_ERROR, EXIT_LOCKED, EXIT_REGISTRY_ERROR, EXIT_SAFETY_CHECK, EXIT_STRICT_FAILURE, EXIT_USAGE, PACKAGE_NAME, VERSION};
+use roa_wizard_lib::announcements::read_announcements;
+use roa_wizard_lib::baseline::Baseline;
+use roa_wizard_lib::blame::{blame, render_blame, BlameQuery};
+use roa_wizard_lib::config::read_config;
+use roa_wizard_lib::conflicts::ConflictReport;
+use roa_wizard_lib::date::{format_time, parse_time};
+use roa_wizard_lib::diff::{RegistryDiff, VrpSet};
+use roa_wizard_lib::publish::{run_hook, LockFile};
+use roa_wizard_lib::registry::{is_route_file, RegistryDatabase};
+use roa_wizard_lib::run::{append_snapshot, run_config, RunOptions, RunSummary};
+use roa_wizard_lib::import::{parse_asn, parse_prefix, read_roa_file};
+use roa_wizard_lib::maxlength::MaxLengthReport;
+use roa_wizard_lib::safety::SafetyChecks;
+use roa_wizard_lib::snapshot::SnapshotStore;
+use roa_wizard_lib::stats::{growth_statistics, render_statistics, StatsFormat};
+use roa_wizard_lib::suggest::suggest_route_objects;
+use roa_wizard_lib::source::{self, GitSource, OverlaySource, RegistrySource};
+use roa_wizard_lib::watch::{watch, WatchEvent, WatchOptions};
 
 fn show_usage() {
     println!("{} {}", PACKAGE_NAME, VERSION);
-    println!("Usage: <path to registry root> <action> [flag]");
+    println!("Usage: {} <command> <path to registry root> [options]", PACKAGE_NAME);
+    println!("       {} run|watch --config <file> [options]", PACKAGE_NAME);
+    println!("       {} diff <path to registry root> <revision> <revision> [options]", PACKAGE_NAME);
+    println!("       {} diff <path to registry root> <path to registry root> [options]", PACKAGE_NAME);
+    println!("       {} diff <path to registry root> --overlay <dir> [options]", PACKAGE_NAME);
+    println!("       {} compare <ROA file or registry> <ROA file or registry> [options]", PACKAGE_NAME);
+    println!("       {} blame <path to registry root> <prefix|asn> [options]", PACKAGE_NAME);
+    println!("       {} query <snapshot store> [<prefix> <asn>] [--at <time>] [options]", PACKAGE_NAME);
+    println!("       {} stats <path to registry root> [options]", PACKAGE_NAME);
+    println!("       {} conflicts <path to registry root> [options]", PACKAGE_NAME);
+    println!("       {} maxlength <path to registry root> <announcements> [options]", PACKAGE_NAME);
+    println!("       {} suggest <path to registry root> <announcements> [options]", PACKAGE_NAME);
     println!();
-    println!("Where <action>:");
-    println!("'v4' : bird2 v4 format");
-    println!("'v6' : bird2 v6 format");
-    println!("'json' : json format");
+    println!("Where <command>:");
+    println!("generate : Output in the format given by --format (default: bird)");
+    println!("v4       : bird2 v4 format (same as 'generate --format bird --family v4')");
+    println!("v6       : bird2 v6 format (same as 'generate --format bird --family v6')");
+    println!("json     : json format (same as 'generate --format json --family both')");
+    println!("check    : Only validate the registry and report warnings, including registry objects whose");
+    println!("           file name does not match their primary key and duplicate objects");
+    println!("baseline : Print the warnings of check in the format of a --baseline file");
+    println!("run      : Render every output described in the config file given by --config");
+    println!("watch    : Like run, but keep running and regenerate whenever the registry changes");
+    println!("           (the config file is reloaded on SIGHUP)");
+    println!("diff     : Report VRPs added, removed or modified between two revisions or registries,");
+    println!("           or by the --overlay of a registry, grouped by maintainer and origin");
+    println!("compare  : Report the differences between the VRPs of two ROA files (bird, JSON, rpki-client JSON");
+    println!("           or CSV) or registries regardless of order and formatting");
+    println!("blame    : Show the commits that introduced the VRPs of a prefix or origin and last changed");
+    println!("           their max length (reads the history of --revision, default: HEAD)");
+    println!("query    : Print the VRPs of a snapshot store as of --at, or validate the route <prefix>");
+    println!("           originated by <asn> against them (RFC 6811)");
+    println!("stats    : Monthly growth statistics from the git history of --revision (default: HEAD)");
+    println!("conflicts: List prefixes with multiple origins, more-specific route objects with other origins and");
+    println!("           maintainers than the covering object and prefixes defined in several files");
+    println!("maxlength: List VRPs whose max length authorises more-specifics that are not announced, with tighter");
+    println!("           VRPs (RFC 9319)");
+    println!("suggest  : Print route objects that make the announcements valid whose prefix lies in an inetnum");
+    println!("           sharing a maintainer with the aut-num of the origin (written below the -o directory)");
     println!();
-    println!("Where <flag>:");
-    println!("'' : No flag");
-    println!("'strict' : Abort program if an error was found in a file");
-    exit(2)
+    println!("Announcements are read from MRT RIB dumps, 'birdc show route [all]' output or '<prefix> <origin>' lines.");
+    println!();
+    println!("Options:");
+    println!("-r, --revision <rev>     : Read the registry from a git commit, tag or branch instead of the working tree");
+    println!("    --overlay <dir>      : Apply the files in <dir> on top of the registry, empty files delete objects");
+    println!("-o, --output <file>      : Atomically replace <file> with the output instead of writing to stdout");
+    println!("-f, --format <format>    : Output format: 'bird' or 'json' ('text' or 'json' for diff, compare, blame, conflicts,\n                           maxlength and validating queries, 'csv' or 'json' for stats)");
+    println!("    --family <family>    : Address family: 'v4', 'v6' or 'both'");
+    println!("-s, --strict             : Abort program if an error was found in a file");
+    println!("    --filter-file <file> : Use <file> instead of data/filter.txt");
+    println!("    --filter6-file <file>: Use <file> instead of data/filter6.txt");
+    println!("    --include-source <source>: Only use route objects with this 'source:', may be repeated");
+    println!("    --exclude-source <source>: Ignore route objects with this 'source:', may be repeated");
+    println!("    --slurm <file>       : Apply local exceptions from a SLURM (RFC 8416) file, may be repeated");
+    println!("    --baseline <file>    : Ignore the accepted warnings listed in <file>, also in strict mode");
+    println!("-c, --config <file>      : Config file for the run command");
+    println!("    --on-change <command>: Run <command> if the file given by --output changed");
+    println!("    --lock-file <file>   : Hold <file> as a lock to prevent concurrent runs");
+    println!("    --snapshot-store <dir>: Append the generated VRPs to the snapshot store in <dir>");
+    println!("    --at <time>          : query: Point in time as UNIX timestamp or YYYY-MM-DD[THH:MM[:SS]] (UTC, default: now)");
+    println!("    --max-drop-percent <n>: Refuse to replace an output if its VRP count drops by more than <n>%");
+    println!("    --must-exist <vrp>   : Refuse to publish if '<prefix> [max <length>] <asn>' is missing, may be repeated");
+    println!("    --debounce <secs>    : watch: Wait until no changes occurred for <secs> (default: 2)");
+    println!("    --poll-interval <secs>: watch: Interval between scans when polling (default: 5)");
+    println!("    --poll               : watch: Poll for changes instead of using inotify");
+    println!("-q, --quiet              : Do not print warnings");
+    println!("-v, --verbose            : Print processing statistics to stderr");
+    println!("-V, --version            : Print version information");
+    println!("-h, --help               : Print this help");
+    println!();
+    println!("Exit codes:");
+    println!("0 : Success (warnings may have been printed)");
+    println!("1 : Registry error (the registry content could not be used)");
+    println!("2 : Usage or configuration error");
+    println!("3 : I/O error (reading the registry or writing the output failed)");
+    println!("4 : Strict mode failure (warnings occurred and --strict was given)");
+    println!("5 : A post-update hook failed");
+    println!("6 : Another run holds the lock file");
+    println!("7 : A publication safety check failed, the previous output was kept");
+    println!("8 : compare found differences");
+    println!();
+    println!("The registry root may also be a .tar or .tar.gz archive of the registry, e.g. from 'git archive'.");
+    println!("The legacy form '<path to registry root> <v4|v6|json> [strict]' is still accepted.");
 }
 
-fn main() {
-    if std::env::args().len() < 3 {
-        println!("Missing commandline arguments");
-        show_usage();
+fn usage_error(message: &str) -> ! {
+    eprintln!("{}", message);
+    eprintln!("Run '{} --help' for usage information", PACKAGE_NAME);
+    exit(EXIT_USAGE)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Command {
+    Generate,
+    Check,
+    Run,
+    Watch,
+    Baseline,
+    Diff,
+    Compare,
+    Blame,
+    Query,
+    Stats,
+    Conflicts,
+    MaxLength,
+    Suggest,
+}
+
+struct Args {
+    command: Command,
+    registry: PathBuf,
+    revision: Option<String>,
+    overlay: Option<PathBuf>,
+    output: Option<PathBuf>,
+    format: OutputFormat,
+    report_format: ReportFormat,
+    stats_format: StatsFormat,
+    /// Revisions or registry paths to compare.
+    compare: Vec<String>,
+    blame: Option<BlameQuery>,
+    /// Route to validate in a snapshot query.
+    route: Option<(IpCidr, u32)>,
+    at: Option<i64>,
+    family: Family,
+    strict: bool,
+    filters: FilterOverrides,
+    slurm_files: Vec<PathBuf>,
+    baseline: Option<PathBuf>,
+    config: Option<PathBuf>,
+    on_change: Option<String>,
+    lock_file: Option<PathBuf>,
+    snapshot_store: Option<PathBuf>,
+    watch: WatchOptions,
+    safety: SafetyChecks,
+    quiet: bool,
+    verbose: bool,
+}
+
+fn parse_args(raw: Vec<String>) -> Result<Args, String> {
+    let mut format: Option<String> = None;
+    let mut family: Option<Family> = None;
+    let mut positional: Vec<String> = Vec::new();
+    let mut args = Args {
+        command: Command::Generate,
+        registry: PathBuf::new(),
+        revision: None,
+        overlay: None,
+        output: None,
+        format: OutputFormat::Bird,
+        report_format: ReportFormat::Text,
+        stats_format: StatsFormat::Csv,
+        compare: Vec::new(),
+        blame: None,
+        route: None,
+        at: None,
+        family: Family::Both,
+        strict: false,
+        filters: FilterOverrides::default(),
+        slurm_files: Vec::new(),
+        baseline: None,
+        config: None,
+        on_change: None,
+        lock_file: None,
+        snapshot_store: None,
+        watch: WatchOptions::default(),
+        safety: SafetyChecks::default(),
+        quiet: false,
+        verbose: false,
+    };
+
+    let mut iter = raw.into_iter();
+    while let Some(arg) = iter.next() {
+        let (name, inline_value) = match arg.split_once('=') {
+            Some((n, v)) if arg.starts_with("--") => (n.to_owned(), Some(v.to_owned())),
+            _ => (arg.clone(), None),
+        };
+        let mut value = |name: &str| -> Result<String, String> {
+            inline_value.clone().or_else(|| iter.next()).ok_or(format!("Missing value for option {}", name))
+        };
+        match name.as_str() {
+            "-h" | "--help" => {
+                show_usage();
+                exit(0)
+            }
+            "-V" | "--version" => {
+                println!("{} {}", PACKAGE_NAME, VERSION);
+                exit(0)
+            }
+            "-r" | "--revision" => args.revision = Some(value(&name)?),
+            "--overlay" => args.overlay = Some(PathBuf::from(value(&name)?)),
+            "-o" | "--output" => args.output = Some(PathBuf::from(value(&name)?)),
+            "-f" | "--format" => format = Some(value(&name)?),
+            "--family" => family = Some(value(&name)?.parse()?),
+            "-s" | "--strict" => args.strict = true,
+            "--filter-file" => args.filters.filter_v4 = Some(PathBuf::from(value(&name)?)),
+            "--filter6-file" => args.filters.filter_v6 = Some(PathBuf::from(value(&name)?)),
+            "--include-source" => args.filters.sources.include.push(value(&name)?),
+            "--exclude-source" => args.filters.sources.exclude.push(value(&name)?),
+            "--slurm" => args.slurm_files.push(PathBuf::from(value(&name)?)),
+            "--baseline" => args.baseline = Some(PathBuf::from(value(&name)?)),
+            "-c" | "--config" => args.config = Some(PathBuf::from(value(&name)?)),
+            "--on-change" => args.on_change = Some(value(&name)?),
+            "--lock-file" => args.lock_file = Some(PathBuf::from(value(&name)?)),
+            "--snapshot-store" => args.snapshot_store = Some(PathBuf::from(value(&name)?)),
+            "--at" => args.at = Some(parse_time(&value(&name)?).map_err(|e| format!("Invalid value for option {}: {}", name, e))?),
+            "--debounce" => args.watch.debounce = parse_seconds(&name, &value(&name)?)?,
+            "--poll-interval" => args.watch.poll_interval = parse_seconds(&name, &value(&name)?)?,
+            "--poll" => args.watch.force_polling = true,
+            "--max-drop-percent" => {
+                let v = value(&name)?;
+                args.safety.max_drop_percent = Some(v.parse::<f64>().ok().filter(|v| *v >= 0.0)
+                    .ok_or(format!("Invalid percentage for option {}: {}", name, v))?);
+            }
+            "--must-exist" => {
+                let v = value(&name)?;
+                args.safety.must_exist.push(v.parse().map_err(|e| format!("Invalid VRP for option {}: {}", name, e))?);
+            }
+            "-q" | "--quiet" => args.quiet = true,
+            "-v" | "--verbose" => args.verbose = true,
+            _ if name.starts_with('-') && name.len() > 1 => return Err(format!("Unknown option {}", name)),
+            _ => positional.push(arg),
+        }
     }
 
-    let base_path = std::env::args().nth(1).expect("no registry path given");
-    let action = std::env::args().nth(2).expect("no action given");
-    let strict = std::env::args().nth(3).unwrap_or_default() == "strict";
+    if positional.is_empty() {
+        return Err("Missing command".to_owned());
+    }
 
-    match action.as_str() {
-        "v4" => {
-            check_and_output(generate_bird(base_path, false), strict);
+    let first = positional.remove(0);
+    let action = match first.as_str() {
+        "generate" | "v4" | "v6" | "json" | "check" | "baseline" | "run" | "watch" | "diff" | "compare" | "blame" | "query" | "stats" | "conflicts" | "maxlength" | "suggest" => first,
+        _ => {
+            // Legacy form: <path> <action> [strict]
+            if positional.is_empty() {
+                return Err(format!("Unknown command {}", first));
+            }
+            let action = positional.remove(0);
+            if positional.first().map(String::as_str) == Some("strict") {
+                positional.remove(0);
+                args.strict = true;
+            }
+            positional.insert(0, first);
+            action
         }
-        "v6" => {
-            check_and_output(generate_bird(base_path, true), strict);
+    };
+
+    args.command = match action.as_str() {
+        "generate" => Command::Generate,
+        "v4" | "v6" => {
+            format.get_or_insert("bird".to_owned());
+            family.get_or_insert(if action == "v4" { Family::V4 } else { Family::V6 });
+            Command::Generate
         }
         "json" => {
-            check_and_output(generate_json(base_path), strict);
+            format.get_or_insert("json".to_owned());
+            Command::Generate
         }
-        _ => {
-            println!("Unknown argument for <action>");
-            show_usage();
+        "check" => Command::Check,
+        "baseline" => Command::Baseline,
+        "run" => Command::Run,
+        "watch" => Command::Watch,
+        "diff" => Command::Diff,
+        "compare" => Command::Compare,
+        "blame" => Command::Blame,
+        "query" => Command::Query,
+        "stats" => Command::Stats,
+        "conflicts" => Command::Conflicts,
+        "maxlength" => Command::MaxLength,
+        "suggest" => Command::Suggest,
+        _ => return Err(format!("Unknown command {}", action)),
+    };
+    if args.overlay.is_some() && !matches!(args.command, Command::Generate | Command::Check | Command::Diff) {
+        return Err("--overlay is only supported by the generate, check and diff commands".to_owned());
+    }
+
+    if args.command == Command::Run || args.command == Command::Watch {
+        if args.config.is_none() {
+            return Err(format!("The {} command requires --config", action));
+        }
+        if let Some(arg) = positional.first() {
+            return Err(format!("Unexpected argument {}", arg));
+        }
+        return Ok(args);
+    }
+
+    if args.command == Command::Diff {
+        if args.overlay.is_some() && positional.len() != 1 {
+            return Err("The diff command compares a registry with its overlay if --overlay is given".to_owned());
+        }
+        if args.overlay.is_none() && !(2..=3).contains(&positional.len()) {
+            return Err("The diff command requires a registry and two revisions, or two registries".to_owned());
+        }
+        if args.revision.is_some() && positional.len() == 3 {
+            return Err("--revision can not be combined with two revisions to compare".to_owned());
+        }
+        args.registry = PathBuf::from(positional.remove(0));
+        args.compare = positional;
+        args.report_format = format.as_deref().unwrap_or("text").parse()?;
+        args.family = family.unwrap_or(Family::Both);
+        return Ok(args);
+    }
+
+    if args.command == Command::Compare {
+        if positional.len() != 2 {
+            return Err("The compare command requires two ROA files or registries".to_owned());
+        }
+        args.registry = PathBuf::from(positional.remove(0));
+        args.compare = positional;
+        args.report_format = format.as_deref().unwrap_or("text").parse()?;
+        args.family = family.unwrap_or(Family::Both);
+        return Ok(args);
+    }
+
+    if args.command == Command::Blame {
+        if positional.len() != 2 {
+            return Err("The blame command requires a registry and a prefix or AS number".to_owned());
+        }
+        args.blame = Some(positional[1].parse().map_err(|e| format!("Invalid prefix or AS number: {}", e))?);
+        args.registry = PathBuf::from(positional.remove(0));
+        args.report_format = format.as_deref().unwrap_or("text").parse()?;
+        args.family = family.unwrap_or(Family::Both);
+        return Ok(args);
+    }
+
+    if args.command == Command::Query {
+        match positional.len() {
+            1 => args.format = format.as_deref().unwrap_or("bird").parse()?,
+            3 => {
+                let prefix = parse_prefix(&positional[1]).map_err(|e| format!("Invalid prefix: {}", e))?;
+                let asn = parse_asn(&positional[2]).map_err(|e| format!("Invalid AS number: {}", e))?;
+                args.route = Some((prefix, asn));
+                args.report_format = format.as_deref().unwrap_or("text").parse()?;
+            }
+            _ => return Err("The query command requires a snapshot store and optionally a prefix and an AS number".to_owned()),
+        }
+        args.registry = PathBuf::from(positional.remove(0));
+        args.family = family.unwrap_or(Family::Both);
+        return Ok(args);
+    }
+    if args.command == Command::Stats {
+        if positional.len() != 1 {
+            return Err(match positional.len() {
+                0 => "Missing path to registry root".to_owned(),
+                _ => format!("Unexpected argument {}", positional[1]),
+            });
+        }
+        args.registry = PathBuf::from(positional.remove(0));
+        args.stats_format = format.as_deref().unwrap_or("csv").parse()?;
+        args.family = family.unwrap_or(Family::Both);
+        return Ok(args);
+    }
+    if args.command == Command::Conflicts {
+        if positional.len() != 1 {
+            return Err(match positional.len() {
+                0 => "Missing path to registry root".to_owned(),
+                _ => format!("Unexpected argument {}", positional[1]),
+            });
+        }
+        args.registry = PathBuf::from(positional.remove(0));
+        args.report_format = format.as_deref().unwrap_or("text").parse()?;
+        args.family = family.unwrap_or(Family::Both);
+        return Ok(args);
+    }
+    if args.command == Command::MaxLength || args.command == Command::Suggest {
+        if positional.len() != 2 {
+            return Err(format!("The {} command requires a registry and a file of announcements", action));
+        }
+        args.registry = PathBuf::from(positional.remove(0));
+        args.compare = positional;
+        args.report_format = format.as_deref().unwrap_or("text").parse()?;
+        args.family = family.unwrap_or(Family::Both);
+        return Ok(args);
+    }
+    if args.at.is_some() {
+        return Err("--at is only supported by the query command".to_owned());
+    }
+
+    if positional.len() != 1 {
+        return Err(match positional.len() {
+            0 => "Missing path to registry root".to_owned(),
+            _ => format!("Unexpected argument {}", positional[1]),
+        });
+    }
+
+    args.registry = PathBuf::from(positional.remove(0));
+    args.format = format.as_deref().unwrap_or("bird").parse()?;
+    args.family = family.unwrap_or(Family::Both);
+    if args.command == Command::Check && args.output.is_some() {
+        return Err("The check command does not produce output".to_owned());
+    }
+    if args.on_change.is_some() && args.output.is_none() {
+        return Err("--on-change requires --output".to_owned());
+    }
+    if args.command == Command::Generate && args.safety.max_drop_percent.is_some() && args.output.is_none() {
+        return Err("--max-drop-percent requires --output".to_owned());
+    }
+    Ok(args)
+}
+
+fn parse_seconds(name: &str, value: &str) -> Result<Duration, String> {
+    value.parse::<f64>().ok().filter(|v| v.is_finite() && *v >= 0.0).map(Duration::from_secs_f64)
+        .ok_or(format!("Invalid number of seconds for option {}: {}", name, value))
+}
+
+fn main() {
+    let args = match parse_args(std::env::args().skip(1).collect()) {
+        Ok(args) => args,
+        Err(err) => usage_error(&err),
+    };
+
+    let start = Instant::now();
+    match args.command {
+        Command::Generate => {
+            if let Err(code) = generate_output(&args, start) {
+                exit(code)
+            }
+        }
+        Command::Run => run(&args),
+        Command::Diff => {
+            if let Err(code) = diff(&args) {
+                exit(code)
+            }
+        }
+        Command::Compare => {
+            if let Err(code) = compare(&args) {
+                exit(code)
+            }
+        }
+        Command::Query => {
+            if let Err(code) = query(&args) {
+                exit(code)
+            }
+        }
+        Command::Stats => {
+            let result = growth_statistics(&args.registry, args.revision.as_deref().unwrap_or("HEAD"), args.family, &args.filters);
+            let output = match result {
+                Ok(stats) => render_statistics(&stats, args.stats_format),
+                Err(err) => {
+                    eprintln!("Error: {}", err);
+                    exit(exit_code_for_error(err.as_ref()))
+                }
+            };
+            if let Err(err) = write_output(args.output.as_deref(), &output) {
+                eprintln!("Error: {}", err);
+                exit(EXIT_IO_ERROR)
+            }
+        }
+        Command::Conflicts => {
+            if let Err(code) = conflicts(&args) {
+                exit(code)
+            }
+        }
+        Command::MaxLength => {
+            if let Err(code) = max_length(&args) {
+                exit(code)
+            }
+        }
+        Command::Suggest => {
+            if let Err(code) = suggest(&args) {
+                exit(code)
+            }
+        }
+        Command::Blame => {
+            let query = args.blame.expect("checked by parse_args");
+            let result = blame(&args.registry, args.revision.as_deref().unwrap_or("HEAD"), query, args.family, &args.filters);
+            let output = match result {
+                Ok(blames) => render_blame(&blames, args.report_format),
+                Err(err) => {
+                    eprintln!("Error: {}", err);
+                    exit(exit_code_for_error(err.as_ref()))
+                }
+            };
+            if let Err(err) = write_output(args.output.as_deref(), &output) {
+                eprintln!("Error: {}", err);
+                exit(EXIT_IO_ERROR)
+            }
+        }
+        Command::Watch => {
+            let mut report = |event: WatchEvent| match event {
+                WatchEvent::Regenerated { summary, result, outputs, elapsed } => {
+                    report_run(&args, summary);
+                    match result {
+                        Ok(()) => eprintln!("Regenerated {} VRPs in {} ms, {} of {} outputs changed", summary.vrp_count,
+                                            elapsed.as_millis(), summary.changed.len(), outputs),
+                        Err(err) => eprintln!("Error: {}", err),
+                    }
+                }
+                WatchEvent::Reloaded(path) => eprintln!("Reloaded config file {}", path.display()),
+                WatchEvent::ReloadFailed(err) => eprintln!("Error: {}, keeping the previous configuration", err),
+                WatchEvent::Polling(err) => eprintln!("Warning: inotify unavailable, falling back to polling: {}", err),
+            };
+            if let Err(err) = watch(args.config.as_deref().expect("checked by parse_args"), &run_options(&args), &args.watch, &mut report) {
+                eprintln!("Error: {}", err);
+                exit(if is_io_error(err.as_ref()) { EXIT_IO_ERROR } else { EXIT_USAGE })
+            }
+        }
+        Command::Baseline => {
+            let (_, warnings) = check_registry(&args).unwrap_or_else(|code| exit(code));
+            if let Err(err) = write_output(args.output.as_deref(), &Baseline::from_warnings(&warnings).render()) {
+                eprintln!("Error: {}", err);
+                exit(EXIT_IO_ERROR)
+            }
+        }
+        Command::Check => {
+            let (object_count, mut warnings) = check_registry(&args).unwrap_or_else(|code| exit(code));
+            let total = warnings.len();
+            let stale = apply_baseline(&args, &mut warnings).unwrap_or_else(|code| exit(code));
+            if !args.quiet {
+                for warning in &warnings {
+                    eprintln!("Warning: {}", warning);
+                }
+                print_stale_baseline(&stale);
+            }
+            if args.verbose || !args.quiet {
+                eprintln!("{} route objects accepted, {} warnings, {} accepted by the baseline ({} ms)",
+                          object_count, warnings.len(), total - warnings.len(), start.elapsed().as_millis());
+            }
+            if args.strict && !warnings.is_empty() {
+                eprintln!("Warnings occurred and strict mode is enabled");
+                exit(EXIT_STRICT_FAILURE)
+            }
+        }
+    }
+}
+
+type BoxResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+/// Validates the registry for the check and baseline commands, returning the number of accepted
+/// route objects and all warnings.
+fn check_registry(args: &Args) -> Result<(usize, Vec<Warning>), i32> {
+    let result = open_source(args).and_then(|source| {
+        let (objects, mut warnings) = get_roa_objects_for_family(source.as_ref(), args.family, &args.filters)?;
+        let (database, database_warnings) = RegistryDatabase::read(source.as_ref())?;
+        warnings.extend(database_warnings.into_iter().filter(|w| !is_route_file(&w.object)));
+        warnings.extend(database.check_keys());
+        Ok((objects.len(), warnings))
+    });
+    result.map_err(|err| {
+        eprintln!("Error: {}", err);
+        exit_code_for_error(err.as_ref())
+    })
+}
+
+/// Removes the warnings accepted by the --baseline file and returns its entries that no longer occur.
+fn apply_baseline(args: &Args, warnings: &mut Vec<Warning>) -> Result<Vec<String>, i32> {
+    let Some(path) = &args.baseline else {
+        return Ok(Vec::new());
+    };
+    let baseline = Baseline::read(path).map_err(|err| {
+        eprintln!("Error: {}", err);
+        if is_io_error(err.as_ref()) { EXIT_IO_ERROR } else { EXIT_USAGE }
+    })?;
+    Ok(baseline.filter(warnings))
+}
+
+fn print_stale_baseline(entries: &[String]) {
+    for entry in entries {
+        eprintln!("Baseline entry no longer occurs: {}", entry);
+    }
+}
+
+/// The registry given on the command line, with the overlay applied if one was given.
+fn open_source(args: &Args) -> BoxResult<Box<dyn RegistrySource>> {
+    let source = source::open(&args.registry, args.revision.as_deref())?;
+    Ok(match &args.overlay {
+        Some(overlay) => Box::new(OverlaySource::new(source, overlay)),
+        None => source,
+    })
+}
+
+fn generate_output(args: &Args, start: Instant) -> Result<(), i32> {
+    let _lock = match &args.lock_file {
+        Some(path) => Some(LockFile::acquire(path).map_err(|err| {
+            eprintln!("Error: {}", err);
+            EXIT_LOCKED
+        })?),
+        None => None,
+    };
+    let source = open_source(args).map_err(|err| {
+        eprintln!("Error: {}", err);
+        exit_code_for_error(err.as_ref())
+    })?;
+    let mut result = get_vrps(source.as_ref(), args.family, &args.filters, &args.slurm_files);
+    if let Ok((_, warnings)) = &mut result {
+        let stale = apply_baseline(args, warnings)?;
+        if !args.quiet {
+            print_stale_baseline(&stale);
         }
     }
+    if let Ok((vrps, _)) = &result {
+        let (failures, warnings) = args.safety.check(vrps, args.family, args.output.as_deref());
+        if !args.quiet {
+            for warning in warnings {
+                eprintln!("Warning: {}", warning);
+            }
+        }
+        if !failures.is_empty() {
+            eprintln!("Error: Refusing to publish: {}", failures.join("; "));
+            return Err(EXIT_SAFETY_CHECK);
+        }
+    }
+    let vrps = result.as_ref().ok().filter(|_| args.snapshot_store.is_some()).map(|(vrps, _)| vrps.clone());
+    let result = result.map(|(vrps, warnings)| (render(&vrps, args.format, args.family, source.as_ref()), warnings));
+    if args.verbose {
+        if let Ok((_, warnings)) = &result {
+            eprintln!("Generated {} output in {} ms with {} warnings", args.format, start.elapsed().as_millis(), warnings.len());
+        }
+    }
+    let changed = try_check_and_write(result, args.strict, args.output.as_deref(), args.quiet)?;
+    if let (Some(store), Some(vrps)) = (&args.snapshot_store, vrps) {
+        append_snapshot(store, &vrps, args.family, source.as_ref()).map_err(|err| {
+            eprintln!("Error: {}", err);
+            exit_code_for_error(err.as_ref())
+        })?;
+    }
+    if let (true, Some(hook)) = (changed, &args.on_change) {
+        run_hook(hook).map_err(|err| {
+            eprintln!("Error: {}", err);
+            EXIT_HOOK_FAILURE
+        })?;
+    }
+    Ok(())
 }
 
+fn run_options(args: &Args) -> RunOptions {
+    RunOptions {
+        strict: args.strict,
+        slurm_files: args.slurm_files.clone(),
+        lock_file: args.lock_file.clone(),
+        snapshot_store: args.snapshot_store.clone(),
+        revision: args.revision.clone(),
+        safety: args.safety.clone(),
+        baseline: args.baseline.clone(),
+    }
+}
 
+fn run(args: &Args) {
+    let start = Instant::now();
+    let config = match read_config(args.config.as_deref().expect("checked by parse_args")) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            exit(if is_io_error(err.as_ref()) { EXIT_IO_ERROR } else { EXIT_USAGE })
+        }
+    };
+    let mut summary = RunSummary::default();
+    let result = run_config(&config, &run_options(args), &mut summary);
+    report_run(args, &summary);
+    match result {
+        Ok(()) => {
+            if args.verbose {
+                eprintln!("Rendered {} outputs ({} changed) from {} VRPs in {} ms with {} warnings", config.outputs.len(),
+                          summary.changed.len(), summary.vrp_count, start.elapsed().as_millis(), summary.warnings.len());
+            }
+        }
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            exit(err.exit_code())
+        }
+    }
+}
+
+/// Prints the warnings, stale baseline entries and source conflicts of a run.
+fn report_run(args: &Args, summary: &RunSummary) {
+    if args.quiet {
+        return;
+    }
+    for warning in &summary.warnings {
+        eprintln!("Warning: {}", warning);
+    }
+    print_stale_baseline(&summary.stale_baseline);
+    for conflict in &summary.conflicts {
+        eprintln!("Conflict: {}", conflict);
+    }
+}
+
+fn diff(args: &Args) -> Result<(), i32> {
+    // Either two revisions of one registry, two registries at the same revision or a registry and its overlay
+    let sides: Vec<(String, BoxResult<Box<dyn RegistrySource>>)> = match args.compare.as_slice() {
+        [] => {
+            let overlay = args.overlay.as_ref().expect("checked by parse_args");
+            let base = args.registry.display().to_string();
+            vec![
+                (base.clone(), source::open(&args.registry, args.revision.as_deref())),
+                (format!("{} with overlay {}", base, overlay.display()), open_source(args)),
+            ]
+        }
+        [other] => [args.registry.display().to_string(), other.clone()].into_iter()
+            .map(|path| {
+                let source = source::open(&path, args.revision.as_deref());
+                (path, source)
+            }).collect(),
+        revisions => revisions.iter()
+            .map(|revision| (revision.clone(), GitSource::open(&args.registry, revision).map(|s| Box::new(s) as Box<dyn RegistrySource>)))
+            .collect(),
+    };
+
+    let mut sets: Vec<(String, VrpSet)> = Vec::new();
+    for (name, source) in sides {
+        sets.push(read_registry_set(args, name, source)?);
+    }
+
+    let (to, new) = sets.pop().expect("two sides");
+    let (from, old) = sets.pop().expect("two sides");
+    let report = RegistryDiff::compare(from, &old, to, &new);
+    write_output(args.output.as_deref(), &report.render(args.report_format)).map_err(|err| {
+        eprintln!("Error: {}", err);
+        EXIT_IO_ERROR
+    })
+}
 
+/// Reads the VRPs of one side of a diff, labeled with its name and commit.
+fn read_registry_set(args: &Args, name: String, source: BoxResult<Box<dyn RegistrySource>>) -> Result<(String, VrpSet), i32> {
+    let result = source.and_then(|source| {
+        let label = match source.commit_hash() {
+            Some(hash) if hash != name => format!("{} ({})", name, &hash[..hash.len().min(12)]),
+            _ => name,
+        };
+        VrpSet::read(source.as_ref(), args.family, &args.filters, &args.slurm_files).map(|(set, warnings)| (label, set, warnings))
+    });
+    let (label, set, warnings) = result.map_err(|err| {
+        eprintln!("Error: {}", err);
+        exit_code_for_error(err.as_ref())
+    })?;
+    if !args.quiet {
+        for warning in &warnings {
+            eprintln!("Warning: {}: {}", label, warning);
+        }
+    }
+    if args.strict && !warnings.is_empty() {
+        eprintln!("Warnings occurred and strict mode is enabled");
+        return Err(EXIT_STRICT_FAILURE);
+    }
+    Ok((label, set))
+}
+
+/// Compares the VRPs of two ROA files or registries regardless of order and formatting.
+/// Fails with `EXIT_DIFFERENCES` if they differ.
+fn compare(args: &Args) -> Result<(), i32> {
+    let mut sets: Vec<(String, VrpSet)> = Vec::new();
+    for name in [args.registry.display().to_string(), args.compare[0].clone()] {
+        let is_registry = Path::new(&name).is_dir() || [".tar", ".tar.gz", ".tgz"].iter().any(|e| name.ends_with(e));
+        if is_registry {
+            let source = source::open(&name, args.revision.as_deref());
+            sets.push(read_registry_set(args, name, source)?);
+            continue;
+        }
+        let vrps = read_roa_file(Path::new(&name)).map_err(|err| {
+            eprintln!("Error: {}", err);
+            exit_code_for_error(err.as_ref())
+        })?;
+        let vrps = vrps.into_iter().filter(|v| args.family.contains(&v.prefix)).collect();
+        sets.push((name, VrpSet::from_vrps(vrps)));
+    }
+
+    let (to, new) = sets.pop().expect("two sides");
+    let (from, old) = sets.pop().expect("two sides");
+    let report = RegistryDiff::compare(from, &old, to, &new);
+    write_output(args.output.as_deref(), &report.render(args.report_format)).map_err(|err| {
+        eprintln!("Error: {}", err);
+        EXIT_IO_ERROR
+    })?;
+    if !report.is_empty() {
+        return Err(EXIT_DIFFERENCES);
+    }
+    Ok(())
+}
+
+/// Reports overlapping route objects for review, e.g. anycast setups or squatting.
+fn conflicts(args: &Args) -> Result<(), i32> {
+    let result = open_source(args).and_then(|source| ConflictReport::read(source.as_ref(), args.family, &args.filters));
+    let (report, warnings) = result.map_err(|err| {
+        eprintln!("Error: {}", err);
+        exit_code_for_error(err.as_ref())
+    })?;
+    if !args.quiet {
+        for warning in &warnings {
+            eprintln!("Warning: {}", warning);
+        }
+    }
+    if args.strict && !warnings.is_empty() {
+        eprintln!("Warnings occurred and strict mode is enabled");
+        return Err(EXIT_STRICT_FAILURE);
+    }
+    write_output(args.output.as_deref(), &report.render(args.report_format)).map_err(|err| {
+        eprintln!("Error: {}", err);
+        EXIT_IO_ERROR
+    })
+}
+
+/// Lists VRPs with a max length beyond the observed announcements.
+fn max_length(args: &Args) -> Result<(), i32> {
+    let announcements = read_announcements(Path::new(&args.compare[0])).map_err(|err| {
+        eprintln!("Error: {}", err);
+        exit_code_for_error(err.as_ref())
+    })?;
+    let name = args.registry.display().to_string();
+    let (_, set) = read_registry_set(args, name, open_source(args))?;
+    let report = MaxLengthReport::new(&set, &announcements);
+    write_output(args.output.as_deref(), &report.render(args.report_format)).map_err(|err| {
+        eprintln!("Error: {}", err);
+        EXIT_IO_ERROR
+    })
+}
+
+/// Prints the suggested route objects, or writes them below the --output directory.
+fn suggest(args: &Args) -> Result<(), i32> {
+    let announcements = read_announcements(Path::new(&args.compare[0])).map_err(|err| {
+        eprintln!("Error: {}", err);
+        exit_code_for_error(err.as_ref())
+    })?;
+    let result = open_source(args).and_then(|source|
+        suggest_route_objects(source.as_ref(), &announcements, args.family, &args.filters, &args.slurm_files)
+    );
+    let (report, warnings) = result.map_err(|err| {
+        eprintln!("Error: {}", err);
+        exit_code_for_error(err.as_ref())
+    })?;
+    if !args.quiet {
+        for warning in &warnings {
+            eprintln!("Warning: {}", warning);
+        }
+        for (announcement, reason) in &report.skipped {
+            eprintln!("Skipped {}: {}", announcement, reason);
+        }
+    }
+    if args.strict && !warnings.is_empty() {
+        eprintln!("Warnings occurred and strict mode is enabled");
+        return Err(EXIT_STRICT_FAILURE);
+    }
+
+    let mut output = String::new();
+    for suggestion in &report.suggestions {
+        match &args.output {
+            Some(directory) => {
+                let path = directory.join(&suggestion.path);
+                let written = path.parent().map_or(Ok(()), std::fs::create_dir_all)
+                    .and_then(|_| write_output(Some(&path), &suggestion.object.to_string()));
+                written.map_err(|err| {
+                    eprintln!("Error: {}", err);
+                    EXIT_IO_ERROR
+                })?;
+            }
+            None => {
+                let valid: Vec<String> = suggestion.announcements.iter().map(ToString::to_string).collect();
+                let state = if suggestion.existing { "changed" } else { "new" };
+                output.push_str(&format!("# {} ({}), makes valid: {}\n", suggestion.path, state, valid.join(", ")));
+                output.push_str(&format!("{}\n", suggestion.object));
+            }
+        }
+    }
+    if args.output.is_none() {
+        write_output(None, &output).map_err(|err| {
+            eprintln!("Error: {}", err);
+            EXIT_IO_ERROR
+        })?;
+    }
+    if !args.quiet {
+        eprintln!("{} announcements valid, {} route objects suggested, {} announcements skipped",
+                  report.valid_count, report.suggestions.len(), report.skipped.len());
+    }
+    Ok(())
+}
+
+fn query(args: &Args) -> Result<(), i32> {
+    let at = args.at.unwrap_or_else(|| SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map_or(0, |d| d.as_secs() as i64));
+    let result = SnapshotStore::new(&args.registry).state_at(at);
+    let snapshot = match result {
+        Ok(Some(snapshot)) => snapshot,
+        Ok(None) => {
+            eprintln!("Error: No snapshot was taken at or before {}", format_time(at));
+            return Err(EXIT_REGISTRY_ERROR);
+        }
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            return Err(exit_code_for_error(err.as_ref()));
+        }
+    };
+
+    let output = match args.route {
+        None => render_with_commit(&snapshot.vrps, args.format, args.family, snapshot.commit),
+        Some((prefix, asn)) => {
+            let (state, covering) = validate(&snapshot.vrps, &prefix, asn);
+            match args.report_format {
+                ReportFormat::Text => {
+                    let mut result = format!("{} AS{} at {}: {}\n", prefix_string(&prefix), asn, format_time(at), state);
+                    result.push_str(&format!("Snapshot of {}", format_time(snapshot.time)));
+                    if let Some(commit) = &snapshot.commit {
+                        result.push_str(&format!(" (commit {})", commit));
+                    }
+                    result.push('\n');
+                    for vrp in covering {
+                        result.push_str(&format!("  {}\n", vrp));
+                    }
+                    result
+                }
+                ReportFormat::Json => {
+                    let mut data = json::JsonValue::new_object();
+                    data["prefix"] = prefix_string(&prefix).into();
+                    data["asn"] = asn.to_string().into();
+                    data["time"] = at.into();
+                    data["state"] = state.to_string().into();
+                    data["snapshotTime"] = snapshot.time.into();
+                    data["commit"] = snapshot.commit.clone().into();
+                    data["covering"] = covering.iter().map(|v| v.get_json_object()).collect::<Vec<json::JsonValue>>().into();
+                    data.dump()
+                }
+            }
+        }
+    };
+    write_output(args.output.as_deref(), &output).map_err(|err| {
+        eprintln!("Error: {}", err);
+        EXIT_IO_ERROR
+    })
+}
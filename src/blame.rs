@@ -0,0 +1,277 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::str::FromStr;
+use cidr_utils::cidr::IpCidr;
+use json::JsonValue;
+use crate::date::format_date;
+use crate::git::{Commit, ObjectId};
+use crate::history::RegistryHistory;
+use crate::import::{parse_asn, parse_prefix};
+use crate::parse::RouteObject;
+use crate::source::GitSource;
+use crate::vrp::{prefix_covers, prefix_string, Vrp};
+use crate::{BoxResult, Family, FilterOverrides, ReportFormat};
+
+/// Selects the VRPs to blame: all VRPs within a prefix or all VRPs of an origin.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlameQuery {
+    Prefix(IpCidr),
+    Asn(u32),
+}
+
+impl BlameQuery {
+    fn matches_object(&self, object: &RouteObject) -> bool {
+        match self {
+            BlameQuery::Prefix(prefix) => prefix_covers(prefix, &object.prefix),
+            BlameQuery::Asn(asn) => object.origins.contains(asn),
+        }
+    }
+
+    fn matches(&self, vrp: &Vrp) -> bool {
+        match self {
+            BlameQuery::Prefix(prefix) => prefix_covers(prefix, &vrp.prefix),
+            BlameQuery::Asn(asn) => vrp.asn == *asn,
+        }
+    }
+}
+
+impl FromStr for BlameQuery {
+    type Err = Box<dyn std::error::Error + Send + Sync>;
+    fn from_str(s: &str) -> BoxResult<Self> {
+        if s.contains(['.', ':']) {
+            parse_prefix(s).map(BlameQuery::Prefix)
+        } else {
+            parse_asn(s).map(BlameQuery::Asn)
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CommitInfo {
+    pub id: ObjectId,
+    pub author: String,
+    pub email: String,
+    /// Commit time in seconds since the UNIX epoch.
+    pub time: i64,
+    pub summary: String,
+}
+
+impl CommitInfo {
+    fn new(commit: &Commit) -> Self {
+        CommitInfo {
+            id: commit.id,
+            author: commit.author.name.clone(),
+            email: commit.author.email.clone(),
+            time: commit.committer.time,
+            summary: commit.summary().to_owned(),
+        }
+    }
+
+    fn get_json_object(&self) -> JsonValue {
+        let mut data = JsonValue::new_object();
+        data["commit"] = self.id.to_hex().into();
+        data["author"] = self.author.as_str().into();
+        data["email"] = self.email.as_str().into();
+        data["time"] = self.time.into();
+        data["summary"] = self.summary.as_str().into();
+        data
+    }
+}
+
+/// History of a VRP that exists at the blamed revision.
+#[derive(Debug, Clone)]
+pub struct VrpBlame {
+    pub vrp: Vrp,
+    /// The commit since which the VRP exists without interruption.
+    pub introduced: CommitInfo,
+    /// The commit that set the current max length, if it changed after the VRP was introduced.
+    pub max_length_changed: Option<CommitInfo>,
+}
+
+/// Follows the first-parent history of `revision` to find when each VRP matching `query` was
+/// introduced and when its max length last changed. Changes merged from other branches are
+/// attributed to the merge commit, which is when they reached the blamed branch.
+pub fn blame(path: &Path, revision: &str, query: BlameQuery, family: Family, filters: &FilterOverrides) -> BoxResult<Vec<VrpBlame>> {
+    let GitSource { repository, commit, .. } = GitSource::open(path, revision)?;
+    let mut state = RegistryHistory::new(repository.clone(), family, filters, Box::new(move |o| query.matches_object(o)));
+
+    let mut commit = repository.read_commit(&commit)?;
+    state.update(&commit)?;
+    let mut current = vrps(&state, query);
+    let mut pending: BTreeMap<(IpCidr, u32), (u8, Option<CommitInfo>)> = current.iter()
+        .map(|(key, max_length)| (*key, (*max_length, None)))
+        .collect();
+    let mut result: Vec<VrpBlame> = Vec::new();
+
+    while !pending.is_empty() {
+        let Some(parent_id) = commit.parents.first() else {
+            break;
+        };
+        let parent = repository.read_commit(parent_id)?;
+        if state.update(&parent)? {
+            let previous = vrps(&state, query);
+            pending.retain(|key, (max_length, max_length_changed)| {
+                match previous.get(key) {
+                    None => {
+                        result.push(VrpBlame {
+                            vrp: Vrp { prefix: key.0, max_length: *max_length, asn: key.1 },
+                            introduced: CommitInfo::new(&commit),
+                            max_length_changed: max_length_changed.take(),
+                        });
+                        return false;
+                    }
+                    Some(previous_length) if *previous_length != current[key] && max_length_changed.is_none() => {
+                        *max_length_changed = Some(CommitInfo::new(&commit));
+                    }
+                    Some(_) => {}
+                }
+                true
+            });
+            current = previous;
+        }
+        commit = parent;
+    }
+
+    // Whatever is left already existed in the root commit
+    for ((prefix, asn), (max_length, max_length_changed)) in pending {
+        result.push(VrpBlame {
+            vrp: Vrp { prefix, max_length, asn },
+            introduced: CommitInfo::new(&commit),
+            max_length_changed,
+        });
+    }
+    result.sort_by(|a, b| a.vrp.cmp(&b.vrp));
+    Ok(result)
+}
+
+/// Max lengths by prefix and origin of the VRPs matching the query.
+fn vrps(state: &RegistryHistory, query: BlameQuery) -> HashMap<(IpCidr, u32), u8> {
+    let mut result = HashMap::new();
+    for vrp in state.vrps().filter(|v| query.matches(v)) {
+        result.insert((vrp.prefix, vrp.asn), vrp.max_length);
+    }
+    result
+}
+
+pub fn render_blame(blames: &[VrpBlame], format: ReportFormat) -> String {
+    match format {
+        ReportFormat::Text => {
+            let mut result = String::new();
+            let line = |label: &str, commit: &CommitInfo| {
+                format!("    {:<11} {} {} {} <{}> {}\n", label, &commit.id.to_hex()[..12], format_date(commit.time),
+                        commit.author, commit.email, commit.summary)
+            };
+            for blame in blames {
+                result.push_str(&format!("{}\n", blame.vrp));
+                result.push_str(&line("added", &blame.introduced));
+                if let Some(commit) = &blame.max_length_changed {
+                    result.push_str(&line("max length", commit));
+                }
+            }
+            result
+        }
+        ReportFormat::Json => {
+            let mut data = JsonValue::new_array();
+            for blame in blames {
+                let mut entry = JsonValue::new_object();
+                entry["prefix"] = prefix_string(&blame.vrp.prefix).into();
+                entry["maxLength"] = blame.vrp.max_length.into();
+                entry["asn"] = blame.vrp.asn.to_string().into();
+                entry["introduced"] = blame.introduced.get_json_object();
+                entry["maxLengthChanged"] = blame.max_length_changed.as_ref().map_or(JsonValue::Null, CommitInfo::get_json_object);
+                data.push(entry).expect("Error converting data to JSON");
+            }
+            data.dump()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::fixtures::{TestRepository, BASE, REF_DELTA};
+
+    const FILTER: &str = "1 permit 172.20.0.0/24 21 29\n99 deny 0.0.0.0/0 0 32\n";
+
+    /// A history of four commits, using the route objects of the pack fixture for 172.20.0.0/24:
+    /// the route object is added, its max length set, the filter set extended to a second route
+    /// object and finally an unrelated file added.
+    fn history(name: &str) -> (TestRepository, Vec<String>) {
+        let mut repository = TestRepository::new(name);
+        let filter = repository.blob(FILTER);
+        let extended_filter = repository.blob(&FILTER.replace("172.20.0.0/24", "172.20.0.0/14"));
+        let other = repository.blob("route: 172.20.1.0/24\norigin: AS4242420002\nmnt-by: BAR-MNT\nsource: DN42\n");
+        let maintainer = repository.blob("mntner: FOO-MNT\nsource: DN42\n");
+        let trees = [
+            repository.tree(&[("data/filter.txt", &filter), ("data/route/172.20.0.0_24", BASE), ("data/route/172.20.1.0_24", &other)]),
+            repository.tree(&[("data/filter.txt", &filter), ("data/route/172.20.0.0_24", REF_DELTA), ("data/route/172.20.1.0_24", &other)]),
+            repository.tree(&[("data/filter.txt", &extended_filter), ("data/route/172.20.0.0_24", REF_DELTA), ("data/route/172.20.1.0_24", &other)]),
+            repository.tree(&[("data/filter.txt", &extended_filter), ("data/route/172.20.0.0_24", REF_DELTA), ("data/route/172.20.1.0_24", &other),
+                              ("data/mntner/FOO-MNT", &maintainer)]),
+        ];
+        let mut commits: Vec<String> = Vec::new();
+        for (i, (tree, summary)) in trees.iter().zip(["Add routes", "Set max length", "Extend filter", "Add maintainer"]).enumerate() {
+            let commit = repository.commit(tree, commits.last().map(String::as_str), 86400 * (i as i64 + 1), summary);
+            commits.push(commit);
+        }
+        (repository, commits)
+    }
+
+    fn summaries(blames: &[VrpBlame]) -> Vec<(String, String, Option<String>)> {
+        blames.iter()
+            .map(|b| (b.vrp.to_string(), b.introduced.summary.clone(), b.max_length_changed.as_ref().map(|c| c.summary.clone())))
+            .collect()
+    }
+
+    #[test]
+    fn first_appearance_and_max_length() {
+        let (repository, commits) = history("blame-first-appearance-and-max-length");
+        let query = BlameQuery::from_str("172.20.0.0/16").unwrap();
+        let blames = blame(&repository.path, &commits[3], query, Family::V4, &FilterOverrides::default()).unwrap();
+        assert_eq!(summaries(&blames), [
+            ("172.20.0.0/24 max 28 as AS4242420000".to_owned(), "Add routes".to_owned(), Some("Set max length".to_owned())),
+            // The route object existed before, the VRP only appeared with the filter change
+            ("172.20.1.0/24 max 29 as AS4242420002".to_owned(), "Extend filter".to_owned(), None),
+        ]);
+        assert_eq!(blames[0].introduced.id.to_hex(), commits[0]);
+        assert_eq!(blames[0].introduced.author, "A U Thor");
+        assert_eq!(blames[0].introduced.time, 86400);
+
+        // At an earlier revision the VRPs of later commits do not exist yet
+        let blames = blame(&repository.path, &commits[1], query, Family::V4, &FilterOverrides::default()).unwrap();
+        assert_eq!(summaries(&blames), [
+            ("172.20.0.0/24 max 28 as AS4242420000".to_owned(), "Add routes".to_owned(), Some("Set max length".to_owned())),
+        ]);
+        let blames = blame(&repository.path, &commits[0], query, Family::V4, &FilterOverrides::default()).unwrap();
+        assert_eq!(summaries(&blames), [("172.20.0.0/24 max 29 as AS4242420000".to_owned(), "Add routes".to_owned(), None)]);
+    }
+
+    #[test]
+    fn queries() {
+        let (repository, commits) = history("blame-queries");
+        let blame = |query: &str| {
+            let query = BlameQuery::from_str(query).unwrap();
+            summaries(&blame(&repository.path, &commits[3], query, Family::V4, &FilterOverrides::default()).unwrap())
+        };
+        assert_eq!(blame("AS4242420002"), [("172.20.1.0/24 max 29 as AS4242420002".to_owned(), "Extend filter".to_owned(), None)]);
+        assert_eq!(blame("172.20.0.0/24").len(), 1);
+        assert!(blame("172.21.0.0/16").is_empty());
+        assert!(BlameQuery::from_str("ASx").is_err());
+    }
+
+    #[test]
+    fn render() {
+        let (repository, commits) = history("blame-render");
+        let query = BlameQuery::from_str("AS4242420000").unwrap();
+        let blames = blame(&repository.path, &commits[3], query, Family::V4, &FilterOverrides::default()).unwrap();
+        assert_eq!(render_blame(&blames, ReportFormat::Text), format!("172.20.0.0/24 max 28 as AS4242420000\n\
+            \x20   added       {} 1970-01-02 A U Thor <author@example.com> Add routes\n\
+            \x20   max length  {} 1970-01-03 A U Thor <author@example.com> Set max length\n", &commits[0][..12], &commits[1][..12]));
+        let data = json::parse(&render_blame(&blames, ReportFormat::Json)).unwrap();
+        assert_eq!(data[0]["prefix"], "172.20.0.0/24");
+        assert_eq!(data[0]["maxLength"], 28);
+        assert_eq!(data[0]["asn"], "4242420000");
+        assert_eq!(data[0]["introduced"]["commit"], commits[0].as_str());
+        assert_eq!(data[0]["maxLengthChanged"]["summary"], "Set max length");
+    }
+}
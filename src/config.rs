@@ -0,0 +1,383 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use json::JsonValue;
+use crate::safety::{SafetyChecks, VrpSpec};
+use crate::{io_error, BoxResult, Family, FilterOverrides, OutputFormat, SourceFilterFiles, SourceSelection};
+
+/// Settings for a `run --config` invocation, read from a JSON file.
+/// Relative paths are resolved against the directory containing the config file.
+#[derive(Debug)]
+pub struct Config {
+    pub registry: PathBuf,
+    /// Read the registry from this git revision instead of the working tree.
+    pub revision: Option<String>,
+    pub strict: bool,
+    /// Warnings listed in this file are ignored, also in strict mode.
+    pub baseline: Option<PathBuf>,
+    pub filters: FilterOverrides,
+    pub slurm_files: Vec<PathBuf>,
+    pub lock_file: Option<PathBuf>,
+    /// Directory of the snapshot store every run appends its VRPs to.
+    pub snapshot_store: Option<PathBuf>,
+    pub safety: SafetyChecks,
+    /// All sources of VRPs in order of precedence, including the registry above.
+    pub sources: Vec<SourceConfig>,
+    /// File the conflicts between sources are reported in.
+    pub conflict_report: Option<PathBuf>,
+    pub outputs: Vec<OutputConfig>,
+}
+
+/// The name of the registry given by `registry` if it is not listed in `sources`.
+pub const DEFAULT_SOURCE_NAME: &str = "dn42";
+
+#[derive(Debug)]
+pub struct SourceConfig {
+    /// Name of the source in the `ta` field of JSON outputs and in messages.
+    pub name: String,
+    pub kind: SourceKind,
+}
+
+#[derive(Debug)]
+pub enum SourceKind {
+    /// The registry given by `registry`, `revision` and the filter files of the config.
+    Main,
+    /// Another registry with the same layout.
+    Registry {
+        path: PathBuf,
+        revision: Option<String>,
+        filters: FilterOverrides,
+    },
+    /// A ROA file in bird2 or JSON format.
+    Vrps(PathBuf),
+}
+
+#[derive(Debug)]
+pub struct OutputConfig {
+    pub format: OutputFormat,
+    pub family: Family,
+    /// Destination file, `None` for stdout.
+    pub path: Option<PathBuf>,
+    /// Command run after the output file changed.
+    pub on_change: Option<String>,
+    /// Sources of the route objects whose VRPs are included in the output.
+    pub sources: SourceSelection,
+}
+
+pub fn read_config(file: &Path) -> BoxResult<Config> {
+    let content = fs::read_to_string(file).map_err(|e|
+        io_error(e, format!("Unable to read config file {}", file.display()))
+    )?;
+    let base_dir = file.parent().unwrap_or(Path::new("."));
+    parse_config(&content, base_dir).map_err(|e| format!("Invalid config file {}: {}", file.display(), e).into())
+}
+
+fn parse_config(content: &str, base_dir: &Path) -> BoxResult<Config> {
+    let root = json::parse(content)?;
+    check_keys(&root, &["registry", "revision", "strict", "baseline", "filter_file", "filter6_file", "source_filter_files", "slurm", "lock_file", "snapshot_store",
+        "safety", "sources", "conflict_report", "outputs"], "config")?;
+
+    let registry = get_path(&root, "registry", base_dir)?.ok_or("missing 'registry'")?;
+    let revision = get_str(&root, "revision")?.map(str::to_owned);
+    let strict = get_bool(&root, "strict")?.unwrap_or(false);
+    let baseline = get_path(&root, "baseline", base_dir)?;
+    let filters = FilterOverrides {
+        filter_v4: get_path(&root, "filter_file", base_dir)?,
+        filter_v6: get_path(&root, "filter6_file", base_dir)?,
+        source_filters: parse_source_filter_files(&root, base_dir)?,
+        sources: SourceSelection::default(),
+    };
+
+    let mut slurm_files: Vec<PathBuf> = Vec::new();
+    for entry in get_array(&root, "slurm")? {
+        let path = entry.as_str().ok_or("'slurm' entries must be strings")?;
+        slurm_files.push(base_dir.join(path));
+    }
+
+    let lock_file = get_path(&root, "lock_file", base_dir)?;
+    let snapshot_store = get_path(&root, "snapshot_store", base_dir)?;
+    let safety = match &root["safety"] {
+        JsonValue::Null => SafetyChecks::default(),
+        value => parse_safety(value).map_err(|e| format!("safety: {}", e))?,
+    };
+
+    let mut sources: Vec<SourceConfig> = Vec::new();
+    for (index, entry) in get_array(&root, "sources")?.enumerate() {
+        let source = parse_source(entry, base_dir).map_err(|e| format!("source #{}: {}", index + 1, e))?;
+        if sources.iter().any(|s| s.name == source.name) {
+            return Err(format!("source #{}: duplicate source name '{}'", index + 1, source.name).into());
+        }
+        if matches!(source.kind, SourceKind::Main) && sources.iter().any(|s| matches!(s.kind, SourceKind::Main)) {
+            return Err(format!("source #{}: the registry is already listed", index + 1).into());
+        }
+        sources.push(source);
+    }
+    if !sources.iter().any(|s| matches!(s.kind, SourceKind::Main)) {
+        if sources.iter().any(|s| s.name == DEFAULT_SOURCE_NAME) {
+            return Err(format!("source name '{}' is used by the registry, list it in 'sources' to rename it", DEFAULT_SOURCE_NAME).into());
+        }
+        sources.insert(0, SourceConfig { name: DEFAULT_SOURCE_NAME.to_owned(), kind: SourceKind::Main });
+    }
+    let conflict_report = get_path(&root, "conflict_report", base_dir)?;
+
+    let mut outputs: Vec<OutputConfig> = Vec::new();
+    for (index, entry) in get_array(&root, "outputs")?.enumerate() {
+        outputs.push(parse_output(entry, base_dir).map_err(|e| format!("output #{}: {}", index + 1, e))?);
+    }
+    if outputs.is_empty() {
+        return Err("no outputs configured".into());
+    }
+
+    Ok(Config {
+        registry,
+        revision,
+        strict,
+        baseline,
+        filters,
+        slurm_files,
+        lock_file,
+        snapshot_store,
+        safety,
+        sources,
+        conflict_report,
+        outputs,
+    })
+}
+
+/// Parses an entry of `sources`. An entry with neither `registry` nor `vrps` names the main registry.
+fn parse_source(entry: &JsonValue, base_dir: &Path) -> BoxResult<SourceConfig> {
+    check_keys(entry, &["name", "registry", "revision", "filter_file", "filter6_file", "source_filter_files", "vrps"], "source")?;
+    let name = get_str(entry, "name")?.filter(|n| !n.is_empty()).ok_or("missing 'name'")?.to_owned();
+    let registry = get_path(entry, "registry", base_dir)?;
+    let vrps = get_path(entry, "vrps", base_dir)?;
+    let revision = get_str(entry, "revision")?.map(str::to_owned);
+    let filters = FilterOverrides {
+        filter_v4: get_path(entry, "filter_file", base_dir)?,
+        filter_v6: get_path(entry, "filter6_file", base_dir)?,
+        source_filters: parse_source_filter_files(entry, base_dir)?,
+        sources: SourceSelection::default(),
+    };
+    let registry_options = revision.is_some() || filters.filter_v4.is_some() || filters.filter_v6.is_some() || !filters.source_filters.is_empty();
+    let kind = match (registry, vrps) {
+        (Some(_), Some(_)) => return Err("'registry' and 'vrps' are mutually exclusive".into()),
+        (Some(path), None) => SourceKind::Registry { path, revision, filters },
+        (None, Some(_)) | (None, None) if registry_options => {
+            return Err("'revision', 'filter_file', 'filter6_file' and 'source_filter_files' require a 'registry'".into());
+        }
+        (None, Some(path)) => SourceKind::Vrps(path),
+        (None, None) => SourceKind::Main,
+    };
+    Ok(SourceConfig { name, kind })
+}
+
+fn parse_output(entry: &JsonValue, base_dir: &Path) -> BoxResult<OutputConfig> {
+    check_keys(entry, &["format", "family", "path", "on_change", "include_sources", "exclude_sources"], "output")?;
+    let format: OutputFormat = get_str(entry, "format")?.ok_or("missing 'format'")?.parse()?;
+    let family: Family = match get_str(entry, "family")? {
+        Some(family) => family.parse()?,
+        None => Family::Both,
+    };
+    let path = match get_str(entry, "path")? {
+        None | Some("-") => None,
+        Some(path) => Some(base_dir.join(path)),
+    };
+    let on_change = get_str(entry, "on_change")?.map(str::to_owned);
+    if on_change.is_some() && path.is_none() {
+        return Err("'on_change' requires an output 'path'".into());
+    }
+    let sources = parse_source_selection(entry)?;
+    Ok(OutputConfig {
+        format,
+        family,
+        path,
+        on_change,
+        sources,
+    })
+}
+
+/// Parses `source_filter_files`, an object of filter set files by route object source.
+fn parse_source_filter_files(entry: &JsonValue, base_dir: &Path) -> BoxResult<Vec<SourceFilterFiles>> {
+    let files = &entry["source_filter_files"];
+    if files.is_null() {
+        return Ok(Vec::new());
+    }
+    if !files.is_object() {
+        return Err("'source_filter_files' must be a JSON object".into());
+    }
+    let mut result: Vec<SourceFilterFiles> = Vec::new();
+    for (source, value) in files.entries() {
+        check_keys(value, &["filter_file", "filter6_file"], "source_filter_files")
+            .map_err(|e| format!("source_filter_files '{}': {}", source, e))?;
+        result.push(SourceFilterFiles {
+            source: source.to_owned(),
+            filter_v4: get_path(value, "filter_file", base_dir)?,
+            filter_v6: get_path(value, "filter6_file", base_dir)?,
+        });
+    }
+    Ok(result)
+}
+
+fn parse_source_selection(entry: &JsonValue) -> BoxResult<SourceSelection> {
+    let mut selection = SourceSelection::default();
+    for (key, list) in [("include_sources", &mut selection.include), ("exclude_sources", &mut selection.exclude)] {
+        for source in get_array(entry, key)? {
+            list.push(source.as_str().ok_or(format!("'{}' entries must be strings", key))?.to_owned());
+        }
+    }
+    Ok(selection)
+}
+
+fn parse_safety(entry: &JsonValue) -> BoxResult<SafetyChecks> {
+    check_keys(entry, &["max_drop_percent", "must_exist"], "safety")?;
+    let max_drop_percent = match &entry["max_drop_percent"] {
+        JsonValue::Null => None,
+        value => Some(value.as_f64().filter(|v| *v >= 0.0).ok_or("'max_drop_percent' must be a non-negative number")?),
+    };
+    let mut must_exist: Vec<VrpSpec> = Vec::new();
+    for spec in get_array(entry, "must_exist")? {
+        let spec = spec.as_str().ok_or("'must_exist' entries must be strings")?;
+        must_exist.push(spec.parse().map_err(|e| format!("invalid must_exist entry '{}': {}", spec, e))?);
+    }
+    Ok(SafetyChecks {
+        max_drop_percent,
+        must_exist,
+    })
+}
+
+fn check_keys(object: &JsonValue, allowed: &[&str], context: &str) -> BoxResult<()> {
+    if !object.is_object() {
+        return Err(format!("{} must be a JSON object", context).into());
+    }
+    for (key, _) in object.entries() {
+        if !allowed.contains(&key) {
+            return Err(format!("unknown {} key '{}'", context, key).into());
+        }
+    }
+    Ok(())
+}
+
+fn get_str<'a>(object: &'a JsonValue, key: &str) -> BoxResult<Option<&'a str>> {
+    match &object[key] {
+        JsonValue::Null => Ok(None),
+        value => Ok(Some(value.as_str().ok_or(format!("'{}' must be a string", key))?)),
+    }
+}
+
+fn get_bool(object: &JsonValue, key: &str) -> BoxResult<Option<bool>> {
+    match &object[key] {
+        JsonValue::Null => Ok(None),
+        value => Ok(Some(value.as_bool().ok_or(format!("'{}' must be a boolean", key))?)),
+    }
+}
+
+fn get_path(object: &JsonValue, key: &str, base_dir: &Path) -> BoxResult<Option<PathBuf>> {
+    Ok(get_str(object, key)?.map(|p| base_dir.join(p)))
+}
+
+fn get_array<'a>(object: &'a JsonValue, key: &str) -> BoxResult<json::iterators::Members<'a>> {
+    match &object[key] {
+        JsonValue::Null | JsonValue::Array(_) => Ok(object[key].members()),
+        _ => Err(format!("'{}' must be an array", key).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(content: &str) -> BoxResult<Config> {
+        parse_config(content, Path::new("/etc/roa_wizard"))
+    }
+
+    fn error(content: &str) -> String {
+        parse(content).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn full_config() {
+        let config = parse(r#"{
+            "registry": "registry", "revision": "origin/master", "strict": true, "baseline": "baseline.txt",
+            "filter_file": "/srv/filter.txt", "source_filter_files": {"NEONETWORK": {"filter_file": "neo.txt"}},
+            "slurm": ["local.json"], "lock_file": "/run/roa.lock", "snapshot_store": "snapshots",
+            "safety": {"max_drop_percent": 10, "must_exist": ["172.20.0.0/24 max 29 AS4242420000"]},
+            "sources": [{"name": "neo", "registry": "neo", "revision": "main"}, {"name": "main"}, {"name": "extra", "vrps": "extra.json"}],
+            "conflict_report": "conflicts.txt",
+            "outputs": [
+                {"format": "bird", "family": "v4", "path": "roa4.conf", "on_change": "birdc configure", "exclude_sources": ["NEONETWORK"]},
+                {"format": "json", "path": "-"}
+            ]
+        }"#).unwrap();
+        assert_eq!(config.registry, Path::new("/etc/roa_wizard/registry"));
+        assert_eq!(config.revision.as_deref(), Some("origin/master"));
+        assert!(config.strict);
+        assert_eq!(config.baseline.as_deref(), Some(Path::new("/etc/roa_wizard/baseline.txt")));
+        // Absolute paths are kept
+        assert_eq!(config.filters.filter_v4.as_deref(), Some(Path::new("/srv/filter.txt")));
+        assert_eq!(config.filters.filter_v6, None);
+        assert_eq!(config.filters.source_filters[0].source, "NEONETWORK");
+        assert_eq!(config.slurm_files, [Path::new("/etc/roa_wizard/local.json")]);
+        assert_eq!(config.safety.max_drop_percent, Some(10.0));
+        assert_eq!(config.safety.must_exist.len(), 1);
+        let sources: Vec<&str> = config.sources.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(sources, ["neo", "main", "extra"]);
+        assert!(matches!(&config.sources[0].kind, SourceKind::Registry { revision: Some(r), .. } if r == "main"));
+        assert!(matches!(config.sources[1].kind, SourceKind::Main));
+        assert!(matches!(&config.sources[2].kind, SourceKind::Vrps(p) if p == Path::new("/etc/roa_wizard/extra.json")));
+
+        assert_eq!(config.outputs.len(), 2);
+        assert_eq!(config.outputs[0].family, Family::V4);
+        assert_eq!(config.outputs[0].path.as_deref(), Some(Path::new("/etc/roa_wizard/roa4.conf")));
+        assert_eq!(config.outputs[0].sources.exclude, ["NEONETWORK"]);
+        assert_eq!(config.outputs[1].family, Family::Both);
+        assert_eq!(config.outputs[1].path, None);
+    }
+
+    #[test]
+    fn minimal_config() {
+        let config = parse(r#"{"registry": "/srv/registry", "outputs": [{"format": "bird2"}]}"#).unwrap();
+        assert!(!config.strict);
+        assert!(config.safety.is_empty());
+        // The registry is the only source
+        assert_eq!(config.sources.len(), 1);
+        assert_eq!(config.sources[0].name, DEFAULT_SOURCE_NAME);
+    }
+
+    #[test]
+    fn invalid_configs() {
+        for (content, message) in [
+            ("[]", "config must be a JSON object"),
+            (r#"{"outputs": [{"format": "bird"}]}"#, "missing 'registry'"),
+            (r#"{"registry": "r", "output": [{"format": "bird"}]}"#, "unknown config key 'output'"),
+            (r#"{"registry": "r"}"#, "no outputs configured"),
+            (r#"{"registry": "r", "outputs": {"format": "bird"}}"#, "'outputs' must be an array"),
+            (r#"{"registry": 1, "outputs": [{"format": "bird"}]}"#, "'registry' must be a string"),
+            (r#"{"registry": "r", "strict": "yes", "outputs": [{"format": "bird"}]}"#, "'strict' must be a boolean"),
+            (r#"{"registry": "r", "outputs": [{}]}"#, "output #1: missing 'format'"),
+            (r#"{"registry": "r", "outputs": [{"format": "bird"}, {"format": "csv"}]}"#, "output #2: "),
+            (r#"{"registry": "r", "outputs": [{"format": "bird", "family": "v5"}]}"#, "output #1: "),
+            (r#"{"registry": "r", "outputs": [{"format": "bird", "file": "roa.conf"}]}"#, "output #1: unknown output key 'file'"),
+            (r#"{"registry": "r", "outputs": [{"format": "bird", "on_change": "true"}]}"#, "output #1: 'on_change' requires an output 'path'"),
+            (r#"{"registry": "r", "outputs": ["bird"]}"#, "output #1: output must be a JSON object"),
+            (r#"{"registry": "r", "outputs": [{"format": "bird", "include_sources": [1]}]}"#, "output #1: 'include_sources' entries must be strings"),
+            (r#"{"registry": "r", "safety": {"max_drop_percent": -1}, "outputs": [{"format": "bird"}]}"#,
+             "safety: 'max_drop_percent' must be a non-negative number"),
+            (r#"{"registry": "r", "safety": {"must_exist": ["172.20.0.0/24"]}, "outputs": [{"format": "bird"}]}"#,
+             "safety: invalid must_exist entry '172.20.0.0/24': missing AS number in '172.20.0.0/24'"),
+            (r#"{"registry": "r", "sources": [{"name": "a"}, {"name": "b"}], "outputs": [{"format": "bird"}]}"#,
+             "source #2: the registry is already listed"),
+            (r#"{"registry": "r", "sources": [{"name": "a", "vrps": "a.json"}, {"name": "a", "vrps": "b.json"}], "outputs": [{"format": "bird"}]}"#,
+             "source #2: duplicate source name 'a'"),
+            (r#"{"registry": "r", "sources": [{"name": "dn42", "vrps": "a.json"}], "outputs": [{"format": "bird"}]}"#,
+             "source name 'dn42' is used by the registry, list it in 'sources' to rename it"),
+            (r#"{"registry": "r", "sources": [{"name": "a", "registry": "a", "vrps": "a.json"}], "outputs": [{"format": "bird"}]}"#,
+             "source #1: 'registry' and 'vrps' are mutually exclusive"),
+            (r#"{"registry": "r", "sources": [{"name": "a", "vrps": "a.json", "revision": "HEAD"}], "outputs": [{"format": "bird"}]}"#,
+             "source #1: 'revision', 'filter_file', 'filter6_file' and 'source_filter_files' require a 'registry'"),
+            (r#"{"registry": "r", "source_filter_files": {"NEO": {"filter": "a"}}, "outputs": [{"format": "bird"}]}"#,
+             "source_filter_files 'NEO': unknown source_filter_files key 'filter'"),
+        ] {
+            let error = error(content);
+            assert!(error.starts_with(message), "{}: {}", content, error);
+        }
+        assert!(parse("{").is_err());
+    }
+}
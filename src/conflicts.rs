@@ -0,0 +1,238 @@
+//! Route objects that overlap in ways that are usually either anycast setups or squatting,
+//! for review by hand.
+
+use std::collections::{BTreeSet, HashMap};
+use cidr_utils::cidr::IpCidr;
+use json::JsonValue;
+use crate::parse::RouteObject;
+use crate::source::RegistrySource;
+use crate::vrp::{address_value, prefix_string, Vrp};
+use crate::{get_roa_objects_for_family, BoxResult, Family, FilterOverrides, ReportFormat, Warnings};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ConflictKind {
+    /// The prefix is authorised for multiple origins (AS0 is not counted).
+    Moas,
+    /// A more-specific object whose origins and maintainers differ from those of the closest covering object.
+    MoreSpecific,
+    /// Several object files define the same prefix, e.g. with differently written IPv6 addresses.
+    Split,
+}
+
+impl ConflictKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ConflictKind::Moas => "moas",
+            ConflictKind::MoreSpecific => "moreSpecific",
+            ConflictKind::Split => "split",
+        }
+    }
+
+    fn describe(self) -> &'static str {
+        match self {
+            ConflictKind::Moas => "Multiple origins",
+            ConflictKind::MoreSpecific => "More-specific with different origin and maintainers",
+            ConflictKind::Split => "Prefix defined in several files",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ObjectConflict {
+    pub kind: ConflictKind,
+    pub prefix: IpCidr,
+    /// The objects involved, for more-specifics the covering objects come first.
+    pub objects: Vec<RouteObject>,
+}
+
+#[derive(Debug, Default)]
+pub struct ConflictReport {
+    pub conflicts: Vec<ObjectConflict>,
+}
+
+impl ConflictReport {
+    pub fn read(source: &dyn RegistrySource, family: Family, filters: &FilterOverrides) -> BoxResult<(Self, Warnings)> {
+        let (objects, warnings) = get_roa_objects_for_family(source, family, filters)?;
+        Ok((Self::find(&objects), warnings))
+    }
+
+    /// Finds the conflicts between route objects that passed the filter set.
+    pub fn find(objects: &[RouteObject]) -> Self {
+        // Objects by address family, network address and prefix length
+        let mut by_network: HashMap<(bool, u128, u8), Vec<&RouteObject>> = HashMap::new();
+        for object in objects {
+            by_network.entry(network_key(&object.prefix, object.prefix.network_length())).or_default().push(object);
+        }
+
+        let mut conflicts: Vec<ObjectConflict> = Vec::new();
+        for group in by_network.values() {
+            let prefix = group[0].prefix;
+            let origins: BTreeSet<u32> = group.iter().flat_map(|o| o.origins.iter().copied()).filter(|asn| *asn != 0).collect();
+            if origins.len() > 1 {
+                conflicts.push(conflict(ConflictKind::Moas, prefix, group));
+            }
+            if group.len() > 1 {
+                conflicts.push(conflict(ConflictKind::Split, prefix, group));
+            }
+
+            let Some(covering) = (0..prefix.network_length()).rev().find_map(|length| by_network.get(&network_key(&prefix, length))) else {
+                continue;
+            };
+            let shares_origin = group.iter().flat_map(|o| &o.origins).any(|asn| covering.iter().any(|c| c.origins.contains(asn)));
+            let shares_maintainer = group.iter().flat_map(|o| &o.maintainers)
+                .any(|mnt| covering.iter().any(|c| c.maintainers.iter().any(|m| m.eq_ignore_ascii_case(mnt))));
+            if !shares_origin && !shares_maintainer {
+                let involved: Vec<&RouteObject> = covering.iter().chain(group).copied().collect();
+                conflicts.push(conflict(ConflictKind::MoreSpecific, prefix, &involved));
+            }
+        }
+        conflicts.sort_by_key(|c| (c.kind, c.prefix));
+        ConflictReport { conflicts }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+
+    fn summary(&self) -> [(ConflictKind, usize); 3] {
+        [ConflictKind::Moas, ConflictKind::MoreSpecific, ConflictKind::Split]
+            .map(|kind| (kind, self.conflicts.iter().filter(|c| c.kind == kind).count()))
+    }
+
+    pub fn render(&self, format: ReportFormat) -> String {
+        match format {
+            ReportFormat::Text => self.render_text(),
+            ReportFormat::Json => self.render_json(),
+        }
+    }
+
+    fn render_text(&self) -> String {
+        if self.is_empty() {
+            return "No conflicts found\n".to_owned();
+        }
+        let [moas, more_specific, split] = self.summary().map(|(_, count)| count);
+        let mut result = format!("{} multiple origins, {} more-specifics, {} split prefixes\n", moas, more_specific, split);
+        for conflict in &self.conflicts {
+            result.push_str(&format!("\n{}: {}\n", conflict.kind.describe(), prefix_string(&conflict.prefix)));
+            for object in &conflict.objects {
+                let maintainers = match object.maintainers.is_empty() {
+                    true => "no maintainer".to_owned(),
+                    false => object.maintainers.join(", "),
+                };
+                result.push_str(&format!("  {} ({})\n", object.file, maintainers));
+                for vrp in object.get_vrps() {
+                    result.push_str(&format!("    {}\n", vrp));
+                }
+            }
+        }
+        result
+    }
+
+    fn render_json(&self) -> String {
+        let mut top = JsonValue::new_object();
+        let mut summary = JsonValue::new_object();
+        for (kind, count) in self.summary() {
+            summary[kind.as_str()] = count.into();
+        }
+        top["summary"] = summary;
+
+        let mut conflicts = JsonValue::new_array();
+        for conflict in &self.conflicts {
+            let mut data = JsonValue::new_object();
+            data["type"] = conflict.kind.as_str().into();
+            data["prefix"] = prefix_string(&conflict.prefix).into();
+            let objects: Vec<JsonValue> = conflict.objects.iter().map(|object| {
+                let mut data = JsonValue::new_object();
+                data["file"] = object.file.as_str().into();
+                data["prefix"] = prefix_string(&object.prefix).into();
+                data["maintainers"] = object.maintainers.clone().into();
+                data["vrps"] = object.get_vrps().iter().map(Vrp::get_json_object).collect::<Vec<JsonValue>>().into();
+                data
+            }).collect();
+            data["objects"] = objects.into();
+            conflicts.push(data).expect("Error converting data to JSON");
+        }
+        top["conflicts"] = conflicts;
+        top.dump()
+    }
+}
+
+fn conflict(kind: ConflictKind, prefix: IpCidr, objects: &[&RouteObject]) -> ObjectConflict {
+    let mut objects: Vec<RouteObject> = objects.iter().map(|o| (*o).clone()).collect();
+    // Covering objects first, the objects of one prefix by file name
+    objects.sort_by(|a, b| (a.prefix.network_length(), &a.file).cmp(&(b.prefix.network_length(), &b.file)));
+    ObjectConflict { kind, prefix, objects }
+}
+
+/// The address family, network address and length of the prefix of length `length` containing `prefix`.
+fn network_key(prefix: &IpCidr, length: u8) -> (bool, u128, u8) {
+    let bits: u8 = if prefix.is_ipv6() { 128 } else { 32 };
+    let mask = match bits - length {
+        128 => 0,
+        host_bits => !((1u128 << host_bits) - 1),
+    };
+    (prefix.is_ipv6(), address_value(prefix.first_address()) & mask, length)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::str::FromStr;
+
+    fn object(file: &str, origins: &[u32], maintainer: &str) -> RouteObject {
+        let prefix = IpCidr::from_str(&file.replace('_', "/")).unwrap();
+        RouteObject {
+            file: file.to_owned(),
+            prefix,
+            origins: origins.to_vec(),
+            max_length: Cell::new(Some(prefix.network_length() as i32)),
+            maintainers: vec![maintainer.to_owned()],
+            source: None,
+        }
+    }
+
+    fn objects() -> Vec<RouteObject> {
+        vec![
+            object("172.20.0.0_16", &[4242420001], "FOO-MNT"),
+            object("172.20.1.0_24", &[4242420002], "BAR-MNT"),
+            // Sharing the origin or a maintainer with the covering object is fine
+            object("172.20.2.0_24", &[4242420001], "BAR-MNT"),
+            object("172.20.3.0_24", &[4242420003], "foo-mnt"),
+            object("172.20.4.0_24", &[4242420004, 4242420005], "FOO-MNT"),
+            // AS0 does not count as another origin
+            object("fd00::_48", &[0], "FOO-MNT"),
+            object("fd00:0::_48", &[4242420006], "FOO-MNT"),
+        ]
+    }
+
+    #[test]
+    fn find() {
+        let report = ConflictReport::find(&objects());
+        let conflicts: Vec<(ConflictKind, String, Vec<&str>)> = report.conflicts.iter()
+            .map(|c| (c.kind, prefix_string(&c.prefix), c.objects.iter().map(|o| o.file.as_str()).collect()))
+            .collect();
+        assert_eq!(conflicts, [
+            (ConflictKind::Moas, "172.20.4.0/24".to_owned(), vec!["172.20.4.0_24"]),
+            (ConflictKind::MoreSpecific, "172.20.1.0/24".to_owned(), vec!["172.20.0.0_16", "172.20.1.0_24"]),
+            (ConflictKind::Split, "fd00::/48".to_owned(), vec!["fd00:0::_48", "fd00::_48"]),
+        ]);
+        assert!(ConflictReport::find(&objects()[2..4]).is_empty());
+    }
+
+    #[test]
+    fn render() {
+        let report = ConflictReport::find(&objects()[..2]);
+        assert_eq!(report.render(ReportFormat::Text), "0 multiple origins, 1 more-specifics, 0 split prefixes\n\n\
+            More-specific with different origin and maintainers: 172.20.1.0/24\n\
+            \x20 172.20.0.0_16 (FOO-MNT)\n    172.20.0.0/16 max 16 as AS4242420001\n\
+            \x20 172.20.1.0_24 (BAR-MNT)\n    172.20.1.0/24 max 24 as AS4242420002\n");
+        let json = json::parse(&report.render(ReportFormat::Json)).unwrap();
+        assert_eq!(json["summary"]["moreSpecific"], 1);
+        assert_eq!(json["summary"]["moas"], 0);
+        assert_eq!(json["conflicts"][0]["type"], "moreSpecific");
+        assert_eq!(json["conflicts"][0]["objects"][1]["file"], "172.20.1.0_24");
+        assert_eq!(json["conflicts"][0]["objects"][1]["vrps"][0]["asn"], "4242420002");
+        assert_eq!(ConflictReport::default().render(ReportFormat::Text), "No conflicts found\n");
+    }
+}
@@ -0,0 +1,126 @@
+//! Conversion between UNIX timestamps and ISO 8601 dates in UTC.
+//! See http://howardhinnant.github.io/date_algorithms.html for the calendar arithmetic.
+
+use crate::BoxResult;
+
+/// Formats a UNIX timestamp as an ISO 8601 date.
+pub fn format_date(time: i64) -> String {
+    let (year, month, day) = civil_from_days(time.div_euclid(86400));
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Formats a UNIX timestamp as an ISO 8601 date and time.
+pub fn format_time(time: i64) -> String {
+    let seconds = time.rem_euclid(86400);
+    format!("{}T{:02}:{:02}:{:02}Z", format_date(time), seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+/// Returns the year and month of a UNIX timestamp.
+pub fn month_of(time: i64) -> (i64, i64) {
+    let (year, month, _) = civil_from_days(time.div_euclid(86400));
+    (year, month)
+}
+
+/// Parses a UNIX timestamp, `YYYY-MM-DD`, `YYYY-MM-DDTHH:MM` or `YYYY-MM-DDTHH:MM:SS`,
+/// optionally followed by `Z`. Dates are interpreted as UTC.
+pub fn parse_time(text: &str) -> BoxResult<i64> {
+    if !text.is_empty() && text.chars().all(|c| c.is_ascii_digit()) {
+        return text.parse::<i64>().map_err(|_| format!("timestamp out of range: {}", text).into());
+    }
+    let invalid = || format!("invalid time '{}' (expected a UNIX timestamp or YYYY-MM-DD[THH:MM[:SS]][Z])", text);
+    let text_utc = text.strip_suffix('Z').unwrap_or(text);
+    let (date, time) = text_utc.split_once(['T', ' ']).unwrap_or((text_utc, "00:00"));
+
+    let number = |part: Option<&str>, min: i64, max: i64| -> Option<i64> {
+        part.filter(|p| !p.is_empty() && p.chars().all(|c| c.is_ascii_digit()))
+            .and_then(|p| p.parse::<i64>().ok())
+            .filter(|v| (min..=max).contains(v))
+    };
+    let mut date_parts = date.split('-');
+    let year = number(date_parts.next(), 0, 9999).ok_or_else(invalid)?;
+    let month = number(date_parts.next(), 1, 12).ok_or_else(invalid)?;
+    let day = number(date_parts.next(), 1, 31).ok_or_else(invalid)?;
+    let mut time_parts = time.split(':');
+    let hour = number(time_parts.next(), 0, 23).ok_or_else(invalid)?;
+    let minute = number(time_parts.next(), 0, 59).ok_or_else(invalid)?;
+    let second = match time_parts.next() {
+        Some(part) => number(Some(part), 0, 60).ok_or_else(invalid)?,
+        None => 0,
+    };
+    if date_parts.next().is_some() || time_parts.next().is_some() {
+        return Err(invalid().into());
+    }
+
+    let days = days_from_civil(year, month, day);
+    if civil_from_days(days) != (year, month, day) {
+        return Err(invalid().into());
+    }
+    Ok(days * 86400 + hour * 3600 + minute * 60 + second)
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month_index = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn civil_dates() {
+        for (date, time) in [
+            ("1970-01-01", 0),
+            ("1969-12-31", -86400),
+            ("1900-03-01", -2203891200),
+            ("2000-02-29", 951782400),
+            ("2024-02-29", 1709164800),
+            ("2100-03-01", 4107542400),
+        ] {
+            assert_eq!(format_date(time), date);
+            assert_eq!(parse_time(date).unwrap(), time);
+            assert_eq!(days_from_civil_checked(date), time / 86400);
+        }
+        assert_eq!(format_time(1709210096), "2024-02-29T12:34:56Z");
+        assert_eq!(format_time(-1), "1969-12-31T23:59:59Z");
+        assert_eq!(month_of(1709164800 - 1), (2024, 2));
+        assert_eq!(month_of(1709164800 + 86400), (2024, 3));
+    }
+
+    fn days_from_civil_checked(date: &str) -> i64 {
+        let parts: Vec<i64> = date.split('-').map(|p| p.parse().unwrap()).collect();
+        let days = days_from_civil(parts[0], parts[1], parts[2]);
+        assert_eq!(civil_from_days(days), (parts[0], parts[1], parts[2]));
+        days
+    }
+
+    #[test]
+    fn parse_times() {
+        assert_eq!(parse_time("1709210096").unwrap(), 1709210096);
+        assert_eq!(parse_time("2024-02-29T12:34:56Z").unwrap(), 1709210096);
+        assert_eq!(parse_time("2024-02-29 12:34:56").unwrap(), 1709210096);
+        assert_eq!(parse_time("2024-02-29T12:34").unwrap(), 1709210040);
+        for invalid in ["", "2023-02-29", "2100-02-29", "2024-13-01", "2024-04-31", "2024-02", "2024-02-29T24:00",
+                        "2024-02-29T12", "2024-02-29T12:34:56:00", "2024-2-29x", "-1", "99999999999999999999"] {
+            assert!(parse_time(invalid).is_err(), "{}", invalid);
+        }
+    }
+}
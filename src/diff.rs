@@ -0,0 +1,374 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::PathBuf;
+use cidr_utils::cidr::IpCidr;
+use json::JsonValue;
+use crate::source::RegistrySource;
+use crate::vrp::{prefix_string, Vrp};
+use crate::{get_vrps_and_objects, BoxResult, Family, FilterOverrides, ReportFormat, Warnings};
+
+/// The VRPs of one registry state together with the maintainers of the route objects they come from.
+#[derive(Debug, Default)]
+pub struct VrpSet {
+    pub vrps: Vec<Vrp>,
+    maintainers: HashMap<IpCidr, Vec<String>>,
+}
+
+impl VrpSet {
+    pub fn read(source: &dyn RegistrySource, family: Family, filters: &FilterOverrides, slurm_files: &[PathBuf]) -> BoxResult<(Self, Warnings)> {
+        let (vrps, objects, warnings) = get_vrps_and_objects(source, family, filters, slurm_files)?;
+        let maintainers = objects.into_iter().map(|o| (o.prefix, o.maintainers)).collect();
+        Ok((VrpSet { vrps, maintainers }, warnings))
+    }
+
+    /// VRPs that do not come from a registry, e.g. from a ROA file.
+    pub fn from_vrps(vrps: Vec<Vrp>) -> Self {
+        VrpSet { vrps, maintainers: HashMap::new() }
+    }
+
+    /// Maintainers of the route object of `prefix`, empty for VRPs that only come from SLURM assertions.
+    pub fn maintainers(&self, prefix: &IpCidr) -> &[String] {
+        self.maintainers.get(prefix).map_or(&[], Vec::as_slice)
+    }
+
+    /// Max lengths by prefix and origin.
+    fn index(&self) -> BTreeMap<IpCidr, BTreeMap<u32, BTreeSet<u8>>> {
+        let mut index: BTreeMap<IpCidr, BTreeMap<u32, BTreeSet<u8>>> = BTreeMap::new();
+        for vrp in &self.vrps {
+            index.entry(vrp.prefix).or_default().entry(vrp.asn).or_default().insert(vrp.max_length);
+        }
+        index
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VrpChange {
+    Added(Vrp),
+    Removed(Vrp),
+    MaxLength { prefix: IpCidr, asn: u32, old: u8, new: u8 },
+    /// The prefix is now originated by `vrp.asn` instead of the previous origins.
+    Origin { vrp: Vrp, previous_origins: Vec<u32> },
+}
+
+impl VrpChange {
+    pub fn prefix(&self) -> IpCidr {
+        match self {
+            VrpChange::Added(vrp) | VrpChange::Removed(vrp) | VrpChange::Origin { vrp, .. } => vrp.prefix,
+            VrpChange::MaxLength { prefix, .. } => *prefix,
+        }
+    }
+
+    pub fn asn(&self) -> u32 {
+        match self {
+            VrpChange::Added(vrp) | VrpChange::Removed(vrp) | VrpChange::Origin { vrp, .. } => vrp.asn,
+            VrpChange::MaxLength { asn, .. } => *asn,
+        }
+    }
+
+    /// The change without its origin, which is given by the group it is listed in.
+    fn describe(&self) -> String {
+        match self {
+            VrpChange::Added(vrp) => format!("+ {} max {}", vrp.get_prefix_string(), vrp.max_length),
+            VrpChange::Removed(vrp) => format!("- {} max {}", vrp.get_prefix_string(), vrp.max_length),
+            VrpChange::MaxLength { prefix, old, new, .. } => format!("~ {} max {} -> {}", prefix_string(prefix), old, new),
+            VrpChange::Origin { vrp, previous_origins } => {
+                let previous: Vec<String> = previous_origins.iter().map(|asn| format!("AS{}", asn)).collect();
+                format!("~ {} max {} origin {} -> AS{}", vrp.get_prefix_string(), vrp.max_length, previous.join(", "), vrp.asn)
+            }
+        }
+    }
+
+    fn get_json_object(&self) -> JsonValue {
+        let mut data = JsonValue::new_object();
+        data["type"] = match self {
+            VrpChange::Added(_) => "added",
+            VrpChange::Removed(_) => "removed",
+            VrpChange::MaxLength { .. } => "maxLength",
+            VrpChange::Origin { .. } => "origin",
+        }.into();
+        data["prefix"] = prefix_string(&self.prefix()).into();
+        match self {
+            VrpChange::Added(vrp) | VrpChange::Removed(vrp) => {
+                data["maxLength"] = vrp.max_length.into();
+            }
+            VrpChange::MaxLength { old, new, .. } => {
+                data["oldMaxLength"] = (*old).into();
+                data["newMaxLength"] = (*new).into();
+            }
+            VrpChange::Origin { vrp, previous_origins } => {
+                data["maxLength"] = vrp.max_length.into();
+                data["previousOrigins"] = previous_origins.iter().map(|asn| asn.to_string()).collect::<Vec<String>>().into();
+            }
+        }
+        data
+    }
+}
+
+/// The VRP level difference between two registry states, grouped by maintainer and origin.
+#[derive(Debug)]
+pub struct RegistryDiff {
+    pub from: String,
+    pub to: String,
+    pub groups: BTreeMap<(Vec<String>, u32), Vec<VrpChange>>,
+}
+
+impl RegistryDiff {
+    pub fn compare(from: String, old: &VrpSet, to: String, new: &VrpSet) -> Self {
+        let old_index = old.index();
+        let new_index = new.index();
+        let empty = BTreeMap::new();
+        let prefixes: BTreeSet<&IpCidr> = old_index.keys().chain(new_index.keys()).collect();
+
+        let mut changes: Vec<VrpChange> = Vec::new();
+        for prefix in prefixes {
+            let old_origins = old_index.get(prefix).unwrap_or(&empty);
+            let new_origins = new_index.get(prefix).unwrap_or(&empty);
+            let removed_origins: Vec<u32> = old_origins.keys().filter(|asn| !new_origins.contains_key(asn)).copied().collect();
+            let added_origins: Vec<u32> = new_origins.keys().filter(|asn| !old_origins.contains_key(asn)).copied().collect();
+            let vrp = |asn: u32, max_length: u8| Vrp { prefix: *prefix, max_length, asn };
+
+            for (asn, old_lengths) in old_origins {
+                let Some(new_lengths) = new_origins.get(asn) else {
+                    continue;
+                };
+                if old_lengths == new_lengths {
+                    continue;
+                }
+                if old_lengths.len() == 1 && new_lengths.len() == 1 {
+                    let (old, new) = (*old_lengths.first().unwrap(), *new_lengths.first().unwrap());
+                    changes.push(VrpChange::MaxLength { prefix: *prefix, asn: *asn, old, new });
+                } else {
+                    changes.extend(new_lengths.difference(old_lengths).map(|l| VrpChange::Added(vrp(*asn, *l))));
+                    changes.extend(old_lengths.difference(new_lengths).map(|l| VrpChange::Removed(vrp(*asn, *l))));
+                }
+            }
+
+            // A prefix that lost and gained origins at the same time was moved to another origin
+            let moved = !removed_origins.is_empty() && !added_origins.is_empty();
+            for asn in &added_origins {
+                for max_length in &new_origins[asn] {
+                    changes.push(match moved {
+                        true => VrpChange::Origin { vrp: vrp(*asn, *max_length), previous_origins: removed_origins.clone() },
+                        false => VrpChange::Added(vrp(*asn, *max_length)),
+                    });
+                }
+            }
+            if !moved {
+                for asn in &removed_origins {
+                    changes.extend(old_origins[asn].iter().map(|l| VrpChange::Removed(vrp(*asn, *l))));
+                }
+            }
+        }
+
+        let mut groups: BTreeMap<(Vec<String>, u32), Vec<VrpChange>> = BTreeMap::new();
+        for change in changes {
+            let maintainers = match &change {
+                VrpChange::Removed(vrp) => old.maintainers(&vrp.prefix),
+                _ => new.maintainers(&change.prefix()),
+            };
+            groups.entry((maintainers.to_vec(), change.asn())).or_default().push(change);
+        }
+        RegistryDiff { from, to, groups }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    pub fn changes(&self) -> impl Iterator<Item = &VrpChange> {
+        self.groups.values().flatten()
+    }
+
+    fn summary(&self) -> [(&'static str, usize); 4] {
+        let count = |f: fn(&VrpChange) -> bool| self.changes().filter(|c| f(c)).count();
+        [
+            ("added", count(|c| matches!(c, VrpChange::Added(_)))),
+            ("removed", count(|c| matches!(c, VrpChange::Removed(_)))),
+            ("maxLengthChanged", count(|c| matches!(c, VrpChange::MaxLength { .. }))),
+            ("originChanged", count(|c| matches!(c, VrpChange::Origin { .. }))),
+        ]
+    }
+
+    pub fn render(&self, format: ReportFormat) -> String {
+        match format {
+            ReportFormat::Text => self.render_text(),
+            ReportFormat::Json => self.render_json(),
+        }
+    }
+
+    fn render_text(&self) -> String {
+        let mut result = format!("VRP changes from {} to {}\n", self.from, self.to);
+        if self.is_empty() {
+            result.push_str("No VRPs changed\n");
+            return result;
+        }
+        let [added, removed, max_length, origin] = self.summary().map(|(_, count)| count);
+        result.push_str(&format!("{} added, {} removed, {} max length changed, {} origin changed\n",
+                                 added, removed, max_length, origin));
+        let mut current: Option<&Vec<String>> = None;
+        for ((maintainers, asn), changes) in &self.groups {
+            if current != Some(maintainers) {
+                result.push('\n');
+                result.push_str(&match maintainers.is_empty() {
+                    true => "(no maintainer)\n".to_owned(),
+                    false => format!("{}\n", maintainers.join(", ")),
+                });
+                current = Some(maintainers);
+            }
+            result.push_str(&format!("  AS{}\n", asn));
+            for change in changes {
+                result.push_str(&format!("    {}\n", change.describe()));
+            }
+        }
+        result
+    }
+
+    fn render_json(&self) -> String {
+        let mut top = JsonValue::new_object();
+        top["from"] = self.from.as_str().into();
+        top["to"] = self.to.as_str().into();
+        let mut summary = JsonValue::new_object();
+        for (name, count) in self.summary() {
+            summary[name] = count.into();
+        }
+        top["summary"] = summary;
+
+        let mut groups = JsonValue::new_array();
+        for ((maintainers, asn), changes) in &self.groups {
+            let mut group = JsonValue::new_object();
+            group["maintainers"] = maintainers.clone().into();
+            group["asn"] = asn.to_string().into();
+            group["changes"] = changes.iter().map(VrpChange::get_json_object).collect::<Vec<JsonValue>>().into();
+            groups.push(group).expect("Error converting data to JSON");
+        }
+        top["groups"] = groups;
+        top.dump()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use crate::source::MemorySource;
+
+    fn route(prefix: &str, origins: &[u32], max_length: u8, maintainer: &str) -> String {
+        let origins: String = origins.iter().map(|asn| format!("origin: AS{}\n", asn)).collect();
+        format!("route: {}\n{}max-length: {}\nmnt-by: {}\nsource: DN42\n", prefix, origins, max_length, maintainer)
+    }
+
+    fn vrp_set(routes: &[(&str, &[u32], u8, &str)]) -> VrpSet {
+        let mut source = MemorySource::new();
+        source.insert("data/filter.txt", "1 permit 172.20.0.0/14 21 29\n");
+        for (prefix, origins, max_length, maintainer) in routes {
+            source.insert(format!("data/route/{}", prefix.replace('/', "_")), route(prefix, origins, *max_length, maintainer));
+        }
+        let (set, warnings) = VrpSet::read(&source, Family::V4, &FilterOverrides::default(), &[]).unwrap();
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        set
+    }
+
+    fn diff() -> RegistryDiff {
+        let old = vrp_set(&[
+            ("172.20.0.0/24", &[1], 24, "FOO-MNT"),
+            ("172.20.1.0/24", &[2], 24, "BAR-MNT"),
+            ("172.20.3.0/24", &[4], 24, "FOO-MNT"),
+            ("172.20.4.0/24", &[1, 2], 24, "FOO-MNT"),
+            ("172.20.5.0/24", &[6], 24, "FOO-MNT"),
+        ]);
+        let new = vrp_set(&[
+            ("172.20.0.0/24", &[1], 28, "FOO-MNT"),
+            ("172.20.2.0/24", &[3], 24, "FOO-MNT"),
+            ("172.20.3.0/24", &[5], 28, "BAR-MNT"),
+            ("172.20.4.0/24", &[1], 24, "FOO-MNT"),
+            ("172.20.5.0/24", &[6], 24, "BAR-MNT"),
+        ]);
+        RegistryDiff::compare("old".to_owned(), &old, "new".to_owned(), &new)
+    }
+
+    fn vrp(prefix: &str, max_length: u8, asn: u32) -> Vrp {
+        Vrp { prefix: IpCidr::from_str(prefix).unwrap(), max_length, asn }
+    }
+
+    #[test]
+    fn maintainers() {
+        let set = vrp_set(&[("172.20.0.0/24", &[1, 2], 28, "FOO-MNT")]);
+        assert_eq!(set.vrps, [vrp("172.20.0.0/24", 28, 1), vrp("172.20.0.0/24", 28, 2)]);
+        assert_eq!(set.maintainers(&IpCidr::from_str("172.20.0.0/24").unwrap()), ["FOO-MNT"]);
+        assert!(set.maintainers(&IpCidr::from_str("172.20.1.0/24").unwrap()).is_empty());
+        let set = VrpSet::from_vrps(vec![vrp("172.20.0.0/24", 24, 1)]);
+        assert!(set.maintainers(&IpCidr::from_str("172.20.0.0/24").unwrap()).is_empty());
+    }
+
+    #[test]
+    fn changes() {
+        let diff = diff();
+        let changes: Vec<VrpChange> = diff.changes().cloned().collect();
+        assert_eq!(changes.len(), 5);
+        assert!(changes.contains(&VrpChange::MaxLength { prefix: IpCidr::from_str("172.20.0.0/24").unwrap(), asn: 1, old: 24, new: 28 }));
+        assert!(changes.contains(&VrpChange::Removed(vrp("172.20.1.0/24", 24, 2))));
+        assert!(changes.contains(&VrpChange::Added(vrp("172.20.2.0/24", 24, 3))));
+        // The origin and the max length changed at once
+        assert!(changes.contains(&VrpChange::Origin { vrp: vrp("172.20.3.0/24", 28, 5), previous_origins: vec![4] }));
+        // An origin removed while another one stays is no origin change
+        assert!(changes.contains(&VrpChange::Removed(vrp("172.20.4.0/24", 24, 2))));
+
+        // A set of max lengths for one origin changes into additions and removals
+        let old = VrpSet::from_vrps(vec![vrp("172.20.0.0/24", 24, 1), vrp("172.20.0.0/24", 26, 1)]);
+        let new = VrpSet::from_vrps(vec![vrp("172.20.0.0/24", 24, 1), vrp("172.20.0.0/24", 28, 1)]);
+        let diff = RegistryDiff::compare("a".to_owned(), &old, "b".to_owned(), &new);
+        assert_eq!(diff.changes().cloned().collect::<Vec<_>>(),
+                   [VrpChange::Added(vrp("172.20.0.0/24", 28, 1)), VrpChange::Removed(vrp("172.20.0.0/24", 26, 1))]);
+        assert!(RegistryDiff::compare("a".to_owned(), &old, "b".to_owned(), &old).is_empty());
+    }
+
+    #[test]
+    fn groups() {
+        let diff = diff();
+        let groups: Vec<(&[String], u32, usize)> = diff.groups.iter().map(|((m, asn), c)| (m.as_slice(), *asn, c.len())).collect();
+        // Removed VRPs are grouped by their old maintainers, all other changes by the new ones
+        assert_eq!(groups, [
+            (&["BAR-MNT".to_owned()][..], 2, 1),
+            (&["BAR-MNT".to_owned()][..], 5, 1),
+            (&["FOO-MNT".to_owned()][..], 1, 1),
+            (&["FOO-MNT".to_owned()][..], 2, 1),
+            (&["FOO-MNT".to_owned()][..], 3, 1),
+        ]);
+    }
+
+    #[test]
+    fn render_text() {
+        assert_eq!(diff().render(ReportFormat::Text), "VRP changes from old to new\n\
+            1 added, 2 removed, 1 max length changed, 1 origin changed\n\
+            \n\
+            BAR-MNT\n  AS2\n    - 172.20.1.0/24 max 24\n  AS5\n    ~ 172.20.3.0/24 max 28 origin AS4 -> AS5\n\
+            \n\
+            FOO-MNT\n  AS1\n    ~ 172.20.0.0/24 max 24 -> 28\n  AS2\n    - 172.20.4.0/24 max 24\n  AS3\n    + 172.20.2.0/24 max 24\n");
+        let empty = RegistryDiff::compare("a".to_owned(), &VrpSet::default(), "b".to_owned(), &VrpSet::default());
+        assert_eq!(empty.render(ReportFormat::Text), "VRP changes from a to b\nNo VRPs changed\n");
+    }
+
+    #[test]
+    fn render_json() {
+        let data = json::parse(&diff().render(ReportFormat::Json)).unwrap();
+        assert_eq!(data["from"], "old");
+        assert_eq!(data["to"], "new");
+        assert_eq!(data["summary"]["added"], 1);
+        assert_eq!(data["summary"]["removed"], 2);
+        assert_eq!(data["summary"]["maxLengthChanged"], 1);
+        assert_eq!(data["summary"]["originChanged"], 1);
+        assert_eq!(data["groups"].len(), 5);
+        let group = &data["groups"][1];
+        assert_eq!(group["maintainers"][0], "BAR-MNT");
+        assert_eq!(group["asn"], "5");
+        assert_eq!(group["changes"][0]["type"], "origin");
+        assert_eq!(group["changes"][0]["prefix"], "172.20.3.0/24");
+        assert_eq!(group["changes"][0]["maxLength"], 28);
+        assert_eq!(group["changes"][0]["previousOrigins"][0], "4");
+        let change = &data["groups"][2]["changes"][0];
+        assert_eq!(change["type"], "maxLength");
+        assert_eq!(change["oldMaxLength"], 24);
+        assert_eq!(change["newMaxLength"], 28);
+        assert_eq!(data["groups"][4]["changes"][0]["type"], "added");
+        assert_eq!(data["groups"][4]["changes"][0]["maxLength"], 24);
+    }
+}
@@ -0,0 +1,1058 @@
+//! Read-only access to a local git object database (loose objects and packfiles)
+//! without requiring a checkout or the git binary.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use crate::inflate::zlib_decompress;
+use crate::BoxResult;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ObjectId(pub [u8; 20]);
+
+impl ObjectId {
+    pub fn to_hex(&self) -> String {
+        self.0.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+impl fmt::Display for ObjectId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_hex())
+    }
+}
+
+impl FromStr for ObjectId {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = parse_hex(s).filter(|b| b.len() == 20).ok_or(format!("invalid object id {}", s))?;
+        let mut id = [0u8; 20];
+        id.copy_from_slice(&bytes);
+        Ok(ObjectId(id))
+    }
+}
+
+fn parse_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) || !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok()).collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjectKind {
+    Commit,
+    Tree,
+    Blob,
+    Tag,
+}
+
+impl ObjectKind {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "commit" => Some(ObjectKind::Commit),
+            "tree" => Some(ObjectKind::Tree),
+            "blob" => Some(ObjectKind::Blob),
+            "tag" => Some(ObjectKind::Tag),
+            _ => None,
+        }
+    }
+
+    fn from_pack_type(value: u8) -> Option<Self> {
+        match value {
+            1 => Some(ObjectKind::Commit),
+            2 => Some(ObjectKind::Tree),
+            3 => Some(ObjectKind::Blob),
+            4 => Some(ObjectKind::Tag),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Signature {
+    pub name: String,
+    pub email: String,
+    /// Seconds since the UNIX epoch.
+    pub time: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Commit {
+    pub id: ObjectId,
+    pub tree: ObjectId,
+    pub parents: Vec<ObjectId>,
+    pub author: Signature,
+    pub committer: Signature,
+    pub message: String,
+}
+
+impl Commit {
+    pub fn summary(&self) -> &str {
+        self.message.lines().next().unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TreeEntry {
+    pub mode: u32,
+    pub name: String,
+    pub id: ObjectId,
+}
+
+impl TreeEntry {
+    pub fn is_tree(&self) -> bool {
+        self.mode == 0o40000
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        self.mode == 0o120000
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.mode == 0o100644 || self.mode == 0o100755 || self.mode == 0o100664
+    }
+}
+
+/// The entries of a tree object, indexed by name.
+#[derive(Debug)]
+pub struct Tree {
+    pub entries: Vec<TreeEntry>,
+    index: HashMap<String, usize>,
+}
+
+impl Tree {
+    fn new(entries: Vec<TreeEntry>) -> Self {
+        let index = entries.iter().enumerate().map(|(i, e)| (e.name.clone(), i)).collect();
+        Tree { entries, index }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&TreeEntry> {
+        self.index.get(name).map(|i| &self.entries[*i])
+    }
+}
+
+type CachedObject = Arc<(ObjectKind, Vec<u8>)>;
+/// Objects by pack index and offset, plus their total size in bytes.
+type DeltaBaseCache = (HashMap<(usize, u64), CachedObject>, usize);
+
+const TREE_CACHE_ENTRIES: usize = 4096;
+
+/// Upper bound for the memory used by cached delta bases.
+const DELTA_CACHE_BYTES: usize = 64 * 1024 * 1024;
+
+/// Longest chain of deltas that is resolved, which also stops cycles of reference deltas. git
+/// writes chains of 50 deltas by default and 250 for `gc --aggressive` before version 2.26.
+const MAX_DELTA_DEPTH: usize = 250;
+
+pub struct GitRepository {
+    git_dir: PathBuf,
+    common_dir: PathBuf,
+    object_dirs: Vec<PathBuf>,
+    packs: Vec<Pack>,
+    /// Commits of a shallow clone whose parents are not in the repository.
+    shallow: HashSet<ObjectId>,
+    delta_cache: Mutex<DeltaBaseCache>,
+    tree_cache: Mutex<HashMap<ObjectId, Arc<Tree>>>,
+}
+
+impl fmt::Debug for GitRepository {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "GitRepository({})", self.git_dir.display())
+    }
+}
+
+impl GitRepository {
+    /// Opens the repository of a working tree (containing `.git`) or a bare repository.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let git_dir = find_git_dir(path)?;
+        let common_dir = match fs::read_to_string(git_dir.join("commondir")) {
+            Ok(dir) => git_dir.join(dir.trim()),
+            Err(_) => git_dir.clone(),
+        };
+
+        let mut object_dirs = vec![common_dir.join("objects")];
+        if let Ok(alternates) = fs::read_to_string(common_dir.join("objects/info/alternates")) {
+            for line in alternates.lines().filter(|l| !l.is_empty() && !l.starts_with('#')) {
+                object_dirs.push(common_dir.join("objects").join(line));
+            }
+        }
+
+        let mut packs = Vec::new();
+        for object_dir in &object_dirs {
+            let Ok(dir) = fs::read_dir(object_dir.join("pack")) else {
+                continue;
+            };
+            for entry in dir.filter_map(Result::ok) {
+                let path = entry.path();
+                if path.extension().is_some_and(|e| e == "idx") {
+                    packs.push(Pack::open(&path)?);
+                }
+            }
+        }
+
+        let shallow = fs::read_to_string(common_dir.join("shallow")).unwrap_or_default().lines()
+            .filter_map(|line| line.trim().parse().ok())
+            .collect();
+
+        Ok(GitRepository {
+            git_dir,
+            common_dir,
+            object_dirs,
+            packs,
+            shallow,
+            delta_cache: Mutex::new((HashMap::new(), 0)),
+            tree_cache: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Resolves a revision (object id, abbreviated id, ref name, optionally followed by `~N`, `^` or `^N`)
+    /// to a commit id.
+    pub fn resolve(&self, revision: &str) -> BoxResult<ObjectId> {
+        let split = revision.find(['~', '^']).unwrap_or(revision.len());
+        let (base, mut suffix) = revision.split_at(split);
+        let mut id = self.peel_to_commit(self.resolve_name(if base.is_empty() { "HEAD" } else { base })?)?;
+
+        while !suffix.is_empty() {
+            let operator = suffix.as_bytes()[0];
+            let digits: String = suffix[1..].chars().take_while(char::is_ascii_digit).collect();
+            suffix = &suffix[1 + digits.len()..];
+            let count: usize = if digits.is_empty() { 1 } else { digits.parse()? };
+            match operator {
+                b'~' => {
+                    for _ in 0..count {
+                        id = *self.read_commit(&id)?.parents.first().ok_or(format!("revision {} has too few ancestors", revision))?;
+                    }
+                }
+                b'^' if count == 0 => {}
+                b'^' => {
+                    id = *self.read_commit(&id)?.parents.get(count - 1).ok_or(format!("revision {} has no such parent", revision))?;
+                }
+                _ => return Err(format!("invalid revision {}", revision).into()),
+            }
+        }
+        Ok(id)
+    }
+
+    fn resolve_name(&self, name: &str) -> BoxResult<ObjectId> {
+        if let Ok(id) = ObjectId::from_str(name) {
+            return Ok(id);
+        }
+        let candidates = [
+            name.to_owned(),
+            format!("refs/{}", name),
+            format!("refs/tags/{}", name),
+            format!("refs/heads/{}", name),
+            format!("refs/remotes/{}", name),
+            format!("refs/remotes/{}/HEAD", name),
+        ];
+        for candidate in &candidates {
+            if let Some(id) = self.read_ref(candidate, 0)? {
+                return Ok(id);
+            }
+        }
+        if name.len() >= 4 {
+            if let Some(id) = self.find_abbreviated(name)? {
+                return Ok(id);
+            }
+        }
+        Err(format!("unknown revision {}", name).into())
+    }
+
+    fn read_ref(&self, name: &str, depth: usize) -> BoxResult<Option<ObjectId>> {
+        if depth > 10 {
+            return Err(format!("too many levels of symbolic refs at {}", name).into());
+        }
+        if name.contains("..") {
+            return Ok(None);
+        }
+        for dir in [&self.git_dir, &self.common_dir] {
+            if let Ok(content) = fs::read_to_string(dir.join(name)) {
+                let content = content.trim();
+                if let Some(target) = content.strip_prefix("ref: ") {
+                    return self.read_ref(target.trim(), depth + 1);
+                }
+                if let Ok(id) = ObjectId::from_str(content) {
+                    return Ok(Some(id));
+                }
+            }
+        }
+        if let Ok(packed) = fs::read_to_string(self.common_dir.join("packed-refs")) {
+            for line in packed.lines().filter(|l| !l.starts_with('#') && !l.starts_with('^')) {
+                if let Some((id, ref_name)) = line.split_once(' ') {
+                    if ref_name == name {
+                        return Ok(Some(ObjectId::from_str(id)?));
+                    }
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    fn find_abbreviated(&self, prefix: &str) -> BoxResult<Option<ObjectId>> {
+        let prefix = prefix.to_ascii_lowercase();
+        if !prefix.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Ok(None);
+        }
+        let mut found: Vec<ObjectId> = Vec::new();
+        for object_dir in &self.object_dirs {
+            if let Ok(dir) = fs::read_dir(object_dir.join(&prefix[..2])) {
+                for entry in dir.filter_map(Result::ok) {
+                    let name = format!("{}{}", &prefix[..2], entry.file_name().to_string_lossy());
+                    if name.starts_with(&prefix) {
+                        if let Ok(id) = ObjectId::from_str(&name) {
+                            found.push(id);
+                        }
+                    }
+                }
+            }
+        }
+        for pack in &self.packs {
+            found.extend(pack.find_prefix(&prefix));
+        }
+        found.sort();
+        found.dedup();
+        match found.len() {
+            0 => Ok(None),
+            1 => Ok(Some(found[0])),
+            _ => Err(format!("abbreviated object id {} is ambiguous", prefix).into()),
+        }
+    }
+
+    fn peel_to_commit(&self, mut id: ObjectId) -> BoxResult<ObjectId> {
+        for _ in 0..10 {
+            let object = self.read_object(&id)?;
+            match object.0 {
+                ObjectKind::Commit => return Ok(id),
+                ObjectKind::Tag => {
+                    let text = String::from_utf8_lossy(&object.1);
+                    let target = text.lines().find_map(|l| l.strip_prefix("object ")).ok_or("tag without object")?;
+                    id = ObjectId::from_str(target)?;
+                }
+                kind => return Err(format!("object {} is a {:?}, not a commit", id, kind).into()),
+            }
+        }
+        Err(format!("too many levels of tags at {}", id).into())
+    }
+
+    pub fn read_object(&self, id: &ObjectId) -> BoxResult<CachedObject> {
+        self.read_object_limited(id, usize::MAX, 0)?.ok_or_else(|| format!("object {} is too large", id).into())
+    }
+
+    /// Reads an object unless it is larger than `limit` bytes. The size is taken from the object
+    /// header, so larger objects are not decompressed. `depth` counts the deltas this object is
+    /// the base of.
+    fn read_object_limited(&self, id: &ObjectId, limit: usize, depth: usize) -> BoxResult<Option<CachedObject>> {
+        for (index, pack) in self.packs.iter().enumerate() {
+            if let Some(offset) = pack.find(id) {
+                return self.read_packed(index, offset, limit, depth);
+            }
+        }
+        let hex = id.to_hex();
+        for object_dir in &self.object_dirs {
+            let path = object_dir.join(&hex[..2]).join(&hex[2..]);
+            if let Ok(compressed) = fs::read(&path) {
+                // The header is at most "commit " and 20 digits
+                let (mut data, _) = zlib_decompress(&compressed, compressed.len() * 4, limit.saturating_add(28))
+                    .map_err(|e| format!("corrupt loose object {}: {}", hex, e))?;
+                let header_end = data.iter().take(28).position(|b| *b == 0).ok_or(format!("corrupt loose object {}", hex))?;
+                let header = String::from_utf8_lossy(&data[..header_end]);
+                let (kind, size) = header.split_once(' ').ok_or(format!("corrupt loose object {}", hex))?;
+                let kind = ObjectKind::from_name(kind).ok_or(format!("unknown object type in {}", hex))?;
+                let size: usize = size.parse().map_err(|_| format!("corrupt loose object {}", hex))?;
+                if size > limit {
+                    return Ok(None);
+                }
+                if data.len() - header_end - 1 != size {
+                    return Err(format!("loose object size mismatch in {}", hex).into());
+                }
+                data.drain(..header_end + 1);
+                return Ok(Some(Arc::new((kind, data))));
+            }
+        }
+        Err(format!("object {} not found", hex).into())
+    }
+
+    fn read_packed(&self, pack_index: usize, offset: u64, limit: usize, depth: usize) -> BoxResult<Option<CachedObject>> {
+        if depth > MAX_DELTA_DEPTH {
+            return Err(format!("delta chain in {} is longer than {}", self.packs[pack_index].path.display(), MAX_DELTA_DEPTH).into());
+        }
+        if let Some(cached) = self.delta_cache.lock().expect("cache poisoned").0.get(&(pack_index, offset)) {
+            return Ok(Some(cached.clone()).filter(|c| c.1.len() <= limit));
+        }
+        let pack = &self.packs[pack_index];
+        let Some(entry) = pack.read_entry(offset, limit)? else {
+            return Ok(None);
+        };
+        let object = match entry.kind {
+            PackEntryKind::Object(kind) => Arc::new((kind, entry.data)),
+            PackEntryKind::OffsetDelta(base_offset) => {
+                let base = self.read_packed(pack_index, base_offset, usize::MAX, depth + 1)?.ok_or("delta base too large")?;
+                self.cache(pack_index, base_offset, &base);
+                Arc::new((base.0, apply_delta(&base.1, &entry.data)?))
+            }
+            PackEntryKind::RefDelta(base_id) => {
+                let base = self.read_object_limited(&base_id, usize::MAX, depth + 1)?.ok_or("delta base too large")?;
+                Arc::new((base.0, apply_delta(&base.1, &entry.data)?))
+            }
+        };
+        Ok(Some(object))
+    }
+
+    fn cache(&self, pack_index: usize, offset: u64, object: &CachedObject) {
+        let mut cache = self.delta_cache.lock().expect("cache poisoned");
+        if cache.0.contains_key(&(pack_index, offset)) {
+            return;
+        }
+        if cache.1 + object.1.len() > DELTA_CACHE_BYTES {
+            cache.0.clear();
+            cache.1 = 0;
+        }
+        cache.1 += object.1.len();
+        cache.0.insert((pack_index, offset), object.clone());
+    }
+
+    pub fn read_commit(&self, id: &ObjectId) -> BoxResult<Commit> {
+        let object = self.read_object(id)?;
+        if object.0 != ObjectKind::Commit {
+            return Err(format!("object {} is not a commit", id).into());
+        }
+        let mut commit = parse_commit(*id, &object.1).map_err(|e| format!("invalid commit {}: {}", id, e))?;
+        // The history of a shallow clone ends at these commits, like at a root commit
+        if self.shallow.contains(id) {
+            commit.parents.clear();
+        }
+        Ok(commit)
+    }
+
+    pub fn read_tree(&self, id: &ObjectId) -> BoxResult<Arc<Tree>> {
+        if let Some(tree) = self.tree_cache.lock().expect("cache poisoned").get(id) {
+            return Ok(tree.clone());
+        }
+        let object = self.read_object(id)?;
+        if object.0 != ObjectKind::Tree {
+            return Err(format!("object {} is not a tree", id).into());
+        }
+        let tree = Arc::new(Tree::new(parse_tree(&object.1).map_err(|e| format!("invalid tree {}: {}", id, e))?));
+        let mut cache = self.tree_cache.lock().expect("cache poisoned");
+        if cache.len() >= TREE_CACHE_ENTRIES {
+            cache.clear();
+        }
+        cache.insert(*id, tree.clone());
+        Ok(tree)
+    }
+
+    /// Reads a blob, or returns None if it is larger than `limit` bytes without decompressing it.
+    pub fn read_blob(&self, id: &ObjectId, limit: usize) -> BoxResult<Option<CachedObject>> {
+        let object = self.read_object_limited(id, limit, 0)?;
+        if object.as_ref().is_some_and(|o| o.0 != ObjectKind::Blob) {
+            return Err(format!("object {} is not a blob", id).into());
+        }
+        Ok(object)
+    }
+
+    /// Looks up a `/` separated path below the given tree.
+    pub fn find_path(&self, tree: &ObjectId, path: &str) -> BoxResult<Option<TreeEntry>> {
+        let mut current = TreeEntry { mode: 0o40000, name: String::new(), id: *tree };
+        for component in path.split('/').filter(|c| !c.is_empty()) {
+            if !current.is_tree() {
+                return Ok(None);
+            }
+            match self.read_tree(&current.id)?.get(component) {
+                Some(entry) => current = entry.clone(),
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(current))
+    }
+}
+
+pub fn find_git_dir(path: &Path) -> io::Result<PathBuf> {
+    let dot_git = path.join(".git");
+    if dot_git.is_dir() {
+        return Ok(dot_git);
+    }
+    if dot_git.is_file() {
+        // Linked worktrees and submodules use a file pointing to the git directory
+        let content = fs::read_to_string(&dot_git)?;
+        if let Some(dir) = content.trim().strip_prefix("gitdir: ") {
+            return Ok(path.join(dir));
+        }
+    }
+    if path.join("objects").is_dir() && path.join("HEAD").is_file() {
+        return Ok(path.to_path_buf());
+    }
+    Err(io::Error::new(io::ErrorKind::NotFound, format!("{} is not a git repository", path.display())))
+}
+
+fn parse_signature(value: &str) -> Signature {
+    let (name, rest) = value.split_once(" <").unwrap_or((value, ""));
+    let (email, rest) = rest.split_once('>').unwrap_or((rest, ""));
+    let time = rest.split_whitespace().next().and_then(|t| t.parse().ok()).unwrap_or(0);
+    Signature {
+        name: name.to_owned(),
+        email: email.to_owned(),
+        time,
+    }
+}
+
+fn parse_commit(id: ObjectId, data: &[u8]) -> BoxResult<Commit> {
+    let text = String::from_utf8_lossy(data);
+    let (headers, message) = text.split_once("\n\n").unwrap_or((&text, ""));
+    let mut tree: Option<ObjectId> = None;
+    let mut parents: Vec<ObjectId> = Vec::new();
+    let mut author: Option<Signature> = None;
+    let mut committer: Option<Signature> = None;
+    for line in headers.lines() {
+        match line.split_once(' ') {
+            Some(("tree", value)) => tree = Some(ObjectId::from_str(value)?),
+            Some(("parent", value)) => parents.push(ObjectId::from_str(value)?),
+            Some(("author", value)) => author = Some(parse_signature(value)),
+            Some(("committer", value)) => committer = Some(parse_signature(value)),
+            _ => {}
+        }
+    }
+    let author = author.ok_or("missing author")?;
+    Ok(Commit {
+        id,
+        tree: tree.ok_or("missing tree")?,
+        parents,
+        committer: committer.unwrap_or_else(|| author.clone()),
+        author,
+        message: message.to_owned(),
+    })
+}
+
+fn parse_tree(data: &[u8]) -> BoxResult<Vec<TreeEntry>> {
+    let mut entries = Vec::new();
+    let mut position = 0;
+    while position < data.len() {
+        let space = data[position..].iter().position(|b| *b == b' ').ok_or("truncated entry")? + position;
+        let nul = data[space..].iter().position(|b| *b == 0).ok_or("truncated entry")? + space;
+        let mode = u32::from_str_radix(&String::from_utf8_lossy(&data[position..space]), 8)?;
+        let name = String::from_utf8_lossy(&data[space + 1..nul]).into_owned();
+        let id_bytes = data.get(nul + 1..nul + 21).ok_or("truncated entry")?;
+        let mut id = [0u8; 20];
+        id.copy_from_slice(id_bytes);
+        entries.push(TreeEntry { mode, name, id: ObjectId(id) });
+        position = nul + 21;
+    }
+    Ok(entries)
+}
+
+fn read_varint_le(data: &[u8], position: &mut usize) -> BoxResult<u64> {
+    let mut value: u64 = 0;
+    let mut shift = 0;
+    loop {
+        let byte = *data.get(*position).ok_or("truncated delta")?;
+        *position += 1;
+        value |= ((byte & 0x7f) as u64) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 || shift > 63 {
+            return Ok(value);
+        }
+    }
+}
+
+fn apply_delta(base: &[u8], delta: &[u8]) -> BoxResult<Vec<u8>> {
+    let mut position = 0;
+    let base_size = read_varint_le(delta, &mut position)? as usize;
+    let result_size = read_varint_le(delta, &mut position)? as usize;
+    if base_size != base.len() {
+        return Err("delta base size mismatch".into());
+    }
+    // The declared size is only trusted as far as the delta could plausibly produce it
+    let mut result = Vec::with_capacity(result_size.min(base.len().saturating_add(delta.len())));
+    while position < delta.len() {
+        let op = delta[position];
+        position += 1;
+        if op & 0x80 != 0 {
+            let mut offset: usize = 0;
+            let mut size: usize = 0;
+            for i in 0..4 {
+                if op & (1 << i) != 0 {
+                    offset |= (*delta.get(position).ok_or("truncated delta")? as usize) << (8 * i);
+                    position += 1;
+                }
+            }
+            for i in 0..3 {
+                if op & (0x10 << i) != 0 {
+                    size |= (*delta.get(position).ok_or("truncated delta")? as usize) << (8 * i);
+                    position += 1;
+                }
+            }
+            if size == 0 {
+                size = 0x10000;
+            }
+            result.extend_from_slice(offset.checked_add(size).and_then(|end| base.get(offset..end)).ok_or("delta copy out of range")?);
+        } else if op != 0 {
+            let length = op as usize;
+            result.extend_from_slice(delta.get(position..position + length).ok_or("truncated delta")?);
+            position += length;
+        } else {
+            return Err("invalid delta opcode".into());
+        }
+        if result.len() > result_size {
+            return Err("delta result size mismatch".into());
+        }
+    }
+    if result.len() != result_size {
+        return Err("delta result size mismatch".into());
+    }
+    Ok(result)
+}
+
+enum PackEntryKind {
+    Object(ObjectKind),
+    OffsetDelta(u64),
+    RefDelta(ObjectId),
+}
+
+struct PackEntry {
+    kind: PackEntryKind,
+    data: Vec<u8>,
+}
+
+struct Pack {
+    path: PathBuf,
+    file: Mutex<File>,
+    fanout: Vec<u32>,
+    ids: Vec<u8>,
+    offsets: Vec<u64>,
+    /// All entry offsets in ascending order, followed by the end of the entry data.
+    sorted_offsets: Vec<u64>,
+}
+
+impl Pack {
+    fn open(idx_path: &Path) -> io::Result<Self> {
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", idx_path.display(), message));
+        let idx = fs::read(idx_path)?;
+        if idx.len() < 8 + 256 * 4 || idx[0..4] != [0xff, b't', b'O', b'c'] || idx[4..8] != [0, 0, 0, 2] {
+            return Err(invalid("unsupported pack index version"));
+        }
+        let read_u32 = |position: usize| u32::from_be_bytes(idx[position..position + 4].try_into().unwrap());
+        let fanout: Vec<u32> = (0..256).map(|i| read_u32(8 + i * 4)).collect();
+        // The last entry is the object count, lookups rely on the others not exceeding it
+        if fanout.windows(2).any(|pair| pair[0] > pair[1]) {
+            return Err(invalid("fanout table is not sorted"));
+        }
+        let count = fanout[255] as usize;
+        let ids_start = 8 + 256 * 4;
+        let offsets_start = ids_start + count * 20 + count * 4;
+        let large_start = offsets_start + count * 4;
+        if idx.len() < large_start + 40 {
+            return Err(invalid("truncated pack index"));
+        }
+        let mut offsets = Vec::with_capacity(count);
+        for i in 0..count {
+            let value = read_u32(offsets_start + i * 4);
+            if value & 0x8000_0000 != 0 {
+                let position = large_start + (value & 0x7fff_ffff) as usize * 8;
+                let bytes = idx.get(position..position + 8).ok_or_else(|| invalid("invalid large offset"))?;
+                offsets.push(u64::from_be_bytes(bytes.try_into().unwrap()));
+            } else {
+                offsets.push(value as u64);
+            }
+        }
+
+        let pack_path = idx_path.with_extension("pack");
+        let file = File::open(&pack_path)?;
+        let pack_length = file.metadata()?.len();
+        let mut sorted_offsets = offsets.clone();
+        sorted_offsets.sort_unstable();
+        sorted_offsets.push(pack_length.saturating_sub(20));
+
+        Ok(Pack {
+            path: pack_path,
+            file: Mutex::new(file),
+            fanout,
+            ids: idx[ids_start..ids_start + count * 20].to_vec(),
+            offsets,
+            sorted_offsets,
+        })
+    }
+
+    fn id_at(&self, index: usize) -> &[u8] {
+        &self.ids[index * 20..index * 20 + 20]
+    }
+
+    fn find(&self, id: &ObjectId) -> Option<u64> {
+        let first = id.0[0] as usize;
+        let start = if first == 0 { 0 } else { self.fanout[first - 1] as usize };
+        let end = self.fanout[first] as usize;
+        let (mut low, mut high) = (start, end);
+        while low < high {
+            let middle = (low + high) / 2;
+            match self.id_at(middle).cmp(&id.0[..]) {
+                std::cmp::Ordering::Less => low = middle + 1,
+                std::cmp::Ordering::Greater => high = middle,
+                std::cmp::Ordering::Equal => return Some(self.offsets[middle]),
+            }
+        }
+        None
+    }
+
+    fn find_prefix(&self, prefix: &str) -> Vec<ObjectId> {
+        let Some(first) = parse_hex(&prefix[..2]).map(|b| b[0] as usize) else {
+            return Vec::new();
+        };
+        let start = if first == 0 { 0 } else { self.fanout[first - 1] as usize };
+        let end = self.fanout[first] as usize;
+        (start..end)
+            .map(|i| {
+                let mut id = [0u8; 20];
+                id.copy_from_slice(self.id_at(i));
+                ObjectId(id)
+            })
+            .filter(|id| id.to_hex().starts_with(prefix))
+            .collect()
+    }
+
+    /// Reads the entry at `offset`, or returns None if the object is larger than `limit` bytes.
+    /// For deltas the size of the result is read from the delta.
+    fn read_entry(&self, offset: u64, limit: usize) -> BoxResult<Option<PackEntry>> {
+        let index = self.sorted_offsets.binary_search(&offset).map_err(|_| "invalid pack offset")?;
+        let length = self.sorted_offsets.get(index + 1).and_then(|end| end.checked_sub(offset)).ok_or("truncated pack entry")? as usize;
+        let mut raw = vec![0u8; length];
+        {
+            let mut file = self.file.lock().expect("pack file poisoned");
+            file.seek(SeekFrom::Start(offset))?;
+            file.read_exact(&mut raw).map_err(|e| format!("Unable to read {}: {}", self.path.display(), e))?;
+        }
+
+        let mut position = 0;
+        let mut byte = *raw.first().ok_or("truncated pack entry")?;
+        position += 1;
+        let pack_type = (byte >> 4) & 0x7;
+        let mut size = (byte & 0x0f) as usize;
+        let mut shift = 4;
+        while byte & 0x80 != 0 {
+            byte = *raw.get(position).ok_or("truncated pack entry")?;
+            position += 1;
+            if shift > usize::BITS - 7 {
+                return Err("invalid pack entry size".into());
+            }
+            size |= ((byte & 0x7f) as usize) << shift;
+            shift += 7;
+        }
+
+        let kind = match pack_type {
+            6 => {
+                let mut byte = *raw.get(position).ok_or("truncated pack entry")?;
+                position += 1;
+                let mut distance = (byte & 0x7f) as u64;
+                while byte & 0x80 != 0 {
+                    byte = *raw.get(position).ok_or("truncated pack entry")?;
+                    position += 1;
+                    distance = distance.checked_add(1).filter(|d| d.leading_zeros() >= 7).ok_or("invalid delta base offset")? << 7 | (byte & 0x7f) as u64;
+                }
+                // The base precedes the delta
+                PackEntryKind::OffsetDelta(offset.checked_sub(distance).filter(|_| distance > 0).ok_or("invalid delta base offset")?)
+            }
+            7 => {
+                let mut id = [0u8; 20];
+                id.copy_from_slice(raw.get(position..position + 20).ok_or("truncated pack entry")?);
+                position += 20;
+                PackEntryKind::RefDelta(ObjectId(id))
+            }
+            value => PackEntryKind::Object(ObjectKind::from_pack_type(value).ok_or("invalid pack entry type")?),
+        };
+        if matches!(kind, PackEntryKind::Object(_)) && size > limit {
+            return Ok(None);
+        }
+        let (data, _) = zlib_decompress(&raw[position..], size, size)
+            .map_err(|e| format!("corrupt pack entry at {} in {}: {}", offset, self.path.display(), e))?;
+        if data.len() != size {
+            return Err(format!("pack entry size mismatch at {} in {}", offset, self.path.display()).into());
+        }
+        if !matches!(kind, PackEntryKind::Object(_)) {
+            let mut position = 0;
+            read_varint_le(&data, &mut position)?;
+            if read_varint_le(&data, &mut position)? > limit as u64 {
+                return Ok(None);
+            }
+        }
+        Ok(Some(PackEntry { kind, data }))
+    }
+}
+
+/// Repositories for tests, built from the pack fixture and loose objects written on top of it.
+#[cfg(test)]
+pub(crate) mod fixtures {
+    use std::collections::{BTreeMap, HashMap};
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    /// A bare repository with a pack of three blobs: a route object, a copy with another origin
+    /// stored as an offset delta and a copy with a max-length stored as a reference delta.
+    pub fn pack_repository() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/pack")
+    }
+
+    pub const BASE: &str = "26255d74ade5bcc3baeb4195d1c2af323bc1142f";
+    pub const OFFSET_DELTA: &str = "3c2c8ee34102faa2176585ebe3be23ec729cdffe";
+    pub const REF_DELTA: &str = "d496b5168bb4d1e438d98002f6d7d838e3566a63";
+
+    pub fn route_object(origin: &str) -> String {
+        format!("route:              172.20.0.0/24\norigin:             {}\nmnt-by:             FOO-MNT\nsource:             DN42\n", origin)
+    }
+
+    /// A copy of the pack repository whose `.pack` or `.idx` file is modified by `modify`.
+    pub fn modified_pack_file(name: &str, extension: &str, modify: impl Fn(&mut Vec<u8>)) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("roa_wizard-{}-{}", std::process::id(), name));
+        let pack_dir = path.join("objects/pack");
+        fs::create_dir_all(&pack_dir).unwrap();
+        fs::write(path.join("HEAD"), "ref: refs/heads/master\n").unwrap();
+        for entry in fs::read_dir(pack_repository().join("objects/pack")).unwrap() {
+            let entry = entry.unwrap().path();
+            let mut data = fs::read(&entry).unwrap();
+            if entry.extension().is_some_and(|e| e == extension) {
+                modify(&mut data);
+            }
+            fs::write(pack_dir.join(entry.file_name().unwrap()), data).unwrap();
+        }
+        path
+    }
+
+    /// Writes a loose object with the given id, which is not checked against the content.
+    pub fn write_loose_object(repository: &Path, id: &str, kind: &str, content: &[u8]) {
+        let data = [format!("{} {}\0", kind, content.len()).as_bytes(), content].concat();
+        // A zlib stream of a single stored block, the Adler-32 checksum is not verified
+        let length = (data.len() as u16).to_le_bytes();
+        let compressed = [&[0x78, 0x01, 0x01, length[0], length[1], !length[0], !length[1]][..], &data, &[0; 4]].concat();
+        fs::create_dir_all(repository.join("objects").join(&id[..2])).unwrap();
+        fs::write(repository.join("objects").join(&id[..2]).join(&id[2..]), &compressed).unwrap();
+    }
+
+    /// A copy of the pack repository that commits are added to. Object ids are assigned in
+    /// order instead of hashing the content, objects with equal content share their id.
+    pub struct TestRepository {
+        pub path: PathBuf,
+        ids: HashMap<(String, Vec<u8>), String>,
+    }
+
+    impl TestRepository {
+        pub fn new(name: &str) -> Self {
+            let path = modified_pack_file(name, "", |_| {});
+            TestRepository { path, ids: HashMap::new() }
+        }
+
+        pub fn object(&mut self, kind: &str, content: Vec<u8>) -> String {
+            let next = self.ids.len() + 1;
+            let key = (kind.to_owned(), content);
+            if let Some(id) = self.ids.get(&key) {
+                return id.clone();
+            }
+            let id = format!("{:040x}", next);
+            write_loose_object(&self.path, &id, kind, &key.1);
+            self.ids.insert(key, id.clone());
+            id
+        }
+
+        /// A tree of the files at the given paths, given by their blob ids.
+        pub fn tree(&mut self, files: &[(&str, &str)]) -> String {
+            let mut entries: BTreeMap<&str, Vec<(&str, &str)>> = BTreeMap::new();
+            let mut data: Vec<u8> = Vec::new();
+            for (path, id) in files {
+                match path.split_once('/') {
+                    Some((directory, rest)) => entries.entry(directory).or_default().push((rest, id)),
+                    None => data.extend(tree_entry("100644", path, id)),
+                }
+            }
+            for (directory, files) in entries {
+                let id = self.tree(&files);
+                data.extend(tree_entry("40000", directory, &id));
+            }
+            self.object("tree", data)
+        }
+
+        pub fn blob(&mut self, content: &str) -> String {
+            self.object("blob", content.as_bytes().to_vec())
+        }
+
+        pub fn commit(&mut self, tree: &str, parent: Option<&str>, time: i64, summary: &str) -> String {
+            let parent = parent.map_or(String::new(), |p| format!("parent {}\n", p));
+            let signature = format!("A U Thor <author@example.com> {} +0000", time);
+            let content = format!("tree {}\n{}author {}\ncommitter {}\n\n{}\n", tree, parent, signature, signature, summary);
+            self.object("commit", content.into_bytes())
+        }
+    }
+
+    impl Drop for TestRepository {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.path);
+        }
+    }
+
+    fn tree_entry(mode: &str, name: &str, id: &str) -> Vec<u8> {
+        let id: Vec<u8> = (0..20).map(|i| u8::from_str_radix(&id[i * 2..i * 2 + 2], 16).unwrap()).collect();
+        [format!("{} {}\0", mode, name).as_bytes(), &id].concat()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::fixtures::*;
+
+    fn read_blob(repository: &GitRepository, id: &str) -> BoxResult<String> {
+        let blob = repository.read_blob(&ObjectId::from_str(id)?, usize::MAX)?.ok_or("too large")?;
+        Ok(String::from_utf8(blob.1.clone())?)
+    }
+
+    /// A copy of the pack repository whose pack file is modified by `modify`.
+    fn modified_repository(name: &str, modify: impl Fn(&mut Vec<u8>)) -> PathBuf {
+        modified_pack_file(name, "pack", modify)
+    }
+
+    #[test]
+    fn packed_objects_and_deltas() {
+        let repository = GitRepository::open(&pack_repository()).unwrap();
+        assert_eq!(read_blob(&repository, BASE).unwrap(), route_object("AS4242420000"));
+        assert_eq!(read_blob(&repository, OFFSET_DELTA).unwrap(), route_object("AS4242420001"));
+        assert_eq!(read_blob(&repository, REF_DELTA).unwrap(), route_object("AS4242420000") + "max-length:         28\n");
+    }
+
+    #[test]
+    fn missing_and_abbreviated_objects() {
+        let repository = GitRepository::open(&pack_repository()).unwrap();
+        assert!(read_blob(&repository, "0000000000000000000000000000000000000000").is_err());
+        assert_eq!(repository.find_abbreviated("3c2c8ee").unwrap(), Some(ObjectId::from_str(OFFSET_DELTA).unwrap()));
+        assert_eq!(repository.find_abbreviated("ffff").unwrap(), None);
+        assert!(repository.read_commit(&ObjectId::from_str(BASE).unwrap()).is_err());
+    }
+
+    #[test]
+    fn size_limit() {
+        let repository = GitRepository::open(&pack_repository()).unwrap();
+        let length = route_object("AS4242420000").len();
+        for (id, size) in [(BASE, length), (OFFSET_DELTA, length), (REF_DELTA, length + 23)] {
+            let id = ObjectId::from_str(id).unwrap();
+            assert!(repository.read_blob(&id, size).unwrap().is_some());
+            assert!(repository.read_blob(&id, size - 1).unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn loose_objects() {
+        let path = modified_repository("loose", |_| {});
+        let content = b"route:              172.20.1.0/24\n";
+        write_loose_object(&path, &format!("12{}", "34".repeat(19)), "blob", content);
+
+        let repository = GitRepository::open(&path).unwrap();
+        let id = ObjectId::from_str(&format!("12{}", "34".repeat(19))).unwrap();
+        assert_eq!(repository.read_blob(&id, content.len()).unwrap().unwrap().1, content);
+        assert!(repository.read_blob(&id, content.len() - 1).unwrap().is_none());
+        assert!(repository.read_tree(&id).is_err());
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn shallow_clone() {
+        let path = modified_repository("shallow", |_| {});
+        let id = "56".repeat(20);
+        let parent = "78".repeat(20);
+        let commit = format!("tree {}\nparent {}\nauthor A <a@example.com> 0 +0000\ncommitter A <a@example.com> 0 +0000\n\nmessage\n", BASE, parent);
+        write_loose_object(&path, &id, "commit", commit.as_bytes());
+        let id = ObjectId::from_str(&id).unwrap();
+        assert_eq!(GitRepository::open(&path).unwrap().read_commit(&id).unwrap().parents.len(), 1);
+
+        // The parent of a shallow commit is missing, its history ends there
+        fs::write(path.join("shallow"), format!("{}\n", id)).unwrap();
+        assert!(GitRepository::open(&path).unwrap().read_commit(&id).unwrap().parents.is_empty());
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn truncated_pack() {
+        let path = modified_repository("truncated-pack", |data| data.truncate(100));
+        let repository = GitRepository::open(&path).unwrap();
+        assert!(read_blob(&repository, BASE).is_ok());
+        assert!(read_blob(&repository, OFFSET_DELTA).is_err());
+        assert!(read_blob(&repository, REF_DELTA).is_err());
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn corrupt_pack_does_not_panic() {
+        let length = fs::read(pack_repository().join("objects/pack/pack-510d60fbabf7182bec71eb8b41efcda8dd47e4b2.pack")).unwrap().len();
+        // The entries follow the 12 byte header
+        for position in 12..length - 20 {
+            let path = modified_repository("corrupt-pack", |data| data[position] ^= 0xff);
+            let repository = GitRepository::open(&path).unwrap();
+            for id in [BASE, OFFSET_DELTA, REF_DELTA] {
+                let _ = read_blob(&repository, id);
+            }
+            fs::remove_dir_all(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn corrupt_index() {
+        // Index version header, then the fanout table
+        let fanout = |k: usize| 8 + k * 4;
+        let path = modified_pack_file("unsorted-fanout", "idx", |data| data[fanout(0x26)..fanout(0x26) + 4].copy_from_slice(&[0, 0, 0, 200]));
+        let error = GitRepository::open(&path).unwrap_err().to_string();
+        assert!(error.ends_with("fanout table is not sorted"), "{}", error);
+        fs::remove_dir_all(path).unwrap();
+
+        let path = modified_pack_file("large-count", "idx", |data| data[fanout(255)..fanout(255) + 4].copy_from_slice(&[0, 0, 1, 0]));
+        assert!(GitRepository::open(&path).unwrap_err().to_string().ends_with("truncated pack index"));
+        fs::remove_dir_all(path).unwrap();
+
+        let path = modified_pack_file("truncated-index", "idx", |data| data.truncate(1000));
+        assert!(GitRepository::open(&path).is_err());
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn cyclic_ref_delta() {
+        let base = ObjectId::from_str(BASE).unwrap().0;
+        let own_id = ObjectId::from_str(REF_DELTA).unwrap().0;
+        // The reference delta names itself as its base
+        let path = modified_repository("cyclic-delta", |data| {
+            let position = data.windows(20).position(|w| w == base).expect("reference delta base");
+            data[position..position + 20].copy_from_slice(&own_id);
+        });
+        let repository = GitRepository::open(&path).unwrap();
+        let error = read_blob(&repository, REF_DELTA).unwrap_err().to_string();
+        assert!(error.contains("delta chain"), "{}", error);
+        assert_eq!(read_blob(&repository, BASE).unwrap(), route_object("AS4242420000"));
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn deltas() {
+        let base = b"0123456789";
+        // Base size 10, result size 6, copy 4 bytes at offset 2, insert "ab"
+        assert_eq!(apply_delta(base, &[10, 6, 0x91, 2, 4, 2, b'a', b'b']).unwrap(), b"2345ab");
+        assert!(apply_delta(base, &[9, 6, 0x91, 2, 4, 2, b'a', b'b']).is_err(), "base size mismatch");
+        assert!(apply_delta(base, &[10, 7, 0x91, 2, 4, 2, b'a', b'b']).is_err(), "result size mismatch");
+        assert!(apply_delta(base, &[10, 6, 0x91, 8, 4]).is_err(), "copy out of range");
+        assert!(apply_delta(base, &[10, 6, 0x91, 2]).is_err(), "truncated copy");
+        assert!(apply_delta(base, &[10, 2, 3, b'a']).is_err(), "truncated insert");
+        assert!(apply_delta(base, &[10, 0, 0]).is_err(), "reserved opcode");
+        // A result size far beyond what the delta produces is not allocated up front
+        assert!(apply_delta(base, &[10, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f, 1, b'a']).is_err());
+    }
+
+    #[test]
+    fn trees() {
+        let mut data = b"100644 filter.txt\0".to_vec();
+        data.extend_from_slice(&[0x11; 20]);
+        data.extend_from_slice(b"40000 data\0");
+        data.extend_from_slice(&[0x22; 20]);
+        let entries = parse_tree(&data).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert!(entries[0].is_file() && entries[0].name == "filter.txt" && entries[0].id == ObjectId([0x11; 20]));
+        assert!(entries[1].is_tree() && entries[1].name == "data");
+        assert!(parse_tree(&data[..data.len() - 1]).is_err());
+        assert!(parse_tree(b"100644 name").is_err());
+    }
+}
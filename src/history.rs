@@ -0,0 +1,241 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use crate::git::{Commit, GitRepository, ObjectId};
+use crate::parse::{evaluate_filter_set, parse_route_object, read_filter_set, read_filter_set_file, FilterSet, RouteObject};
+use crate::rpsl::parse_object;
+use crate::source::{GitSource, RegistrySource};
+use crate::vrp::Vrp;
+use crate::{read_source_filter_sets, BoxResult, Family, FilterOverrides};
+
+/// The route objects and filter sets of a registry at one commit, moved between commits by
+/// re-reading only the files that differ. Invalid route objects are skipped silently.
+pub(crate) struct RegistryHistory<'a> {
+    repository: Arc<GitRepository>,
+    families: Vec<bool>,
+    filter_overrides: FilterOverrides,
+    /// Route objects that are not accepted by this function are not kept.
+    object_filter: Box<dyn Fn(&RouteObject) -> bool + 'a>,
+    /// Tree ids of the route directories and filter set files, by path.
+    ids: HashMap<&'static str, Option<ObjectId>>,
+    objects: BTreeMap<String, RouteObject>,
+    filters_v4: Vec<FilterSet>,
+    filters_v6: Vec<FilterSet>,
+    /// Filter sets of specific sources by family, from files outside the registry.
+    source_filters: [Vec<(String, Vec<FilterSet>)>; 2],
+    initialized: bool,
+}
+
+impl<'a> RegistryHistory<'a> {
+    pub fn new(repository: Arc<GitRepository>, family: Family, filters: &FilterOverrides, object_filter: Box<dyn Fn(&RouteObject) -> bool + 'a>) -> Self {
+        let families = match family {
+            Family::V4 => vec![false],
+            Family::V6 => vec![true],
+            Family::Both => vec![false, true],
+        };
+        RegistryHistory {
+            repository,
+            families,
+            filter_overrides: filters.clone(),
+            object_filter,
+            ids: HashMap::new(),
+            objects: BTreeMap::new(),
+            filters_v4: Vec::new(),
+            filters_v6: Vec::new(),
+            source_filters: [Vec::new(), Vec::new()],
+            initialized: false,
+        }
+    }
+
+    fn route_directory(is_v6: bool) -> &'static str {
+        if is_v6 { "data/route6" } else { "data/route" }
+    }
+
+    fn filter_file(is_v6: bool) -> &'static str {
+        if is_v6 { "data/filter6.txt" } else { "data/filter.txt" }
+    }
+
+    /// Moves the state to `commit`. Returns whether anything changed.
+    pub fn update(&mut self, commit: &Commit) -> BoxResult<bool> {
+        let source = GitSource { repository: self.repository.clone(), commit: commit.id, tree: commit.tree };
+        let mut changed = false;
+        for is_v6 in self.families.clone() {
+            let directory = Self::route_directory(is_v6);
+            let new_id = self.repository.find_path(&commit.tree, directory)?.map(|e| e.id);
+            let old_id = self.ids.insert(directory, new_id).flatten();
+            if old_id != new_id {
+                changed = true;
+                for name in self.changed_names(old_id.as_ref(), new_id.as_ref())? {
+                    let path = format!("{}/{}", directory, name);
+                    self.objects.remove(&path);
+                    let object = match source.read_file(&path) {
+                        Ok(content) => parse_route_object(&path, &parse_object(&content).0, is_v6).ok(),
+                        Err(_) => None,
+                    };
+                    let selected = |o: &RouteObject| (self.object_filter)(o) && self.filter_overrides.sources.matches(o.source.as_deref());
+                    if let Some(object) = object.filter(selected) {
+                        self.objects.insert(path, object);
+                    }
+                }
+            }
+
+            let filter_file = Self::filter_file(is_v6);
+            let new_id = self.repository.find_path(&commit.tree, filter_file)?.map(|e| e.id);
+            let old_id = self.ids.insert(filter_file, new_id).flatten();
+            // Filter set overrides apply to the whole history and are only read once
+            let filters = match if is_v6 { &self.filter_overrides.filter_v6 } else { &self.filter_overrides.filter_v4 } {
+                Some(file) if !self.initialized => Some(read_filter_set_file(file)?.0),
+                Some(_) => None,
+                None if old_id == new_id && self.initialized => None,
+                None if new_id.is_some() => Some(read_filter_set(&source, filter_file)?.0),
+                None => Some(Vec::new()),
+            };
+            if let Some(filters) = filters {
+                changed = true;
+                if is_v6 { self.filters_v6 = filters } else { self.filters_v4 = filters };
+            }
+            if !self.initialized {
+                self.source_filters[is_v6 as usize] = read_source_filter_sets(&self.filter_overrides, is_v6, &mut Vec::new())?;
+            }
+        }
+        self.initialized = true;
+        Ok(changed)
+    }
+
+    fn changed_names(&self, old: Option<&ObjectId>, new: Option<&ObjectId>) -> BoxResult<Vec<String>> {
+        let old = old.map(|id| self.repository.read_tree(id)).transpose()?;
+        let new = new.map(|id| self.repository.read_tree(id)).transpose()?;
+        let mut names: Vec<String> = Vec::new();
+        if let Some(new) = &new {
+            for entry in &new.entries {
+                if old.as_ref().and_then(|o| o.get(&entry.name)).is_none_or(|o| o.id != entry.id) {
+                    names.push(entry.name.clone());
+                }
+            }
+        }
+        if let Some(old) = &old {
+            for entry in &old.entries {
+                if new.as_ref().and_then(|n| n.get(&entry.name)).is_none() {
+                    names.push(entry.name.clone());
+                }
+            }
+        }
+        Ok(names)
+    }
+
+    /// All valid route objects of a family, before the filter set is applied.
+    pub fn objects(&self, is_v6: bool) -> impl Iterator<Item = &RouteObject> {
+        self.objects.values().filter(move |o| o.prefix.is_ipv6() == is_v6)
+    }
+
+    /// The route objects of a family accepted by its filter set, with the max length applied.
+    pub fn accepted_objects(&self, is_v6: bool) -> Vec<RouteObject> {
+        let mut objects: Vec<RouteObject> = self.objects(is_v6).cloned().collect();
+        let filters = if is_v6 { &self.filters_v6 } else { &self.filters_v4 };
+        evaluate_filter_set(&mut objects, filters, &self.source_filters[is_v6 as usize]);
+        objects
+    }
+
+    /// The VRPs of all tracked families.
+    pub fn vrps(&self) -> impl Iterator<Item = Vrp> + '_ {
+        self.families.iter().flat_map(|is_v6| self.accepted_objects(*is_v6)).flat_map(|o| o.get_vrps())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use crate::git::fixtures::{TestRepository, BASE, OFFSET_DELTA, REF_DELTA};
+
+    const FILTER: &str = "1 permit 172.20.0.0/14 21 29\n99 deny 0.0.0.0/0 0 32\n";
+
+    fn vrps(state: &RegistryHistory) -> Vec<String> {
+        let mut vrps: Vec<String> = state.vrps().map(|v| v.to_string()).collect();
+        vrps.sort();
+        vrps
+    }
+
+    /// Commits changing a route object, adding and removing another one, touching only an
+    /// unrelated file and changing the filter set.
+    fn history(name: &str) -> (TestRepository, Vec<Commit>) {
+        let mut repository = TestRepository::new(name);
+        let filter = repository.blob(FILTER);
+        let narrow_filter = repository.blob(&FILTER.replace("21 29", "24 24"));
+        let other = repository.blob("route: 172.20.1.0/24\norigin: AS4242420002\nmnt-by: BAR-MNT\nsource: DN42\n");
+        let maintainer = repository.blob("mntner: FOO-MNT\nsource: DN42\n");
+        let route = "data/route/172.20.0.0_24";
+        let trees = [
+            repository.tree(&[("data/filter.txt", &filter), (route, BASE)]),
+            repository.tree(&[("data/filter.txt", &filter), (route, OFFSET_DELTA), ("data/route/172.20.1.0_24", &other)]),
+            repository.tree(&[("data/filter.txt", &filter), (route, OFFSET_DELTA), ("data/route/172.20.1.0_24", &other),
+                              ("data/mntner/FOO-MNT", &maintainer)]),
+            repository.tree(&[("data/filter.txt", &filter), (route, REF_DELTA), ("data/mntner/FOO-MNT", &maintainer)]),
+            repository.tree(&[("data/filter.txt", &narrow_filter), (route, REF_DELTA), ("data/mntner/FOO-MNT", &maintainer)]),
+        ];
+        let mut ids: Vec<String> = Vec::new();
+        for (i, tree) in trees.iter().enumerate() {
+            let id = repository.commit(tree, ids.last().map(String::as_str), i as i64, "change");
+            ids.push(id);
+        }
+        let git = GitRepository::open(&repository.path).unwrap();
+        let commits = ids.iter().map(|id| git.read_commit(&ObjectId::from_str(id).unwrap()).unwrap()).collect();
+        (repository, commits)
+    }
+
+    #[test]
+    fn incremental_updates() {
+        let (repository, commits) = history("history-updates");
+        let git = Arc::new(GitRepository::open(&repository.path).unwrap());
+        let expected: [&[&str]; 5] = [
+            &["172.20.0.0/24 max 29 as AS4242420000"],
+            &["172.20.0.0/24 max 29 as AS4242420001", "172.20.1.0/24 max 29 as AS4242420002"],
+            &["172.20.0.0/24 max 29 as AS4242420001", "172.20.1.0/24 max 29 as AS4242420002"],
+            &["172.20.0.0/24 max 28 as AS4242420000"],
+            &["172.20.0.0/24 max 24 as AS4242420000"],
+        ];
+        let mut state = RegistryHistory::new(git.clone(), Family::V4, &FilterOverrides::default(), Box::new(|_| true));
+        // Forwards, backwards and skipping commits end in the same state as reading each commit from scratch
+        for (index, changed) in [(0, true), (1, true), (2, false), (3, true), (4, true), (0, true), (4, true), (3, true)] {
+            assert_eq!(state.update(&commits[index]).unwrap(), changed, "commit {}", index);
+            assert_eq!(vrps(&state), expected[index], "commit {}", index);
+            let mut fresh = RegistryHistory::new(git.clone(), Family::V4, &FilterOverrides::default(), Box::new(|_| true));
+            fresh.update(&commits[index]).unwrap();
+            assert_eq!(vrps(&fresh), expected[index]);
+            assert_eq!(state.objects(false).count(), fresh.objects(false).count());
+        }
+        assert!(!state.update(&commits[3]).unwrap());
+    }
+
+    #[test]
+    fn object_filter_and_families() {
+        let (repository, commits) = history("history-filter");
+        let git = Arc::new(GitRepository::open(&repository.path).unwrap());
+        let mut state = RegistryHistory::new(git.clone(), Family::V4, &FilterOverrides::default(),
+                                             Box::new(|o| o.maintainers.iter().any(|m| m == "BAR-MNT")));
+        state.update(&commits[1]).unwrap();
+        assert_eq!(vrps(&state), ["172.20.1.0/24 max 29 as AS4242420002"]);
+        state.update(&commits[3]).unwrap();
+        assert!(vrps(&state).is_empty());
+
+        // A registry without IPv6 data has no IPv6 objects
+        let mut state = RegistryHistory::new(git, Family::Both, &FilterOverrides::default(), Box::new(|_| true));
+        state.update(&commits[1]).unwrap();
+        assert_eq!(state.objects(true).count(), 0);
+        assert_eq!(state.objects(false).count(), 2);
+    }
+
+    #[test]
+    fn filter_overrides() {
+        let (repository, commits) = history("history-overrides");
+        let filter_file = repository.path.join("filter.txt");
+        std::fs::write(&filter_file, FILTER.replace("21 29", "24 26")).unwrap();
+        let overrides = FilterOverrides { filter_v4: Some(filter_file), ..FilterOverrides::default() };
+        let git = Arc::new(GitRepository::open(&repository.path).unwrap());
+        let mut state = RegistryHistory::new(git, Family::V4, &overrides, Box::new(|_| true));
+        state.update(&commits[0]).unwrap();
+        assert_eq!(vrps(&state), ["172.20.0.0/24 max 26 as AS4242420000"]);
+        // Changes of the filter set in the registry are ignored
+        state.update(&commits[4]).unwrap();
+        assert_eq!(vrps(&state), ["172.20.0.0/24 max 26 as AS4242420000"]);
+    }
+}
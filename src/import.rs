@@ -0,0 +1,189 @@
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use cidr_utils::cidr::IpCidr;
+use json::JsonValue;
+use crate::{io_error, BoxResult};
+use crate::vrp::Vrp;
+
+/// Reads a ROA file in one of the formats recognized by `parse_roa_data`.
+pub fn read_roa_file(path: &Path) -> BoxResult<Vec<Vrp>> {
+    let content = fs::read_to_string(path).map_err(|e|
+        io_error(e, format!("Unable to read ROA file {}", path.display()))
+    )?;
+    parse_roa_data(&content).map_err(|e| format!("Unable to parse ROA file {}: {}", path.display(), e).into())
+}
+
+/// Parses ROA data in bird2 or bird1 format, JSON as written by roa_wizard, rpki-client or
+/// Routinator, or CSV with `ASN`, `IP Prefix` and `Max Length` columns.
+pub fn parse_roa_data(content: &str) -> BoxResult<Vec<Vrp>> {
+    let first_line = content.lines().map(str::trim).find(|l| !l.is_empty() && !l.starts_with('#')).unwrap_or_default();
+    if first_line.starts_with('{') {
+        parse_json(content)
+    } else if first_line.starts_with("route ") || first_line.starts_with("roa ") || first_line.is_empty() {
+        parse_bird(content)
+    } else {
+        parse_csv(content)
+    }
+}
+
+/// Parses `route <prefix> max <length> as <asn>;` lines, or `roa ...` lines in bird1 format.
+fn parse_bird(content: &str) -> BoxResult<Vec<Vrp>> {
+    let mut result: Vec<Vrp> = Vec::new();
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let parse_line = || -> BoxResult<Vrp> {
+            let tokens: Vec<&str> = line.trim_end_matches(';').split_whitespace().collect();
+            match tokens.as_slice() {
+                ["route" | "roa", prefix, "max", max_length, "as", asn] => Ok(Vrp {
+                    prefix: parse_prefix(prefix)?,
+                    max_length: max_length.parse().map_err(|_| format!("invalid max length {}", max_length))?,
+                    asn: parse_asn(asn)?,
+                }),
+                _ => Err("expected 'route <prefix> max <length> as <asn>;'".into()),
+            }
+        };
+        result.push(parse_line().map_err(|e| format!("line {}: {}", index + 1, e))?);
+    }
+    Ok(result)
+}
+
+fn parse_json(content: &str) -> BoxResult<Vec<Vrp>> {
+    let root = json::parse(content)?;
+    if !root["roas"].is_array() {
+        return Err("missing 'roas' array".into());
+    }
+    let mut result: Vec<Vrp> = Vec::new();
+    for (index, entry) in root["roas"].members().enumerate() {
+        let parse_entry = || -> BoxResult<Vrp> {
+            let prefix = parse_prefix(entry["prefix"].as_str().ok_or("missing prefix")?)?;
+            let max_length = entry["maxLength"].as_u8().ok_or("missing or invalid maxLength")?;
+            let asn = match &entry["asn"] {
+                JsonValue::Number(_) => entry["asn"].as_u32().ok_or("invalid asn")?,
+                value => parse_asn(value.as_str().ok_or("missing asn")?)?,
+            };
+            Ok(Vrp { prefix, max_length, asn })
+        };
+        result.push(parse_entry().map_err(|e| format!("roa #{}: {}", index + 1, e))?);
+    }
+    Ok(result)
+}
+
+/// Parses CSV as written by rpki-client and Routinator: `ASN,IP Prefix,Max Length,Trust Anchor`.
+/// Without a header line, the columns are expected in this order.
+fn parse_csv(content: &str) -> BoxResult<Vec<Vrp>> {
+    let mut columns = [0, 1, 2];
+    let mut result: Vec<Vrp> = Vec::new();
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').map(|f| f.trim().trim_matches('"')).collect();
+        if result.is_empty() && fields.iter().any(|f| f.eq_ignore_ascii_case("asn")) {
+            let position = |names: &[&str]| fields.iter().position(|f| names.iter().any(|n| f.eq_ignore_ascii_case(n)))
+                .ok_or("CSV header lacks one of the columns ASN, IP Prefix and Max Length");
+            columns = [position(&["asn"])?, position(&["ip prefix", "prefix"])?, position(&["max length", "maxlength"])?];
+            continue;
+        }
+        let parse_line = || -> BoxResult<Vrp> {
+            let field = |column: usize| fields.get(column).copied().ok_or("missing column");
+            let max_length = field(columns[2])?;
+            Ok(Vrp {
+                prefix: parse_prefix(field(columns[1])?)?,
+                max_length: max_length.parse().map_err(|_| format!("invalid max length {}", max_length))?,
+                asn: parse_asn(field(columns[0])?)?,
+            })
+        };
+        result.push(parse_line().map_err(|e| format!("line {}: {}", index + 1, e))?);
+    }
+    Ok(result)
+}
+
+pub fn parse_prefix(text: &str) -> BoxResult<IpCidr> {
+    IpCidr::from_str(text).map_err(|e| format!("invalid prefix {}: {}", text, e).into())
+}
+
+/// Parses an AS number written with or without the `AS` prefix.
+pub fn parse_asn(text: &str) -> BoxResult<u32> {
+    let digits = text.strip_prefix("AS").or_else(|| text.strip_prefix("as")).unwrap_or(text);
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return Err(format!("invalid AS number {}", text).into());
+    }
+    digits.parse::<u32>().map_err(|_| format!("AS number out of range {}", text).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{render_with_commit, Family, OutputFormat};
+
+    fn vrp(prefix: &str, max_length: u8, asn: u32) -> Vrp {
+        Vrp { prefix: IpCidr::from_str(prefix).unwrap(), max_length, asn }
+    }
+
+    fn expected() -> Vec<Vrp> {
+        vec![vrp("172.20.0.0/24", 28, 4242420000), vrp("fd00::/48", 64, 4242420001)]
+    }
+
+    fn error(content: &str) -> String {
+        parse_roa_data(content).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn csv() {
+        let routinator = "ASN,IP Prefix,Max Length,Trust Anchor\nAS4242420000,172.20.0.0/24,28,dn42\nAS4242420001,fd00::/48,64,dn42\n";
+        assert_eq!(parse_roa_data(routinator).unwrap(), expected());
+        // Columns in another order, quoted and with CRLF line ends
+        let reordered = "\"IP Prefix\",\"Max Length\",\"ASN\"\r\n\"172.20.0.0/24\",28,\"4242420000\"\r\n\r\nfd00::/48,64,as4242420001\r\n";
+        assert_eq!(parse_roa_data(reordered).unwrap(), expected());
+        assert_eq!(parse_roa_data("AS4242420000,172.20.0.0/24,28\nAS4242420001,fd00::/48,64\n").unwrap(), expected());
+
+        assert_eq!(error("ASN,Prefix\n"), "CSV header lacks one of the columns ASN, IP Prefix and Max Length");
+        assert_eq!(error("AS4242420000,172.20.0.0/24\n"), "line 1: missing column");
+        assert_eq!(error("AS4242420000,172.20.0.0/24,x\n"), "line 1: invalid max length x");
+        assert_eq!(error("ASX,172.20.0.0/24,28\n"), "line 1: invalid AS number ASX");
+        assert_eq!(error("AS4294967296,172.20.0.0/24,28\n"), "line 1: AS number out of range AS4294967296");
+        assert!(error("AS4242420000,172.20.0.1/24,28\n").starts_with("line 1: invalid prefix 172.20.0.1/24"));
+    }
+
+    #[test]
+    fn bird() {
+        let bird2 = "# roa_wizard\n\nroute 172.20.0.0/24 max 28 as 4242420000;\nroute fd00::/48 max 64 as 4242420001;\n";
+        assert_eq!(parse_roa_data(bird2).unwrap(), expected());
+        let bird1 = "roa 172.20.0.0/24 max 28 as 4242420000;\nroa fd00::/48 max 64 as 4242420001;\n";
+        assert_eq!(parse_roa_data(bird1).unwrap(), expected());
+        assert_eq!(parse_roa_data("# no routes\n").unwrap(), []);
+
+        assert_eq!(error("route 172.20.0.0/24 max 28 as 4242420000;\nroute 172.20.1.0/24 as 4242420000;\n"),
+                   "line 2: expected 'route <prefix> max <length> as <asn>;'");
+        assert_eq!(error("route 172.20.0.0/24 max 256 as 4242420000;\n"), "line 1: invalid max length 256");
+    }
+
+    #[test]
+    fn json() {
+        let roa_wizard = r#"{"metadata":{"counts":2},"roas":[{"prefix":"172.20.0.0/24","maxLength":28,"asn":"AS4242420000"},{"prefix":"fd00::/48","maxLength":64,"asn":"4242420001"}]}"#;
+        assert_eq!(parse_roa_data(roa_wizard).unwrap(), expected());
+        let rpki_client = r#"{"roas":[{"asn":4242420000,"prefix":"172.20.0.0/24","maxLength":28,"ta":"dn42"},{"asn":4242420001,"prefix":"fd00::/48","maxLength":64,"ta":"dn42"}]}"#;
+        assert_eq!(parse_roa_data(rpki_client).unwrap(), expected());
+
+        assert_eq!(error(r#"{"vrps":[]}"#), "missing 'roas' array");
+        assert_eq!(error(r#"{"roas":[{"prefix":"172.20.0.0/24","asn":1}]}"#), "roa #1: missing or invalid maxLength");
+        assert_eq!(error(r#"{"roas":[{"prefix":"172.20.0.0/24","maxLength":28}]}"#), "roa #1: missing asn");
+        assert_eq!(error(r#"{"roas":[{"prefix":"172.20.0.0/24","maxLength":28,"asn":-1}]}"#), "roa #1: invalid asn");
+        assert!(parse_roa_data("{\"roas\":[").is_err());
+    }
+
+    #[test]
+    fn rendered_output() {
+        for format in [OutputFormat::Bird, OutputFormat::Json] {
+            let rendered = render_with_commit(&expected(), format, Family::Both, Some("0".repeat(40)));
+            let mut parsed = parse_roa_data(&rendered).unwrap();
+            parsed.sort();
+            assert_eq!(parsed, expected());
+        }
+    }
+}
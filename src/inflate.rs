@@ -0,0 +1,474 @@
+//! A small DEFLATE (RFC 1951) decoder with zlib (RFC 1950) and gzip (RFC 1952) framing.
+
+use crate::BoxResult;
+
+const MAX_BITS: usize = 15;
+
+const LENGTH_BASE: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DIST_BASE: [u16; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577];
+const DIST_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    bit_buffer: u32,
+    bit_count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        BitReader { data, position: 0, bit_buffer: 0, bit_count: 0 }
+    }
+
+    fn bits(&mut self, count: u32) -> BoxResult<u32> {
+        while self.bit_count < count {
+            let byte = *self.data.get(self.position).ok_or("unexpected end of compressed data")?;
+            self.position += 1;
+            self.bit_buffer |= (byte as u32) << self.bit_count;
+            self.bit_count += 8;
+        }
+        let value = self.bit_buffer & ((1u32 << count) - 1);
+        self.bit_buffer >>= count;
+        self.bit_count -= count;
+        Ok(value)
+    }
+
+    fn align_to_byte(&mut self) {
+        self.bit_buffer = 0;
+        self.bit_count = 0;
+    }
+}
+
+/// Canonical Huffman code with a lookup table for codes of up to `FAST_BITS` bits.
+struct Huffman {
+    counts: [u16; MAX_BITS + 1],
+    symbols: Vec<u16>,
+    fast: Vec<u16>,
+}
+
+const FAST_BITS: u32 = 9;
+const FAST_INVALID: u16 = 0xffff;
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> BoxResult<Self> {
+        let mut counts = [0u16; MAX_BITS + 1];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        let mut left: i32 = 1;
+        for count in counts.iter().skip(1) {
+            left = (left << 1) - *count as i32;
+            if left < 0 {
+                return Err("over-subscribed Huffman code".into());
+            }
+        }
+        let mut offsets = [0u16; MAX_BITS + 2];
+        for length in 1..=MAX_BITS {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+        let mut symbols = vec![0u16; lengths.len()];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+
+        // Entries hold the symbol in the low 12 bits and the code length in the high 4 bits
+        let mut fast = vec![FAST_INVALID; 1 << FAST_BITS];
+        let mut code: u32 = 0;
+        let mut index: usize = 0;
+        for length in 1..=FAST_BITS {
+            for _ in 0..counts[length as usize] {
+                let reversed = reverse_bits(code, length);
+                let mut slot = reversed as usize;
+                while slot < fast.len() {
+                    fast[slot] = symbols[index] | ((length as u16) << 12);
+                    slot += 1 << length;
+                }
+                code += 1;
+                index += 1;
+            }
+            code <<= 1;
+        }
+        Ok(Huffman { counts, symbols, fast })
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> BoxResult<u16> {
+        // Try the lookup table with whatever bits are available
+        while reader.bit_count < FAST_BITS && reader.position < reader.data.len() {
+            reader.bit_buffer |= (reader.data[reader.position] as u32) << reader.bit_count;
+            reader.position += 1;
+            reader.bit_count += 8;
+        }
+        let entry = self.fast[(reader.bit_buffer & ((1 << FAST_BITS) - 1)) as usize];
+        if entry != FAST_INVALID {
+            let length = (entry >> 12) as u32;
+            if length <= reader.bit_count {
+                reader.bit_buffer >>= length;
+                reader.bit_count -= length;
+                return Ok(entry & 0x0fff);
+            }
+        }
+
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+        for length in 1..=MAX_BITS {
+            code |= reader.bits(1)? as i32;
+            let count = self.counts[length] as i32;
+            if code - count < first {
+                return Ok(self.symbols[(index + (code - first)) as usize]);
+            }
+            index += count;
+            first += count;
+            first <<= 1;
+            code <<= 1;
+        }
+        Err("invalid Huffman code".into())
+    }
+}
+
+fn reverse_bits(mut code: u32, length: u32) -> u32 {
+    let mut result = 0;
+    for _ in 0..length {
+        result = (result << 1) | (code & 1);
+        code >>= 1;
+    }
+    result
+}
+
+/// Decompresses a raw DEFLATE stream, returning the data and the number of input bytes consumed.
+/// Decompression stops early once the output exceeds `limit` bytes, callers check the output length.
+pub fn inflate(input: &[u8], size_hint: usize, limit: usize) -> BoxResult<(Vec<u8>, usize)> {
+    let mut reader = BitReader::new(input);
+    // DEFLATE expands at most about 1032:1, a larger hint is not to be trusted
+    let mut output: Vec<u8> = Vec::with_capacity(size_hint.min(input.len().saturating_mul(1032)));
+    loop {
+        let last = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => inflate_stored(&mut reader, &mut output)?,
+            1 => {
+                let (literal, distance) = fixed_codes()?;
+                inflate_block(&mut reader, &mut output, &literal, &distance, limit)?;
+            }
+            2 => {
+                let (literal, distance) = dynamic_codes(&mut reader)?;
+                inflate_block(&mut reader, &mut output, &literal, &distance, limit)?;
+            }
+            _ => return Err("invalid DEFLATE block type".into()),
+        }
+        if last || output.len() > limit {
+            break;
+        }
+    }
+    // Unused bits of the last byte belong to the stream
+    let consumed = reader.position - (reader.bit_count / 8) as usize;
+    Ok((output, consumed))
+}
+
+fn inflate_stored(reader: &mut BitReader, output: &mut Vec<u8>) -> BoxResult<()> {
+    // The remaining bits of the current byte are discarded; whole buffered bytes are given back
+    reader.position -= (reader.bit_count / 8) as usize;
+    reader.align_to_byte();
+    let header = reader.data.get(reader.position..reader.position + 4).ok_or("unexpected end of stored block")?;
+    let length = u16::from_le_bytes([header[0], header[1]]);
+    let complement = u16::from_le_bytes([header[2], header[3]]);
+    if length != !complement {
+        return Err("stored block length mismatch".into());
+    }
+    reader.position += 4;
+    let data = reader.data.get(reader.position..reader.position + length as usize).ok_or("unexpected end of stored block")?;
+    output.extend_from_slice(data);
+    reader.position += length as usize;
+    Ok(())
+}
+
+fn fixed_codes() -> BoxResult<(Huffman, Huffman)> {
+    let mut lengths = [0u8; 288];
+    lengths[0..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..288].fill(8);
+    Ok((Huffman::new(&lengths)?, Huffman::new(&[5u8; 30])?))
+}
+
+fn dynamic_codes(reader: &mut BitReader) -> BoxResult<(Huffman, Huffman)> {
+    let literal_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_length_count = reader.bits(4)? as usize + 4;
+    if literal_count > 286 || distance_count > 30 {
+        return Err("invalid dynamic block header".into());
+    }
+
+    let mut code_lengths = [0u8; 19];
+    for &index in CODE_LENGTH_ORDER.iter().take(code_length_count) {
+        code_lengths[index] = reader.bits(3)? as u8;
+    }
+    let code_length_code = Huffman::new(&code_lengths)?;
+
+    let mut lengths = vec![0u8; literal_count + distance_count];
+    let mut index = 0;
+    while index < lengths.len() {
+        let symbol = code_length_code.decode(reader)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lengths.get(index.wrapping_sub(1)).ok_or("repeat without previous length")?;
+                (previous, 3 + reader.bits(2)? as usize)
+            }
+            17 => (0, 3 + reader.bits(3)? as usize),
+            18 => (0, 11 + reader.bits(7)? as usize),
+            _ => return Err("invalid code length symbol".into()),
+        };
+        if index + repeat > lengths.len() {
+            return Err("too many code lengths".into());
+        }
+        lengths[index..index + repeat].fill(value);
+        index += repeat;
+    }
+    if lengths[256] == 0 {
+        return Err("missing end-of-block code".into());
+    }
+    Ok((Huffman::new(&lengths[..literal_count])?, Huffman::new(&lengths[literal_count..])?))
+}
+
+fn inflate_block(reader: &mut BitReader, output: &mut Vec<u8>, literal: &Huffman, distance: &Huffman, limit: usize) -> BoxResult<()> {
+    while output.len() <= limit {
+        let symbol = literal.decode(reader)? as usize;
+        match symbol {
+            0..=255 => output.push(symbol as u8),
+            256 => return Ok(()),
+            257..=285 => {
+                let index = symbol - 257;
+                let length = LENGTH_BASE[index] as usize + reader.bits(LENGTH_EXTRA[index] as u32)? as usize;
+                let distance_symbol = distance.decode(reader)? as usize;
+                if distance_symbol >= 30 {
+                    return Err("invalid distance symbol".into());
+                }
+                let distance = DIST_BASE[distance_symbol] as usize + reader.bits(DIST_EXTRA[distance_symbol] as u32)? as usize;
+                if distance > output.len() {
+                    return Err("distance too far back".into());
+                }
+                let start = output.len() - distance;
+                if distance >= length {
+                    output.extend_from_within(start..start + length);
+                } else {
+                    for i in 0..length {
+                        output.push(output[start + i]);
+                    }
+                }
+            }
+            _ => return Err("invalid literal/length symbol".into()),
+        }
+    }
+    Ok(())
+}
+
+/// Decompresses a zlib stream, returning the data and the number of input bytes consumed.
+/// See `inflate` for `limit`.
+pub fn zlib_decompress(input: &[u8], size_hint: usize, limit: usize) -> BoxResult<(Vec<u8>, usize)> {
+    if input.len() < 2 || input[0] & 0x0f != 8 || !((input[0] as u16) << 8 | input[1] as u16).is_multiple_of(31) {
+        return Err("invalid zlib header".into());
+    }
+    if input[1] & 0x20 != 0 {
+        return Err("zlib preset dictionaries are not supported".into());
+    }
+    let (output, consumed) = inflate(&input[2..], size_hint, limit)?;
+    // Adler-32 checksum follows the compressed data
+    Ok((output, 2 + consumed + 4))
+}
+
+/// Decompresses gzip data. Concatenated members are decompressed into one output, which must
+/// not exceed `limit` bytes.
+pub fn gunzip(input: &[u8], limit: usize) -> BoxResult<Vec<u8>> {
+    let mut output: Vec<u8> = Vec::new();
+    let mut position = 0;
+    while position < input.len() {
+        let member = &input[position..];
+        if member.len() < 10 || member[0] != 0x1f || member[1] != 0x8b || member[2] != 8 {
+            return Err("invalid gzip header".into());
+        }
+        let flags = member[3];
+        let mut offset = 10;
+        if flags & 0x04 != 0 {
+            let length = member.get(offset..offset + 2).ok_or("truncated gzip header")?;
+            offset += 2 + u16::from_le_bytes([length[0], length[1]]) as usize;
+        }
+        // File name and comment are zero terminated
+        for flag in [0x08, 0x10] {
+            if flags & flag != 0 {
+                let end = member.get(offset..).and_then(|m| m.iter().position(|b| *b == 0)).ok_or("truncated gzip header")?;
+                offset += end + 1;
+            }
+        }
+        if flags & 0x02 != 0 {
+            offset += 2;
+        }
+        let data = member.get(offset..).ok_or("truncated gzip header")?;
+
+        let (data, consumed) = inflate(data, 0, limit - output.len())?;
+        if data.len() > limit - output.len() {
+            return Err(format!("decompressed data exceeds the size limit of {} bytes", limit).into());
+        }
+        let trailer = member.get(offset + consumed..offset + consumed + 8).ok_or("truncated gzip data")?;
+        let crc = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+        let size = u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]);
+        if crc32(&data) != crc || data.len() as u32 != size {
+            return Err("gzip checksum mismatch".into());
+        }
+        output.extend_from_slice(&data);
+        position += offset + consumed + 8;
+    }
+    Ok(output)
+}
+
+pub(crate) fn crc32(data: &[u8]) -> u32 {
+    let mut table = [0u32; 256];
+    for (i, entry) in table.iter_mut().enumerate() {
+        let mut value = i as u32;
+        for _ in 0..8 {
+            value = if value & 1 != 0 { 0xedb88320 ^ (value >> 1) } else { value >> 1 };
+        }
+        *entry = value;
+    }
+    let mut crc = !0u32;
+    for byte in data {
+        crc = table[((crc ^ *byte as u32) & 0xff) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &[u8] = b"route: 172.20.0.0/24\n";
+    /// `TEXT` compressed with zlib level 0, a single stored block.
+    const STORED: [u8; 32] = [
+        0x78, 0x01, 0x01, 0x15, 0x00, 0xea, 0xff, 0x72, 0x6f, 0x75, 0x74, 0x65, 0x3a, 0x20, 0x31, 0x37,
+        0x32, 0x2e, 0x32, 0x30, 0x2e, 0x30, 0x2e, 0x30, 0x2f, 0x32, 0x34, 0x0a, 0x43, 0x1e, 0x05, 0x0f,
+    ];
+    /// `TEXT` compressed with zlib level 9, a block with the fixed Huffman codes.
+    const FIXED: [u8; 27] = [
+        0x78, 0xda, 0x2b, 0xca, 0x2f, 0x2d, 0x49, 0xb5, 0x52, 0x30, 0x34, 0x37, 0xd2, 0x33, 0x32, 0xd0,
+        0x03, 0x42, 0x7d, 0x23, 0x13, 0x2e, 0x00, 0x43, 0x1e, 0x05, 0x0f,
+    ];
+    /// `route_objects()` compressed with zlib level 9, a block with dynamic Huffman codes.
+    const DYNAMIC: [u8; 113] = [
+        0x78, 0xda, 0x9d, 0xcc, 0x31, 0x0a, 0x80, 0x30, 0x0c, 0x85, 0xe1, 0xbd, 0xa7, 0xf0, 0x04, 0xda,
+        0xa6, 0xa9, 0x55, 0x37, 0xc1, 0xd9, 0xc5, 0x23, 0x88, 0x48, 0x17, 0x0b, 0xd1, 0xde, 0xdf, 0x3a,
+        0x56, 0x28, 0x84, 0xbc, 0x7f, 0x7d, 0x7c, 0x14, 0xd3, 0x73, 0x4c, 0x4d, 0x31, 0xe3, 0xa1, 0x05,
+        0xdd, 0xe6, 0x3a, 0x40, 0x15, 0x29, 0x9c, 0xe1, 0x2a, 0x2f, 0xf3, 0x86, 0xf0, 0xa5, 0xf3, 0xd4,
+        0x1d, 0x13, 0xed, 0x3f, 0x63, 0x59, 0x11, 0x14, 0xd5, 0x6d, 0xc3, 0xb2, 0xbd, 0xc8, 0x06, 0x8e,
+        0x6d, 0x50, 0x64, 0x5b, 0x8e, 0x0d, 0x46, 0x64, 0x23, 0xcb, 0x1e, 0x44, 0xb6, 0xe3, 0xd8, 0xd6,
+        0x89, 0xec, 0x9e, 0x63, 0x67, 0x42, 0x62, 0x7b, 0x96, 0x3d, 0xd6, 0xed, 0x17, 0xb5, 0xe8, 0x98,
+        0xb9,
+    ];
+    /// `TEXT` compressed with gzip.
+    const GZIP: [u8; 39] = [
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0x2b, 0xca, 0x2f, 0x2d, 0x49, 0xb5,
+        0x52, 0x30, 0x34, 0x37, 0xd2, 0x33, 0x32, 0xd0, 0x03, 0x42, 0x7d, 0x23, 0x13, 0x2e, 0x00, 0xbf,
+        0x66, 0x72, 0x2e, 0x15, 0x00, 0x00, 0x00,
+    ];
+
+    fn route_objects() -> Vec<u8> {
+        (0..8).flat_map(|i| format!("route:              172.20.{}.0/24\norigin:             AS424242{:04}\nsource:             DN42\n", i, i * 7).into_bytes()).collect()
+    }
+
+    #[test]
+    fn stored_block() {
+        assert_eq!(zlib_decompress(&STORED, 0, usize::MAX).unwrap(), (TEXT.to_vec(), STORED.len()));
+    }
+
+    #[test]
+    fn fixed_huffman_block() {
+        assert_eq!(zlib_decompress(&FIXED, TEXT.len(), usize::MAX).unwrap(), (TEXT.to_vec(), FIXED.len()));
+    }
+
+    #[test]
+    fn dynamic_huffman_block() {
+        assert_eq!(zlib_decompress(&DYNAMIC, 0, usize::MAX).unwrap(), (route_objects(), DYNAMIC.len()));
+    }
+
+    #[test]
+    fn trailing_data_is_not_consumed() {
+        let mut input = FIXED.to_vec();
+        input.extend_from_slice(b"next object");
+        assert_eq!(zlib_decompress(&input, 0, usize::MAX).unwrap().1, FIXED.len());
+    }
+
+    #[test]
+    fn size_hint_is_not_trusted() {
+        assert_eq!(zlib_decompress(&FIXED, usize::MAX, usize::MAX).unwrap().0, TEXT);
+    }
+
+    #[test]
+    fn limit() {
+        let (output, _) = zlib_decompress(&DYNAMIC, 0, 100).unwrap();
+        assert!(output.len() > 100 && output.len() < route_objects().len());
+        assert_eq!(zlib_decompress(&DYNAMIC, 0, route_objects().len()).unwrap().0, route_objects());
+        assert_eq!(zlib_decompress(&STORED, 0, 1).unwrap().0, TEXT);
+    }
+
+    #[test]
+    fn gzip() {
+        assert_eq!(gunzip(&GZIP, usize::MAX).unwrap(), TEXT);
+        let twice = [GZIP, GZIP].concat();
+        assert_eq!(gunzip(&twice, usize::MAX).unwrap(), [TEXT, TEXT].concat());
+    }
+
+    #[test]
+    fn gzip_limit() {
+        assert_eq!(gunzip(&GZIP, TEXT.len()).unwrap(), TEXT);
+        let error = gunzip(&GZIP, TEXT.len() - 1).unwrap_err().to_string();
+        assert_eq!(error, format!("decompressed data exceeds the size limit of {} bytes", TEXT.len() - 1));
+        // The limit applies to all members together
+        assert!(gunzip(&[GZIP, GZIP].concat(), TEXT.len() * 2 - 1).is_err());
+    }
+
+    #[test]
+    fn gzip_checksum_mismatch() {
+        let mut input = GZIP;
+        input[31] ^= 1;
+        assert!(gunzip(&input, usize::MAX).is_err());
+    }
+
+    #[test]
+    fn invalid_headers() {
+        assert!(zlib_decompress(&[], 0, usize::MAX).is_err());
+        assert!(zlib_decompress(&[0x78, 0x00], 0, usize::MAX).is_err());
+        assert!(zlib_decompress(&GZIP, 0, usize::MAX).is_err());
+        assert!(gunzip(&FIXED, usize::MAX).is_err());
+        // Block type 3 is reserved
+        assert!(inflate(&[0x07], 0, usize::MAX).is_err());
+    }
+
+    #[test]
+    fn truncated_streams() {
+        for input in [&STORED[..], &FIXED[..], &DYNAMIC[..]] {
+            // The Adler-32 checksum is not verified
+            for length in 0..input.len() - 4 {
+                assert!(zlib_decompress(&input[..length], 0, usize::MAX).is_err(), "truncated to {} bytes", length);
+            }
+        }
+        for length in 1..GZIP.len() {
+            assert!(gunzip(&GZIP[..length], usize::MAX).is_err(), "truncated to {} bytes", length);
+        }
+    }
+
+    #[test]
+    fn corrupt_streams_do_not_panic() {
+        for input in [&STORED[..], &FIXED[..], &DYNAMIC[..]] {
+            for position in 2..input.len() {
+                for bit in 0..8 {
+                    let mut corrupt = input.to_vec();
+                    corrupt[position] ^= 1 << bit;
+                    let _ = zlib_decompress(&corrupt, 0, usize::MAX);
+                }
+            }
+        }
+    }
+}
@@ -1,86 +1,398 @@
 mod parse;
 mod output;
+mod vrp;
+mod inflate;
+mod history;
+mod tar;
+mod mrt;
+pub mod announcements;
+pub mod baseline;
+pub mod blame;
+pub mod config;
+pub mod conflicts;
+pub mod date;
+pub mod diff;
+pub mod git;
+pub mod import;
+pub mod maxlength;
+pub mod merge;
+pub mod publish;
+pub mod registry;
+pub mod rpsl;
+pub mod run;
+pub mod safety;
+pub mod slurm;
+pub mod snapshot;
+pub mod stats;
+pub mod suggest;
+pub mod source;
+pub mod watch;
 
+use std::collections::HashMap;
 use std::error::Error;
+use std::fmt;
 use std::path::{Path, PathBuf};
 use std::process::exit;
+use std::str::FromStr;
 use crate::output::{output_bird, output_json};
-use crate::parse::{evaluate_filter_set, read_filter_set, read_route_objects, RouteObject};
+use crate::parse::{evaluate_filter_set, read_filter_set, read_filter_set_file, read_route_objects, FilterSet, RouteObject};
+use crate::slurm::Slurm;
+use crate::source::{DirectorySource, RegistrySource};
+use cidr_utils::cidr::IpCidr;
 use std::{io, thread};
 use std::io::Write;
-use std::thread::JoinHandle;
+
+pub use crate::vrp::{prefix_string, validate, ValidationState, Vrp};
 
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 pub const PACKAGE_NAME: &str = env!("CARGO_PKG_NAME");
-type Warnings = Vec<String>;
+
+pub const EXIT_REGISTRY_ERROR: i32 = 1;
+pub const EXIT_USAGE: i32 = 2;
+pub const EXIT_IO_ERROR: i32 = 3;
+pub const EXIT_STRICT_FAILURE: i32 = 4;
+pub const EXIT_HOOK_FAILURE: i32 = 5;
+pub const EXIT_LOCKED: i32 = 6;
+pub const EXIT_SAFETY_CHECK: i32 = 7;
+pub const EXIT_DIFFERENCES: i32 = 8;
+
+type Warnings = Vec<Warning>;
 type RouteObjectsWithWarnings = (Vec<RouteObject>, Warnings);
 type BoxResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
 
+/// A problem in the registry that does not prevent generating output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Warning {
+    /// Stable identifier of the kind of problem, e.g. `invalid-origin`.
+    pub code: &'static str,
+    /// The file or entry the problem was found in, e.g. `172.20.0.0_24` for route objects.
+    pub object: String,
+    pub message: String,
+}
+
+impl Warning {
+    pub fn new(code: &'static str, object: impl Into<String>, message: impl Into<String>) -> Self {
+        Warning { code, object: object.into(), message: message.into() }
+    }
+
+    /// A warning about the registry file `file`.
+    pub fn in_file(code: &'static str, file: &str, error: impl fmt::Display) -> Self {
+        Warning::new(code, file, format!("Error in file: {}: {}", file, error))
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl Error for Warning {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Family {
+    V4,
+    V6,
+    Both,
+}
+
+impl Family {
+    pub fn contains(self, prefix: &IpCidr) -> bool {
+        match self {
+            Family::V4 => prefix.is_ipv4(),
+            Family::V6 => prefix.is_ipv6(),
+            Family::Both => true,
+        }
+    }
+
+    /// The smallest family covering both `self` and `other`.
+    pub fn union(self, other: Family) -> Family {
+        if self == other { self } else { Family::Both }
+    }
+}
+
+impl FromStr for Family {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "v4" | "4" => Ok(Family::V4),
+            "v6" | "6" => Ok(Family::V6),
+            "both" | "all" => Ok(Family::Both),
+            _ => Err(format!("unknown address family '{}' (expected v4, v6 or both)", s)),
+        }
+    }
+}
+
+impl fmt::Display for Family {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Family::V4 => write!(f, "v4"),
+            Family::V6 => write!(f, "v6"),
+            Family::Both => write!(f, "both"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Bird,
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bird" | "bird2" => Ok(OutputFormat::Bird),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(format!("unknown output format '{}' (expected bird or json)", s)),
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutputFormat::Bird => write!(f, "bird"),
+            OutputFormat::Json => write!(f, "json"),
+        }
+    }
+}
+
+/// Format of reports such as `diff` that are meant for people as well as tools.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for ReportFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(ReportFormat::Text),
+            "json" => Ok(ReportFormat::Json),
+            _ => Err(format!("unknown report format '{}' (expected text or json)", s)),
+        }
+    }
+}
+
+/// Filter set files to use instead of `data/filter.txt` and `data/filter6.txt`.
+#[derive(Debug, Clone, Default)]
+pub struct FilterOverrides {
+    pub filter_v4: Option<PathBuf>,
+    pub filter_v6: Option<PathBuf>,
+    /// Filter set files for the route objects of specific sources.
+    pub source_filters: Vec<SourceFilterFiles>,
+    /// Route objects of sources that are not selected are ignored.
+    pub sources: SourceSelection,
+}
+
+/// Filter set files that apply to the route objects with a `source:` of `source`.
+#[derive(Debug, Clone)]
+pub struct SourceFilterFiles {
+    pub source: String,
+    pub filter_v4: Option<PathBuf>,
+    pub filter_v6: Option<PathBuf>,
+}
+
+/// Selects route objects by their `source:` attribute. Source names are compared case-insensitively.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceSelection {
+    /// If not empty, only these sources are selected.
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+impl SourceSelection {
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    /// Objects without a source are only selected if no sources are explicitly included.
+    pub fn matches(&self, source: Option<&str>) -> bool {
+        let listed = |list: &[String]| source.is_some_and(|s| list.iter().any(|l| l.eq_ignore_ascii_case(s)));
+        (self.include.is_empty() || listed(&self.include)) && !listed(&self.exclude)
+    }
+}
+
 pub fn generate_bird(base_path: impl AsRef<Path>, is_v6: bool) -> BoxResult<(String, Warnings)> {
-    let result = get_roa_objects(is_v6, base_path.as_ref().into());
-    let (objects, warnings) = result?;
-    Ok((output_bird(objects, base_path.as_ref()), warnings))
+    let family = if is_v6 { Family::V6 } else { Family::V4 };
+    generate(&DirectorySource::new(base_path), OutputFormat::Bird, family, &FilterOverrides::default(), &[])
 }
 
 pub fn generate_json(base_path: impl AsRef<Path>) -> BoxResult<(String, Warnings)> {
-    let handler_v4 = process_handler(false, base_path.as_ref().into());
-    let handler_v6 = process_handler(true, base_path.as_ref().into());
-    let f_result_v4 = handler_v4.join().expect("thread failed");
-    let f_result_v6 = handler_v6.join().expect("thread failed");
+    generate(&DirectorySource::new(base_path), OutputFormat::Json, Family::Both, &FilterOverrides::default(), &[])
+}
 
-    let (mut result_v4, mut warnings_v4) = f_result_v4?;
-    let (mut result_v6, mut warnings_v6) = f_result_v6?;
+pub fn generate(source: &dyn RegistrySource, format: OutputFormat, family: Family, filters: &FilterOverrides, slurm_files: &[PathBuf]) -> BoxResult<(String, Warnings)> {
+    let (vrps, warnings) = get_vrps(source, family, filters, slurm_files)?;
+    Ok((render(&vrps, format, family, source), warnings))
+}
 
-    result_v4.append(result_v6.as_mut());
-    warnings_v4.append(warnings_v6.as_mut());
-    Ok((output_json(result_v4), warnings_v4))
+/// Reads the registry once and returns the resulting VRPs with any SLURM exceptions applied.
+pub fn get_vrps(source: &dyn RegistrySource, family: Family, filters: &FilterOverrides, slurm_files: &[PathBuf]) -> BoxResult<(Vec<Vrp>, Warnings)> {
+    get_vrps_and_objects(source, family, filters, slurm_files).map(|(vrps, _, warnings)| (vrps, warnings))
 }
 
-pub fn get_roa_objects(is_v6: bool, base_path: PathBuf) -> BoxResult<RouteObjectsWithWarnings> {
-    let route_directory: PathBuf;
-    let filter_txt: PathBuf;
-    match is_v6 {
-        true => {
-            let route6_directory = base_path.join("data/route6/");
-            let filter6_txt = base_path.join("data/filter6.txt");
-            route_directory = route6_directory;
-            filter_txt = filter6_txt;
-        }
-        false => {
-            let route4_directory = base_path.join("data/route/");
-            let filter4_txt = base_path.join("data/filter.txt");
-            route_directory = route4_directory;
-            filter_txt = filter4_txt;
+/// Like `get_vrps`, but also returns the route objects that passed the filter set.
+fn get_vrps_and_objects(source: &dyn RegistrySource, family: Family, filters: &FilterOverrides, slurm_files: &[PathBuf]) -> BoxResult<(Vec<Vrp>, Vec<RouteObject>, Warnings)> {
+    let slurm = Slurm::read_files(slurm_files)?;
+    let (objects, warnings) = get_roa_objects_for_family(source, family, filters)?;
+    let mut vrps: Vec<Vrp> = objects.iter().flat_map(RouteObject::get_vrps).collect();
+    if !slurm.is_empty() {
+        slurm.apply(&mut vrps);
+        vrps.retain(|v| family.contains(&v.prefix));
+    }
+    Ok((vrps, objects, warnings))
+}
+
+/// Renders the VRPs of the given family in the requested output format.
+pub fn render(vrps: &[Vrp], format: OutputFormat, family: Family, source: &dyn RegistrySource) -> String {
+    render_with_commit(vrps, format, family, source.commit_hash())
+}
+
+/// Like `render`, for VRPs that do not come directly from a registry source.
+pub fn render_with_commit(vrps: &[Vrp], format: OutputFormat, family: Family, commit_hash: Option<String>) -> String {
+    render_with_sources(vrps, format, family, commit_hash, None)
+}
+
+/// Like `render_with_commit`, naming the source of each VRP in the `ta` field of the JSON output.
+pub fn render_with_sources(vrps: &[Vrp], format: OutputFormat, family: Family, commit_hash: Option<String>, sources: Option<&HashMap<Vrp, String>>) -> String {
+    let selected: Vec<Vrp> = vrps.iter().filter(|v| family.contains(&v.prefix)).cloned().collect();
+    match format {
+        OutputFormat::Bird => output_bird(&selected, commit_hash),
+        OutputFormat::Json => output_json(&selected, sources),
+    }
+}
+
+pub fn get_roa_objects_for_family(source: &dyn RegistrySource, family: Family, filters: &FilterOverrides) -> BoxResult<RouteObjectsWithWarnings> {
+    match family {
+        Family::V4 => get_roa_objects_with_filter(false, source, filters),
+        Family::V6 => get_roa_objects_with_filter(true, source, filters),
+        Family::Both => {
+            let (f_result_v4, f_result_v6) = thread::scope(|scope| {
+                let handler_v4 = scope.spawn(|| get_roa_objects_with_filter(false, source, filters));
+                let handler_v6 = scope.spawn(|| get_roa_objects_with_filter(true, source, filters));
+                (handler_v4.join().expect("thread failed"), handler_v6.join().expect("thread failed"))
+            });
+
+            let (mut result_v4, mut warnings_v4) = f_result_v4?;
+            let (mut result_v6, mut warnings_v6) = f_result_v6?;
+
+            result_v4.append(result_v6.as_mut());
+            warnings_v4.append(warnings_v6.as_mut());
+            Ok((result_v4, warnings_v4))
         }
     }
-    let (mut objects, mut warnings) = read_route_objects(route_directory, is_v6)?;
-    let (filters, mut warnings_filter) = read_filter_set(&filter_txt)?;
-    warnings.append(&mut warnings_filter);
+}
+
+pub fn get_roa_objects(is_v6: bool, base_path: PathBuf) -> BoxResult<RouteObjectsWithWarnings> {
+    get_roa_objects_with_filter(is_v6, &DirectorySource::new(base_path), &FilterOverrides::default())
+}
 
-    evaluate_filter_set(objects.as_mut(), filters.as_ref());
+pub fn get_roa_objects_with_filter(is_v6: bool, source: &dyn RegistrySource, filter_overrides: &FilterOverrides) -> BoxResult<RouteObjectsWithWarnings> {
+    let route_directory = if is_v6 { "data/route6" } else { "data/route" };
+    let (mut objects, mut warnings) = read_route_objects(source, route_directory, is_v6)?;
+    objects.retain(|o| filter_overrides.sources.matches(o.source.as_deref()));
+    warnings.append(&mut apply_filter_sets(&mut objects, is_v6, source, filter_overrides)?);
     Ok((objects, warnings))
 }
 
-fn process_handler(is_v6: bool, base_path: PathBuf) -> JoinHandle<BoxResult<RouteObjectsWithWarnings>> {
-    thread::spawn(move || {
-        get_roa_objects(is_v6, base_path)
-    })
+/// Removes the route objects of one address family the filter set does not permit and applies its max lengths.
+pub(crate) fn apply_filter_sets(objects: &mut Vec<RouteObject>, is_v6: bool, source: &dyn RegistrySource, filter_overrides: &FilterOverrides) -> BoxResult<Warnings> {
+    let filter_txt = if is_v6 { "data/filter6.txt" } else { "data/filter.txt" };
+    let mut warnings: Warnings = Vec::new();
+    let filter_override = if is_v6 { &filter_overrides.filter_v6 } else { &filter_overrides.filter_v4 };
+    let (filters, mut warnings_filter) = match filter_override {
+        Some(file) => read_filter_set_file(file)?,
+        None => read_filter_set(source, filter_txt)?,
+    };
+    warnings.append(&mut warnings_filter);
+    if filters.is_empty() {
+        let name = filter_override.as_ref().map_or_else(|| source.describe(filter_txt), |f| f.display().to_string());
+        return Err(format!("Filter set file {} contains no valid entries", name).into());
+    }
+
+    let source_filters = read_source_filter_sets(filter_overrides, is_v6, &mut warnings)?;
+    evaluate_filter_set(objects, filters.as_ref(), &source_filters);
+    Ok(warnings)
+}
+
+/// Reads the filter set files of `filters.source_filters` for one address family.
+fn read_source_filter_sets(filters: &FilterOverrides, is_v6: bool, warnings: &mut Warnings) -> BoxResult<Vec<(String, Vec<FilterSet>)>> {
+    let mut result = Vec::new();
+    for source_filter in &filters.source_filters {
+        let Some(file) = (if is_v6 { &source_filter.filter_v6 } else { &source_filter.filter_v4 }) else {
+            continue;
+        };
+        let (filters, mut warnings_filter) = read_filter_set_file(file)?;
+        warnings.append(&mut warnings_filter);
+        if filters.is_empty() {
+            return Err(format!("Filter set file {} contains no valid entries", file.display()).into());
+        }
+        result.push((source_filter.source.clone(), filters));
+    }
+    Ok(result)
+}
+
+/// Wraps an I/O error with context while keeping it recognisable as an I/O error.
+fn io_error(err: io::Error, context: String) -> io::Error {
+    io::Error::new(err.kind(), format!("{}: {}", context, err))
+}
+
+pub fn is_io_error(err: &(dyn Error + Send + Sync + 'static)) -> bool {
+    err.downcast_ref::<io::Error>().is_some()
+}
+
+pub fn exit_code_for_error(err: &(dyn Error + Send + Sync + 'static)) -> i32 {
+    if is_io_error(err) {
+        EXIT_IO_ERROR
+    } else {
+        EXIT_REGISTRY_ERROR
+    }
+}
+
+/// Atomically replaces the destination file with the output, or writes it to stdout.
+pub fn write_output(destination: Option<&Path>, output: &str) -> io::Result<()> {
+    match destination {
+        Some(path) => publish::replace(path, output),
+        None => write!(io::stdout(), "{}", output),
+    }
 }
 
 pub fn check_and_output(result: BoxResult<(String, Warnings)>, strict: bool) {
-    if let Err(err) = result {
+    check_and_write(result, strict, None, false);
+}
+
+/// Prints warnings and publishes the output to the given file or writes it to stdout,
+/// exiting with the matching exit code on failure. Returns whether the output file changed.
+pub fn check_and_write(result: BoxResult<(String, Warnings)>, strict: bool, destination: Option<&Path>, quiet: bool) -> bool {
+    try_check_and_write(result, strict, destination, quiet).unwrap_or_else(|code| exit(code))
+}
+
+/// Like `check_and_write`, but returns the exit code instead of exiting.
+pub fn try_check_and_write(result: BoxResult<(String, Warnings)>, strict: bool, destination: Option<&Path>, quiet: bool) -> Result<bool, i32> {
+    let (output, warnings) = result.map_err(|err| {
         eprintln!("Error: {}", err);
-        exit(1)
-    }
-    let (output, warnings) = result.unwrap();
-    let mut had_warning: bool = false;
-    for warning in warnings {
-        eprintln!("Warning: {}", warning);
-        had_warning = true;
+        exit_code_for_error(err.as_ref())
+    })?;
+    let had_warning = !warnings.is_empty();
+    if !quiet {
+        for warning in warnings {
+            eprintln!("Warning: {}", warning);
+        }
     }
     if strict && had_warning {
         eprintln!("Warnings occurred and strict mode is enabled");
-        exit(1)
+        return Err(EXIT_STRICT_FAILURE);
     }
-    write!(io::stdout(), "{}", output).ok();
+    let result = match destination {
+        Some(path) => publish::publish(path, &output),
+        None => write_output(None, &output).map(|_| false),
+    };
+    result.map_err(|err| {
+        eprintln!("Error: {}", err);
+        EXIT_IO_ERROR
+    })
 }
@@ -0,0 +1,218 @@
+//! Lint for VRPs whose max length authorises more-specifics that are not announced, which
+//! enables forged-origin sub-prefix hijacks (RFC 9319).
+
+use std::collections::{BTreeMap, BTreeSet};
+use cidr_utils::cidr::IpCidr;
+use json::JsonValue;
+use crate::announcements::Announcement;
+use crate::diff::VrpSet;
+use crate::vrp::{prefix_covers, prefix_string, Vrp};
+use crate::ReportFormat;
+
+/// A VRP that authorises prefixes its origin does not announce.
+#[derive(Debug, Clone)]
+pub struct LooseVrp {
+    pub vrp: Vrp,
+    /// The prefixes within the VRP announced by its origin.
+    pub announced: Vec<IpCidr>,
+    /// The smallest set of VRPs that still authorises the prefix of the VRP and all announcements.
+    pub suggested: Vec<Vrp>,
+}
+
+/// Loose VRPs grouped by the maintainers of their route objects.
+#[derive(Debug, Default)]
+pub struct MaxLengthReport {
+    pub vrp_count: usize,
+    pub groups: BTreeMap<Vec<String>, Vec<LooseVrp>>,
+}
+
+impl MaxLengthReport {
+    pub fn new(set: &VrpSet, announcements: &[Announcement]) -> Self {
+        let mut groups: BTreeMap<Vec<String>, Vec<LooseVrp>> = BTreeMap::new();
+        let vrps: BTreeSet<&Vrp> = set.vrps.iter().collect();
+        for vrp in &vrps {
+            // AS0 VRPs authorise nothing, their max length does not matter
+            if vrp.asn == 0 || vrp.max_length <= vrp.prefix.network_length() {
+                continue;
+            }
+            let announced: BTreeSet<IpCidr> = announcements.iter()
+                .filter(|a| a.origin == vrp.asn && prefix_covers(&vrp.prefix, &a.prefix) && a.prefix.network_length() <= vrp.max_length)
+                .map(|a| a.prefix)
+                .collect();
+            let mut prefixes = announced.clone();
+            prefixes.insert(vrp.prefix);
+            let suggested = minimal_vrps(&prefixes, vrp.max_length, vrp.asn);
+            if suggested.as_slice() != [(*vrp).clone()] {
+                let loose = LooseVrp { vrp: (*vrp).clone(), announced: announced.into_iter().collect(), suggested };
+                groups.entry(set.maintainers(&vrp.prefix).to_vec()).or_default().push(loose);
+            }
+        }
+        MaxLengthReport { vrp_count: vrps.len(), groups }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    pub fn loose_count(&self) -> usize {
+        self.groups.values().map(Vec::len).sum()
+    }
+
+    /// The VRPs to replace the loose VRPs of a maintainer with.
+    pub fn suggested(loose: &[LooseVrp]) -> Vec<Vrp> {
+        let suggested: BTreeSet<&Vrp> = loose.iter().flat_map(|l| &l.suggested).collect();
+        suggested.into_iter().cloned().collect()
+    }
+
+    pub fn render(&self, format: ReportFormat) -> String {
+        match format {
+            ReportFormat::Text => self.render_text(),
+            ReportFormat::Json => self.render_json(),
+        }
+    }
+
+    fn render_text(&self) -> String {
+        let mut result = format!("{} of {} VRPs authorise more-specifics that are not announced (RFC 9319)\n",
+                                 self.loose_count(), self.vrp_count);
+        for (maintainers, loose) in &self.groups {
+            result.push('\n');
+            result.push_str(&match maintainers.is_empty() {
+                true => "(no maintainer)\n".to_owned(),
+                false => format!("{}\n", maintainers.join(", ")),
+            });
+            for entry in loose {
+                let announced: Vec<String> = entry.announced.iter().map(prefix_string).collect();
+                result.push_str(&format!("  {}\n", entry.vrp));
+                result.push_str(&match announced.is_empty() {
+                    true => "    announced: nothing\n".to_owned(),
+                    false => format!("    announced: {}\n", announced.join(", ")),
+                });
+            }
+            result.push_str("  suggested:\n");
+            for vrp in Self::suggested(loose) {
+                result.push_str(&format!("    {}\n", vrp));
+            }
+        }
+        result
+    }
+
+    fn render_json(&self) -> String {
+        let mut top = JsonValue::new_object();
+        let mut summary = JsonValue::new_object();
+        summary["vrps"] = self.vrp_count.into();
+        summary["loose"] = self.loose_count().into();
+        top["summary"] = summary;
+
+        let mut groups = JsonValue::new_array();
+        for (maintainers, loose) in &self.groups {
+            let mut group = JsonValue::new_object();
+            group["maintainers"] = maintainers.clone().into();
+            group["vrps"] = loose.iter().map(|entry| {
+                let mut data = entry.vrp.get_json_object();
+                data["announced"] = entry.announced.iter().map(prefix_string).collect::<Vec<String>>().into();
+                data
+            }).collect::<Vec<JsonValue>>().into();
+            group["suggested"] = Self::suggested(loose).iter().map(Vrp::get_json_object).collect::<Vec<JsonValue>>().into();
+            groups.push(group).expect("Error converting data to JSON");
+        }
+        top["groups"] = groups;
+        top.dump()
+    }
+}
+
+/// Few VRPs for `asn` that authorise exactly `prefixes`. A max length is only used where every
+/// more-specific up to it is in the set, at most `max_length`.
+fn minimal_vrps(prefixes: &BTreeSet<IpCidr>, max_length: u8, asn: u32) -> Vec<Vrp> {
+    let mut by_length: Vec<&IpCidr> = prefixes.iter().collect();
+    by_length.sort_by_key(|p| p.network_length());
+    let mut result: Vec<Vrp> = Vec::new();
+    for prefix in by_length {
+        let mut length = prefix.network_length();
+        // All 2^depth more-specifics of the next length must be in the set
+        while length < max_length {
+            let depth = (length + 1 - prefix.network_length()) as u32;
+            if depth >= usize::BITS || 1usize << depth > prefixes.len() {
+                break;
+            }
+            let count = prefixes.iter().filter(|p| p.network_length() == length + 1 && prefix_covers(prefix, p)).count();
+            if count != 1usize << depth {
+                break;
+            }
+            length += 1;
+        }
+        if !result.iter().any(|v| prefix_covers(&v.prefix, prefix) && length <= v.max_length) {
+            result.push(Vrp { prefix: *prefix, max_length: length, asn });
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use crate::announcements::parse_announcements;
+    use crate::source::MemorySource;
+    use crate::{Family, FilterOverrides};
+
+    fn vrp(prefix: &str, max_length: u8, asn: u32) -> Vrp {
+        Vrp { prefix: IpCidr::from_str(prefix).unwrap(), max_length, asn }
+    }
+
+    fn report(announcements: &str) -> MaxLengthReport {
+        let mut source = MemorySource::new();
+        source.insert("data/filter.txt", "1 permit 172.20.0.0/14 16 29\n");
+        let route = |prefix: &str, origin: &str, max_length: u8, maintainer: &str| format!(
+            "route: {}\norigin: {}\nmax-length: {}\nmnt-by: {}\n", prefix, origin, max_length, maintainer);
+        source.insert("data/route/172.20.0.0_24", route("172.20.0.0/24", "AS4242420001", 26, "FOO-MNT"));
+        source.insert("data/route/172.20.1.0_24", route("172.20.1.0/24", "AS4242420002", 24, "FOO-MNT"));
+        source.insert("data/route/172.20.2.0_24", route("172.20.2.0/24", "AS4242420003", 25, "BAR-MNT"));
+        source.insert("data/route/172.20.3.0_24", route("172.20.3.0/24", "AS0", 28, "BAR-MNT"));
+        let (set, _) = VrpSet::read(&source, Family::V4, &FilterOverrides::default(), &[]).unwrap();
+        MaxLengthReport::new(&set, &parse_announcements(announcements.as_bytes()).unwrap())
+    }
+
+    #[test]
+    fn loose_vrps() {
+        let report = report("172.20.0.0/24 AS4242420001\n172.20.0.0/25 AS4242420001\n172.20.0.128/25 AS4242420001\n\
+            172.20.0.64/26 AS4242420001\n172.20.0.0/26 AS4242420009\n\
+            172.20.2.0/24 AS4242420003\n172.20.2.0/25 AS4242420003\n172.20.2.128/25 AS4242420003\n");
+        assert_eq!(report.vrp_count, 4);
+        assert_eq!(report.loose_count(), 1);
+        assert_eq!(report.groups.keys().collect::<Vec<_>>(), [&vec!["FOO-MNT".to_owned()]]);
+        assert_eq!(report.render(ReportFormat::Text), "1 of 4 VRPs authorise more-specifics that are not announced (RFC 9319)\n\n\
+            FOO-MNT\n\
+            \x20 172.20.0.0/24 max 26 as AS4242420001\n\
+            \x20   announced: 172.20.0.0/24, 172.20.0.0/25, 172.20.0.64/26, 172.20.0.128/25\n\
+            \x20 suggested:\n\
+            \x20   172.20.0.0/24 max 25 as AS4242420001\n\
+            \x20   172.20.0.64/26 max 26 as AS4242420001\n");
+
+        let json = json::parse(&report.render(ReportFormat::Json)).unwrap();
+        assert_eq!(json["summary"]["loose"], 1);
+        assert_eq!(json["groups"][0]["maintainers"][0], "FOO-MNT");
+        assert_eq!(json["groups"][0]["vrps"][0]["announced"].len(), 4);
+        assert_eq!(json["groups"][0]["suggested"].len(), 2);
+    }
+
+    #[test]
+    fn nothing_announced() {
+        let report = report("");
+        assert_eq!(report.loose_count(), 2);
+        let suggested: Vec<Vec<Vrp>> = report.groups.values().map(|loose| MaxLengthReport::suggested(loose)).collect();
+        assert_eq!(suggested, [vec![vrp("172.20.2.0/24", 24, 4242420003)], vec![vrp("172.20.0.0/24", 24, 4242420001)]]);
+    }
+
+    #[test]
+    fn minimal() {
+        let prefixes = |list: &[&str]| list.iter().map(|p| IpCidr::from_str(p).unwrap()).collect::<BTreeSet<IpCidr>>();
+        assert_eq!(minimal_vrps(&prefixes(&["10.0.0.0/8"]), 24, 1), [vrp("10.0.0.0/8", 8, 1)]);
+        assert_eq!(minimal_vrps(&prefixes(&["10.0.0.0/8", "10.0.0.0/9", "10.128.0.0/9"]), 24, 1), [vrp("10.0.0.0/8", 9, 1)]);
+        // The max length is not raised beyond the given one
+        assert_eq!(minimal_vrps(&prefixes(&["10.0.0.0/8", "10.0.0.0/9", "10.128.0.0/9"]), 8, 1),
+                   [vrp("10.0.0.0/8", 8, 1), vrp("10.0.0.0/9", 9, 1), vrp("10.128.0.0/9", 9, 1)]);
+        assert_eq!(minimal_vrps(&prefixes(&["10.0.0.0/8", "10.0.0.0/9", "10.1.0.0/16"]), 24, 1),
+                   [vrp("10.0.0.0/8", 8, 1), vrp("10.0.0.0/9", 9, 1), vrp("10.1.0.0/16", 16, 1)]);
+        assert_eq!(minimal_vrps(&prefixes(&["fd00::/48"]), 64, 1), [vrp("fd00::/48", 48, 1)]);
+    }
+}
@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+use cidr_utils::cidr::IpCidr;
+use json::JsonValue;
+use crate::vrp::{address_value, prefix_covers, Vrp};
+
+/// A VRP overlapping prefixes that a source of higher precedence authorises for other origins
+/// only. The VRP is dropped if these prefixes cover its prefix, more-specifics are only reported.
+#[derive(Debug, Clone)]
+pub struct Conflict {
+    pub vrp: Vrp,
+    pub source: String,
+    pub preferred_source: String,
+    /// The VRPs of the preferred source for overlapping prefixes without the origin of `vrp`.
+    pub preferred: Vec<Vrp>,
+    pub dropped: bool,
+}
+
+impl Conflict {
+    pub fn get_json_object(&self) -> JsonValue {
+        let mut data = self.vrp.get_json_object();
+        data["source"] = self.source.as_str().into();
+        data["preferredSource"] = self.preferred_source.as_str().into();
+        data["preferred"] = self.preferred.iter().map(Vrp::get_json_object).collect::<Vec<JsonValue>>().into();
+        data["dropped"] = self.dropped.into();
+        data
+    }
+}
+
+impl std::fmt::Display for Conflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let preferred: Vec<String> = self.preferred.iter().map(Vrp::to_string).collect();
+        match self.dropped {
+            true => write!(f, "{} from {} conflicts with {} from {}", self.vrp, self.source, preferred.join(", "), self.preferred_source),
+            false => write!(f, "{} from {} overlaps {} from {} (kept)", self.vrp, self.source, preferred.join(", "), self.preferred_source),
+        }
+    }
+}
+
+/// VRPs of several sources combined into one set.
+#[derive(Debug, Default)]
+pub struct MergedVrps {
+    pub vrps: Vec<Vrp>,
+    /// Name of the source each VRP was taken from.
+    pub sources: HashMap<Vrp, String>,
+    /// Dropped VRPs and kept VRPs overlapping more-specifics of other origins.
+    pub conflicts: Vec<Conflict>,
+}
+
+/// The accepted VRPs of one source, indexed for finding the VRPs overlapping a prefix.
+#[derive(Default)]
+struct PrefixIndex {
+    /// VRPs by address family, network address and prefix length.
+    by_network: HashMap<(bool, u128, u8), Vec<Vrp>>,
+    /// Address family and network address of every VRP, sorted.
+    sorted: Vec<(bool, u128, Vrp)>,
+}
+
+impl PrefixIndex {
+    fn insert(&mut self, vrp: &Vrp) {
+        let network = address_value(vrp.prefix.first_address());
+        self.by_network.entry((vrp.prefix.is_ipv6(), network, vrp.prefix.network_length())).or_default().push(vrp.clone());
+        self.sorted.push((vrp.prefix.is_ipv6(), network, vrp.clone()));
+    }
+
+    fn sort(&mut self) {
+        self.sorted.sort_by_key(|(is_v6, network, _)| (*is_v6, *network));
+    }
+
+    /// VRPs for `prefix`, prefixes covering it and prefixes within it.
+    fn overlapping(&self, prefix: &IpCidr) -> Vec<&Vrp> {
+        let is_v6 = prefix.is_ipv6();
+        let bits: u8 = if is_v6 { 128 } else { 32 };
+        let first = address_value(prefix.first_address());
+        let last = address_value(prefix.last_address());
+        let mut result: Vec<&Vrp> = Vec::new();
+        for length in 0..prefix.network_length() {
+            let mask = match bits - length {
+                128 => 0,
+                host_bits => !((1u128 << host_bits) - 1),
+            };
+            if let Some(vrps) = self.by_network.get(&(is_v6, first & mask, length)) {
+                result.extend(vrps);
+            }
+        }
+        let start = self.sorted.partition_point(|(v6, network, _)| (*v6, *network) < (is_v6, first));
+        let end = self.sorted.partition_point(|(v6, network, _)| (*v6, *network) <= (is_v6, last));
+        // Networks within the range either lie within the prefix or cover it with the same network address
+        result.extend(self.sorted[start..end].iter().map(|(_, _, vrp)| vrp));
+        result.sort();
+        result.dedup();
+        result
+    }
+}
+
+/// Combines the VRPs of named sources, given in order of precedence. A VRP is dropped if a source
+/// of higher precedence authorises its prefix or a covering prefix, but none of them for its origin.
+/// More-specifics of other origins in a source of higher precedence are reported as conflicts
+/// without dropping the VRP. VRPs contained in several sources are attributed to the one with the
+/// highest precedence.
+pub fn merge_sources(sources: &[(String, Vec<Vrp>)]) -> MergedVrps {
+    let mut result = MergedVrps::default();
+    let mut indexes: Vec<PrefixIndex> = Vec::new();
+    for (name, vrps) in sources {
+        let mut accepted = PrefixIndex::default();
+        'vrps: for vrp in vrps {
+            if result.sources.contains_key(vrp) {
+                continue;
+            }
+            let mut overlaps: Vec<Conflict> = Vec::new();
+            for (index, (preferred_source, _)) in indexes.iter().zip(sources) {
+                let (covering, within): (Vec<&Vrp>, Vec<&Vrp>) = index.overlapping(&vrp.prefix).into_iter()
+                    .partition(|v| prefix_covers(&v.prefix, &vrp.prefix));
+                let conflict = |preferred: Vec<Vrp>, dropped| Conflict {
+                    vrp: vrp.clone(),
+                    source: name.clone(),
+                    preferred_source: preferred_source.clone(),
+                    preferred,
+                    dropped,
+                };
+                if !covering.is_empty() && !covering.iter().any(|v| v.asn == vrp.asn) {
+                    result.conflicts.push(conflict(covering.into_iter().cloned().collect(), true));
+                    continue 'vrps;
+                }
+                let preferred: Vec<Vrp> = within.iter()
+                    .filter(|v| !within.iter().any(|o| o.prefix == v.prefix && o.asn == vrp.asn))
+                    .map(|v| (*v).clone())
+                    .collect();
+                if !preferred.is_empty() {
+                    overlaps.push(conflict(preferred, false));
+                }
+            }
+            result.conflicts.append(&mut overlaps);
+            accepted.insert(vrp);
+            result.sources.insert(vrp.clone(), name.clone());
+            result.vrps.push(vrp.clone());
+        }
+        accepted.sort();
+        indexes.push(accepted);
+    }
+    result
+}
+
+pub fn render_conflicts(conflicts: &[Conflict]) -> String {
+    let data: Vec<JsonValue> = conflicts.iter().map(Conflict::get_json_object).collect();
+    JsonValue::from(data).dump()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn vrp(prefix: &str, max_length: u8, asn: u32) -> Vrp {
+        Vrp { prefix: IpCidr::from_str(prefix).unwrap(), max_length, asn }
+    }
+
+    fn merge(sources: &[(&str, Vec<Vrp>)]) -> MergedVrps {
+        let sources: Vec<(String, Vec<Vrp>)> = sources.iter().map(|(n, v)| (n.to_string(), v.clone())).collect();
+        merge_sources(&sources)
+    }
+
+    fn conflicts(merged: &MergedVrps) -> Vec<String> {
+        merged.conflicts.iter().map(Conflict::to_string).collect()
+    }
+
+    #[test]
+    fn precedence() {
+        let merged = merge(&[
+            ("dn42", vec![vrp("172.20.0.0/16", 24, 1), vrp("172.21.1.0/24", 24, 2)]),
+            ("neo", vec![
+                vrp("172.20.0.0/16", 24, 3),
+                vrp("172.20.5.0/24", 24, 3),
+                vrp("172.20.6.0/24", 24, 1),
+                vrp("172.21.0.0/16", 24, 3),
+                vrp("172.22.0.0/16", 24, 3),
+                vrp("fd00::/48", 64, 1),
+            ]),
+        ]);
+        let kept: Vec<String> = merged.vrps.iter().map(Vrp::to_string).collect();
+        assert_eq!(kept, [
+            "172.20.0.0/16 max 24 as AS1",
+            "172.21.1.0/24 max 24 as AS2",
+            "172.20.6.0/24 max 24 as AS1",
+            "172.21.0.0/16 max 24 as AS3",
+            "172.22.0.0/16 max 24 as AS3",
+            "fd00::/48 max 64 as AS1",
+        ]);
+        // Equal and covering prefixes of other origins drop the VRP, more-specifics are only reported
+        assert_eq!(conflicts(&merged), [
+            "172.20.0.0/16 max 24 as AS3 from neo conflicts with 172.20.0.0/16 max 24 as AS1 from dn42",
+            "172.20.5.0/24 max 24 as AS3 from neo conflicts with 172.20.0.0/16 max 24 as AS1 from dn42",
+            "172.21.0.0/16 max 24 as AS3 from neo overlaps 172.21.1.0/24 max 24 as AS2 from dn42 (kept)",
+        ]);
+        assert_eq!(merged.conflicts.iter().map(|c| c.dropped).collect::<Vec<_>>(), [true, true, false]);
+    }
+
+    #[test]
+    fn shared_origins() {
+        // A covering prefix for the same origin authorises more-specifics of a lower source,
+        // even if other covering prefixes have other origins
+        let merged = merge(&[
+            ("a", vec![vrp("10.0.0.0/8", 8, 1), vrp("10.1.0.0/16", 16, 2), vrp("11.2.0.0/16", 16, 4), vrp("11.3.0.0/16", 16, 3)]),
+            ("b", vec![vrp("10.1.2.0/24", 24, 2), vrp("10.1.3.0/24", 24, 1), vrp("11.2.0.0/15", 16, 3)]),
+        ]);
+        assert_eq!(merged.vrps.len(), 7);
+        // Only the more-specific of the other origin is reported
+        assert_eq!(conflicts(&merged), ["11.2.0.0/15 max 16 as AS3 from b overlaps 11.2.0.0/16 max 16 as AS4 from a (kept)"]);
+    }
+
+    #[test]
+    fn deduplication() {
+        let shared = vrp("172.20.0.0/24", 24, 1);
+        let merged = merge(&[
+            ("a", vec![shared.clone()]),
+            ("b", vec![shared.clone(), vrp("172.20.1.0/24", 24, 1)]),
+            ("c", vec![shared.clone(), vrp("172.20.1.0/24", 24, 1), vrp("172.20.1.0/24", 24, 2)]),
+        ]);
+        assert_eq!(merged.vrps.len(), 2);
+        assert_eq!(merged.sources[&shared], "a");
+        assert_eq!(merged.sources[&vrp("172.20.1.0/24", 24, 1)], "b");
+        assert_eq!(conflicts(&merged), ["172.20.1.0/24 max 24 as AS2 from c conflicts with 172.20.1.0/24 max 24 as AS1 from b"]);
+    }
+
+    #[test]
+    fn conflict_report() {
+        let merged = merge(&[
+            ("a", vec![vrp("172.20.0.0/16", 24, 1), vrp("172.21.1.0/24", 24, 1)]),
+            ("b", vec![vrp("172.20.1.0/24", 24, 2), vrp("172.21.0.0/16", 24, 2)]),
+        ]);
+        let report = json::parse(&render_conflicts(&merged.conflicts)).unwrap();
+        assert_eq!(report.len(), 2);
+        assert_eq!(report[0]["prefix"], "172.20.1.0/24");
+        assert_eq!(report[0]["source"], "b");
+        assert_eq!(report[0]["preferredSource"], "a");
+        assert_eq!(report[0]["preferred"][0]["prefix"], "172.20.0.0/16");
+        assert_eq!(report[0]["dropped"], true);
+        assert_eq!(report[1]["prefix"], "172.21.0.0/16");
+        assert_eq!(report[1]["preferred"][0]["prefix"], "172.21.1.0/24");
+        assert_eq!(report[1]["dropped"], false);
+        assert_eq!(render_conflicts(&[]), "[]");
+    }
+}
@@ -0,0 +1,282 @@
+//! Reader for the routes of MRT (RFC 6396) RIB dumps in TABLE_DUMP and TABLE_DUMP_V2 format,
+//! including the ADD-PATH variants (RFC 8050).
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use cidr_utils::cidr::{IpCidr, IpInet};
+use crate::BoxResult;
+
+const TABLE_DUMP: u16 = 12;
+const TABLE_DUMP_V2: u16 = 13;
+const ATTRIBUTE_AS_PATH: u8 = 2;
+const AS_SEQUENCE: u8 = 2;
+
+/// Whether `data` starts with the header of an MRT record of a known type.
+pub fn is_mrt(data: &[u8]) -> bool {
+    data.len() >= 12 && (11..=17).contains(&u16::from_be_bytes([data[4], data[5]]))
+}
+
+/// Reads the prefix and origin of every route in the RIB records of an MRT file. Records of
+/// other types and routes without a definite origin, e.g. ending in an AS_SET, are skipped.
+pub fn read_routes(data: &[u8]) -> BoxResult<Vec<(IpCidr, u32)>> {
+    let mut routes: Vec<(IpCidr, u32)> = Vec::new();
+    let mut position = 0;
+    while position < data.len() {
+        let header = data.get(position..position + 12).ok_or("truncated MRT record header")?;
+        let record_type = u16::from_be_bytes([header[4], header[5]]);
+        let subtype = u16::from_be_bytes([header[6], header[7]]);
+        let length = u32::from_be_bytes([header[8], header[9], header[10], header[11]]) as usize;
+        let start = position + 12;
+        let message = start.checked_add(length).and_then(|end| data.get(start..end))
+            .ok_or_else(|| format!("truncated MRT record at offset {}", position))?;
+        let result = match (record_type, subtype) {
+            (TABLE_DUMP, 1 | 2) => read_table_dump(&mut Reader::new(message), subtype == 2, &mut routes),
+            // RIB_IPV4_UNICAST, RIB_IPV6_UNICAST and their ADD-PATH variants
+            (TABLE_DUMP_V2, 2 | 4 | 8 | 10) => read_rib(&mut Reader::new(message), subtype == 4 || subtype == 10, subtype >= 8, &mut routes),
+            _ => Ok(()),
+        };
+        result.map_err(|e| format!("{} in MRT record at offset {}", e, position))?;
+        position = start + length;
+    }
+    Ok(routes)
+}
+
+fn read_table_dump(reader: &mut Reader, is_v6: bool, routes: &mut Vec<(IpCidr, u32)>) -> BoxResult<()> {
+    // View number and sequence number
+    reader.take(4)?;
+    let address = reader.address(is_v6)?;
+    let length = reader.u8()?;
+    // Status, originated time, peer address and peer AS
+    reader.take(1 + 4 + if is_v6 { 16 } else { 4 } + 2)?;
+    let attributes_length = reader.u16()? as usize;
+    if let Some(origin) = origin(reader.take(attributes_length)?, 2)? {
+        routes.push((network(address, length)?, origin));
+    }
+    Ok(())
+}
+
+fn read_rib(reader: &mut Reader, is_v6: bool, add_path: bool, routes: &mut Vec<(IpCidr, u32)>) -> BoxResult<()> {
+    // Sequence number
+    reader.take(4)?;
+    let length = reader.u8()?;
+    let bits: u8 = if is_v6 { 128 } else { 32 };
+    if length > bits {
+        return Err(format!("invalid prefix length {}", length).into());
+    }
+    let mut octets = [0u8; 16];
+    octets[..length.div_ceil(8) as usize].copy_from_slice(reader.take(length.div_ceil(8) as usize)?);
+    let address = match is_v6 {
+        true => IpAddr::V6(Ipv6Addr::from(octets)),
+        false => IpAddr::V4(Ipv4Addr::new(octets[0], octets[1], octets[2], octets[3])),
+    };
+    let prefix = network(address, length)?;
+
+    let entries = reader.u16()?;
+    for _ in 0..entries {
+        // Peer index, originated time and path identifier
+        reader.take(2 + 4 + if add_path { 4 } else { 0 })?;
+        let attributes_length = reader.u16()? as usize;
+        if let Some(origin) = origin(reader.take(attributes_length)?, 4)? {
+            routes.push((prefix, origin));
+        }
+    }
+    Ok(())
+}
+
+/// The origin AS of the AS_PATH attribute, which is the last AS of the path if it ends in an AS_SEQUENCE.
+fn origin(attributes: &[u8], asn_size: usize) -> BoxResult<Option<u32>> {
+    let mut reader = Reader::new(attributes);
+    while !reader.is_empty() {
+        let flags = reader.u8()?;
+        let attribute_type = reader.u8()?;
+        // Extended length
+        let length = match flags & 0x10 != 0 {
+            true => reader.u16()? as usize,
+            false => reader.u8()? as usize,
+        };
+        let value = reader.take(length)?;
+        if attribute_type != ATTRIBUTE_AS_PATH {
+            continue;
+        }
+
+        let mut path = Reader::new(value);
+        let mut last: Option<(u8, u32)> = None;
+        while !path.is_empty() {
+            let segment_type = path.u8()?;
+            let count = path.u8()? as usize;
+            let asns = path.take(count * asn_size)?;
+            if let Some(asn) = asns.rchunks(asn_size).next() {
+                let asn = asn.iter().fold(0u32, |value, byte| value << 8 | *byte as u32);
+                last = Some((segment_type, asn));
+            }
+        }
+        // The origin of a path ending in an AS_SET is undefined (RFC 6811)
+        return Ok(match last {
+            Some((AS_SEQUENCE, asn)) => Some(asn),
+            _ => None,
+        });
+    }
+    Ok(None)
+}
+
+fn network(address: IpAddr, length: u8) -> BoxResult<IpCidr> {
+    Ok(IpInet::new(address, length).map_err(|e| e.to_string())?.network())
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Reader { data, position: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.position >= self.data.len()
+    }
+
+    fn take(&mut self, count: usize) -> BoxResult<&'a [u8]> {
+        let result = self.position.checked_add(count).and_then(|end| self.data.get(self.position..end)).ok_or("truncated data")?;
+        self.position += count;
+        Ok(result)
+    }
+
+    fn u8(&mut self) -> BoxResult<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> BoxResult<u16> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn address(&mut self, is_v6: bool) -> BoxResult<IpAddr> {
+        Ok(match is_v6 {
+            true => IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(self.take(16)?).expect("16 bytes"))),
+            false => {
+                let bytes = self.take(4)?;
+                IpAddr::V4(Ipv4Addr::new(bytes[0], bytes[1], bytes[2], bytes[3]))
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    const AS_SET: u8 = 1;
+
+    fn record(record_type: u16, subtype: u16, body: &[u8]) -> Vec<u8> {
+        [&[0; 4][..], &record_type.to_be_bytes(), &subtype.to_be_bytes(), &(body.len() as u32).to_be_bytes(), body].concat()
+    }
+
+    /// Path attributes with an ORIGIN attribute and an AS_PATH of the given segments.
+    fn attributes(segments: &[(u8, &[u32])], asn_size: usize) -> Vec<u8> {
+        let mut path: Vec<u8> = Vec::new();
+        for (segment_type, asns) in segments {
+            path.extend([*segment_type, asns.len() as u8]);
+            for asn in *asns {
+                path.extend(&asn.to_be_bytes()[4 - asn_size..]);
+            }
+        }
+        // The AS_PATH uses the extended length to test both encodings
+        [&[0x40, 1, 1, 0][..], &[0x50, ATTRIBUTE_AS_PATH], &(path.len() as u16).to_be_bytes(), &path].concat()
+    }
+
+    /// A TABLE_DUMP_V2 RIB record of `prefix` with one entry per element of `paths`.
+    fn rib(prefix: &str, add_path: bool, paths: &[&[(u8, &[u32])]]) -> Vec<u8> {
+        let prefix = IpCidr::from_str(prefix).unwrap();
+        let octets = match prefix.first_address() {
+            IpAddr::V4(address) => address.octets().to_vec(),
+            IpAddr::V6(address) => address.octets().to_vec(),
+        };
+        let length = prefix.network_length();
+        let mut body = [&[0, 0, 0, 1, length][..], &octets[..length.div_ceil(8) as usize], &(paths.len() as u16).to_be_bytes()].concat();
+        for segments in paths {
+            let attributes = attributes(segments, 4);
+            body.extend([0, 0, 0, 0, 0, 0]);
+            if add_path {
+                body.extend([0, 0, 0, 1]);
+            }
+            body.extend((attributes.len() as u16).to_be_bytes());
+            body.extend(attributes);
+        }
+        let subtype = match (prefix.is_ipv6(), add_path) {
+            (false, false) => 2,
+            (true, false) => 4,
+            (false, true) => 8,
+            (true, true) => 10,
+        };
+        record(TABLE_DUMP_V2, subtype, &body)
+    }
+
+    fn routes(data: &[u8]) -> Vec<(String, u32)> {
+        read_routes(data).unwrap().into_iter().map(|(prefix, origin)| (prefix.to_string(), origin)).collect()
+    }
+
+    #[test]
+    fn table_dump_v2() {
+        let data = [
+            // A PEER_INDEX_TABLE record is skipped
+            record(TABLE_DUMP_V2, 1, &[0; 10]),
+            rib("172.20.0.0/24", false, &[&[(AS_SEQUENCE, &[4242420001, 4242420000])], &[(AS_SEQUENCE, &[4242420002])]]),
+            rib("fd00::/48", false, &[&[(AS_SEQUENCE, &[4242420003])]]),
+            rib("172.20.1.0/25", true, &[&[(AS_SEQUENCE, &[4242420004])]]),
+            rib("fd00:1::/64", true, &[&[(AS_SEQUENCE, &[4242420005])]]),
+        ].concat();
+        assert!(is_mrt(&data));
+        assert_eq!(routes(&data), [
+            ("172.20.0.0/24".to_owned(), 4242420000),
+            ("172.20.0.0/24".to_owned(), 4242420002),
+            ("fd00::/48".to_owned(), 4242420003),
+            ("172.20.1.0/25".to_owned(), 4242420004),
+            ("fd00:1::/64".to_owned(), 4242420005),
+        ]);
+    }
+
+    #[test]
+    fn as_sets() {
+        let data = rib("172.20.0.0/24", false, &[
+            // The origin of a path ending in an AS_SET is undefined
+            &[(AS_SEQUENCE, &[4242420001]), (AS_SET, &[4242420002, 4242420003])],
+            &[(AS_SET, &[4242420004, 4242420005]), (AS_SEQUENCE, &[4242420006])],
+            // No AS_PATH segments at all
+            &[],
+        ]);
+        assert_eq!(routes(&data), [("172.20.0.0/24".to_owned(), 4242420006)]);
+    }
+
+    #[test]
+    fn table_dump() {
+        let attributes = attributes(&[(AS_SEQUENCE, &[64500, 4200])], 2);
+        let v4 = [&[0, 0, 0, 1][..], &[172, 20, 0, 0], &[24, 1, 0, 0, 0, 0], &[172, 20, 0, 1], &[0, 1],
+                  &(attributes.len() as u16).to_be_bytes(), &attributes].concat();
+        let mut address = [0u8; 16];
+        address[..2].copy_from_slice(&[0xfd, 0x00]);
+        let v6 = [&[0, 0, 0, 1][..], &address, &[48, 1, 0, 0, 0, 0], &address, &[0, 1],
+                  &(attributes.len() as u16).to_be_bytes(), &attributes].concat();
+        let data = [record(TABLE_DUMP, 1, &v4), record(TABLE_DUMP, 2, &v6)].concat();
+        assert_eq!(routes(&data), [("172.20.0.0/24".to_owned(), 4200), ("fd00::/48".to_owned(), 4200)]);
+    }
+
+    #[test]
+    fn malformed_records() {
+        let data = rib("172.20.0.0/24", false, &[&[(AS_SEQUENCE, &[4242420000])]]);
+        assert!(!is_mrt(b"172.20.0.0/24 AS4242420000\n"));
+        assert_eq!(read_routes(&data[..8]).unwrap_err().to_string(), "truncated MRT record header");
+        assert_eq!(read_routes(&data[..data.len() - 1]).unwrap_err().to_string(), "truncated MRT record at offset 0");
+
+        // The length of the AS_PATH exceeds the attributes
+        let mut corrupt = data.clone();
+        let length = corrupt.len();
+        corrupt[length - 8] = 0xff;
+        assert_eq!(read_routes(&corrupt).unwrap_err().to_string(), "truncated data in MRT record at offset 0");
+
+        let mut corrupt = data.clone();
+        corrupt[12 + 4] = 33;
+        assert_eq!(read_routes(&corrupt).unwrap_err().to_string(), "invalid prefix length 33 in MRT record at offset 0");
+    }
+}
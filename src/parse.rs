@@ -1,60 +1,70 @@
 use std::cell::Cell;
-use std::fs::{File, read_dir};
+use std::fs;
 use std::io;
-use std::io::BufRead;
 use std::path::Path;
 use std::str::FromStr;
 use cidr_utils::cidr::IpCidr;
-use json::JsonValue;
-use crate::{BoxResult, RouteObjectsWithWarnings};
+use cidr_utils::cidr::errors::NetworkParseError;
+use crate::{io_error, BoxResult, RouteObjectsWithWarnings, Warning, Warnings};
+use crate::rpsl::{parse_object, RpslObject};
+use crate::source::RegistrySource;
+use crate::vrp::Vrp;
 
 
-pub fn evaluate_filter_set(object_list: &mut Vec<RouteObject>, filter_set: &[FilterSet]) {
+/// Objects of a source listed in `by_source` are evaluated against the filter set of that source.
+pub fn evaluate_filter_set(object_list: &mut Vec<RouteObject>, filter_set: &[FilterSet], by_source: &[(String, Vec<FilterSet>)]) {
     object_list.retain(|v| {
-        let mut filter_set_iter = filter_set.iter();
-        let mut bits: u8 = 0;
-        let applicable_filter_set = filter_set_iter.find(|f| {
-            if f.prefix.contains(&v.prefix.first_address()) && f.prefix.contains(&v.prefix.last_address()) {
-                bits = v.prefix.network_length();
-                return true;
-            }
-            false
-        });
-
+        let source_filter_set = v.source.as_deref()
+            .and_then(|source| by_source.iter().find(|(s, _)| s.eq_ignore_ascii_case(source)));
+        filter_accepts(v, source_filter_set.map_or(filter_set, |(_, f)| f))
+    })
+}
 
-        if applicable_filter_set.is_none() {
-            return false;
+/// Checks an object against the filter set and applies the max length of the filter set to it.
+fn filter_accepts(v: &RouteObject, filter_set: &[FilterSet]) -> bool {
+    let mut filter_set_iter = filter_set.iter();
+    let mut bits: u8 = 0;
+    let applicable_filter_set = filter_set_iter.find(|f| {
+        if f.prefix.contains(&v.prefix.first_address()) && f.prefix.contains(&v.prefix.last_address()) {
+            bits = v.prefix.network_length();
+            return true;
         }
+        false
+    });
 
-        if !applicable_filter_set.unwrap().allow {
-            return false;
-        }
 
-        let filter_max_length = applicable_filter_set.unwrap().max_len;
-        let filter_min_length = applicable_filter_set.unwrap().min_len;
-        let applicable_max_length: i32;
+    if applicable_filter_set.is_none() {
+        return false;
+    }
 
+    if !applicable_filter_set.unwrap().allow {
+        return false;
+    }
 
-        if let Some(mut obj_max_length) = v.max_length.get() {
-            if obj_max_length > filter_max_length {
-                obj_max_length = filter_max_length;
-                v.max_length.set(Some(filter_max_length));
-            }
-            if obj_max_length < filter_min_length {
-                obj_max_length = filter_min_length;
-                v.max_length.set(Some(filter_min_length));
-            }
-            applicable_max_length = obj_max_length;
-        } else {
+    let filter_max_length = applicable_filter_set.unwrap().max_len;
+    let filter_min_length = applicable_filter_set.unwrap().min_len;
+    let applicable_max_length: i32;
+
+
+    if let Some(mut obj_max_length) = v.max_length.get() {
+        if obj_max_length > filter_max_length {
+            obj_max_length = filter_max_length;
             v.max_length.set(Some(filter_max_length));
-            applicable_max_length = filter_max_length;
         }
-
-        if (bits as i32) > applicable_max_length {
-            return false;
+        if obj_max_length < filter_min_length {
+            obj_max_length = filter_min_length;
+            v.max_length.set(Some(filter_min_length));
         }
-        true
-    })
+        applicable_max_length = obj_max_length;
+    } else {
+        v.max_length.set(Some(filter_max_length));
+        applicable_max_length = filter_max_length;
+    }
+
+    if (bits as i32) > applicable_max_length {
+        return false;
+    }
+    true
 }
 
 
@@ -80,16 +90,24 @@ impl FilterSet {
     }
 }
 
-pub fn read_filter_set(file: &Path) -> BoxResult<(Vec<FilterSet>, Vec<String>)> {
-    let mut warnings: Vec<String> = Vec::new();
-    let mut set: Vec<FilterSet> = Vec::new();
-    let lines = read_lines(file).map_err(|e|
-        format!("Error reading filter set file: {}", e)
+pub fn read_filter_set(source: &dyn RegistrySource, path: &str) -> BoxResult<(Vec<FilterSet>, Warnings)> {
+    let content = source.read_file(path).and_then(|c| into_text(c, &source.describe(path))).map_err(|e|
+        io_error(e, format!("Error reading filter set file {}", source.describe(path)))
+    )?;
+    Ok(parse_filter_set(&content))
+}
+
+pub fn read_filter_set_file(file: &Path) -> BoxResult<(Vec<FilterSet>, Warnings)> {
+    let content = fs::read(file).and_then(|c| into_text(c, &file.display().to_string())).map_err(|e|
+        io_error(e, format!("Error reading filter set file {}", file.display()))
     )?;
-    for line_result in lines {
-        let line = line_result.map_err(|e|
-            format!("Error reading filter set line: {}", e)
-        )?;
+    Ok(parse_filter_set(&content))
+}
+
+fn parse_filter_set(content: &str) -> (Vec<FilterSet>, Warnings) {
+    let mut warnings: Warnings = Vec::new();
+    let mut set: Vec<FilterSet> = Vec::new();
+    for line in content.lines() {
         if line.starts_with('#') || line.is_empty() {
             continue;
         }
@@ -107,177 +125,346 @@ pub fn read_filter_set(file: &Path) -> BoxResult<(Vec<FilterSet>, Vec<String>)>
             }
             Err(err) => {
                 let error_message = format!("Failed to parse filter.txt line: {} Error: {}", line, err);
-                warnings.push(error_message);
+                warnings.push(Warning::new("invalid-filter-line", line.trim(), error_message));
             }
         }
     }
 
-    set.sort_by(|a, b| a.priority.cmp(&b.priority));
-    Ok((set, warnings))
+    set.sort_by_key(|a| a.priority);
+    (set, warnings)
 }
 
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct RouteObject {
+    /// Name of the object file, e.g. `172.20.0.0_24`.
+    pub file: String,
     pub prefix: IpCidr,
-    pub origins: Vec<String>,
+    pub origins: Vec<u32>,
     pub max_length: Cell<Option<i32>>,
+    pub maintainers: Vec<String>,
+    /// The registry the object belongs to, e.g. `DN42` or an interconnected network.
+    pub source: Option<String>,
 }
 
 impl RouteObject {
-    pub fn get_bird_format(self) -> String {
-        let mut result: String = "".to_owned();
-        for origin in &self.origins {
-            result.push_str(&format!("route {prefix} max {max_length} as {origin};\n", prefix = self.get_prefix_string(),
-                                     max_length = self.max_length.get().unwrap(), origin = origin));
-        }
-        result
-    }
-    pub fn get_json_objects(self) -> Vec<JsonValue> {
-        let mut result: Vec<JsonValue> = Vec::new();
-        for origin in &self.origins {
-            let mut data = JsonValue::new_object();
-            data["prefix"] = self.get_prefix_string().into();
-            data["maxLength"] = self.max_length.get().unwrap().into();
-            data["asn"] = origin.to_owned().into();
-            result.push(data);
-        }
-        result
+    pub fn get_vrps(&self) -> Vec<Vrp> {
+        let max_length = self.max_length.get().expect("max_length is set by the filter set").clamp(0, 128) as u8;
+        self.origins.iter().map(|origin| Vrp {
+            prefix: self.prefix,
+            max_length,
+            asn: *origin,
+        }).collect()
     }
+}
 
-    fn get_prefix_string(&self) -> String {
-        if self.prefix.is_host_address() {
-            return if self.prefix.is_ipv4() {
-                self.prefix.to_string() + "/32"
-            } else {
-                self.prefix.to_string() + "/128"
-            };
+pub fn read_route_objects(source: &dyn RegistrySource, path: &str, expect_v6: bool) -> BoxResult<RouteObjectsWithWarnings> {
+    let mut objects: Vec<RouteObject> = Vec::new();
+    let mut warnings: Warnings = Vec::new();
+    for filename in source.list_directory(path)? {
+        let file = format!("{}/{}", path.trim_end_matches('/'), filename);
+        let content = match source.read_file(&file) {
+            Ok(content) => content,
+            Err(err) => {
+                warnings.push(Warning::in_file("unreadable-file", &file, err));
+                continue;
+            }
+        };
+        let (object, diagnostics) = parse_object(&content);
+        for diagnostic in diagnostics {
+            warnings.push(Warning::in_file(diagnostic.code, &file, diagnostic));
         }
-        self.prefix.to_string()
-    }
+        match parse_route_object(&file, &object, expect_v6) {
+            Ok(result) => {
+                objects.push(result);
+            }
+            Err(warning) => {
+                warnings.push(warning);
+            }
+        }
+    };
+    Ok((objects, warnings))
 }
 
-pub fn read_route_objects<P>(path: P, expect_v6: bool) -> BoxResult<RouteObjectsWithWarnings>
-where
-    P: AsRef<Path>,
-{
+/// Builds a route object from a parsed route or route6 object file. Warnings refer to `path`,
+/// the file relative to the registry root.
+pub fn parse_route_object(path: &str, object: &RpslObject, expect_v6: bool) -> Result<RouteObject, Warning> {
     #[derive(Debug)]
     struct RouteObjectBuilder {
+        path: String,
         filename: String,
         prefix: Option<String>,
         origins: Vec<String>,
         max_length: Option<String>,
+        maintainers: Vec<String>,
+        source: Option<String>,
     }
     impl RouteObjectBuilder {
-        fn new(filename: String) -> Self {
+        fn new(path: &str) -> Self {
             Self {
-                filename,
+                path: path.to_owned(),
+                filename: path.rsplit('/').next().unwrap_or(path).to_owned(),
                 prefix: None,
                 origins: Vec::new(),
                 max_length: None,
+                maintainers: Vec::new(),
+                source: None,
             }
         }
-        fn validate_and_build(mut self, expect_v6: bool) -> BoxResult<RouteObject> {
+        fn validate_and_build(self, expect_v6: bool) -> Result<RouteObject, Warning> {
+            let invalid = |code, message: &str| Warning::in_file(code, &self.path, message);
             if self.origins.is_empty() {
-                return Err("missing origin field in object")?;
+                return Err(invalid("missing-origin", "missing origin field in object"));
             }
 
+            let mut origins: Vec<u32> = Vec::new();
             for origin in &self.origins {
-                if !origin.starts_with("AS") {
-                    return Err("Invalid origin field")?;
+                let asn = parse_origin(origin).map_err(|e| invalid("invalid-origin", &e))?;
+                if origins.contains(&asn) {
+                    return Err(invalid("duplicate-origin", &format!("Duplicate origin field {}", origin)));
                 }
+                origins.push(asn);
             }
-
-            self.origins.iter_mut().for_each(|x| {
-                *x = x.replace("AS", "");
-            });
-
-            for origin in &self.origins {
-                if !origin.chars().all(char::is_numeric) {
-                    return Err("Invalid origin field")?;
-                }
+            // An AS0 ROA states that the prefix must not be routed at all (RFC 7607)
+            if origins.len() > 1 && origins.contains(&0) {
+                return Err(invalid("invalid-origin", "origin AS0 cannot be combined with other origins"));
             }
 
 
-            if self.prefix.is_none() {
-                return Err("missing route or route6 field in object")?;
-            }
-            if self.filename.replace('_', "/") != self.prefix.as_deref().unwrap() {
-                return Err("filename does not equal prefix field")?;
+            let Some(prefix_text) = self.prefix.as_deref() else {
+                return Err(invalid("missing-prefix", "missing route or route6 field in object"));
+            };
+            if self.filename.replace('_', "/") != prefix_text {
+                return Err(invalid("filename-mismatch", "filename does not equal prefix field"));
             }
-            let prefix = IpCidr::from_str(&self.prefix.unwrap()).map_err(|e|
-                format!("Unable to parse IP CIDR: {}", e)
-            )?;
+            let prefix = IpCidr::from_str(prefix_text).map_err(|e| match e {
+                NetworkParseError::InvalidHostPart => invalid("host-bits", &format!("Prefix {} has host bits set", prefix_text)),
+                e => invalid("invalid-prefix", &format!("Unable to parse IP CIDR: {}", e)),
+            })?;
 
             if prefix.is_ipv4() && expect_v6 {
-                return Err("expected IPv6 but found an IPv4 object")?;
+                return Err(invalid("wrong-family", "expected IPv6 but found an IPv4 object"));
             } else if prefix.is_ipv6() && !expect_v6 {
-                return Err("expected IPv4 but found an IPv6 object")?;
+                return Err(invalid("wrong-family", "expected IPv4 but found an IPv6 object"));
             }
 
 
-            let max_length = self.max_length.map_or(Ok(None), |s|
+            let max_length = self.max_length.as_deref().map_or(Ok(None), |s|
                 if let Ok(parsed) = s.parse::<i32>() {
                     Ok(Some(parsed))
                 } else {
-                    Err("Failed to parse max_length value as i32")
+                    Err(invalid("invalid-max-length", "Failed to parse max_length value as i32"))
                 },
             )?;
+            if let Some(max_length) = max_length {
+                let bits = if prefix.is_ipv6() { 128 } else { 32 };
+                let problem = if max_length < 0 {
+                    Some(format!("max-length {} is negative", max_length))
+                } else if max_length > bits {
+                    Some(format!("max-length {} exceeds the address length {}", max_length, bits))
+                } else if max_length < prefix.network_length() as i32 {
+                    Some(format!("max-length {} is shorter than the prefix length {}", max_length, prefix.network_length()))
+                } else {
+                    None
+                };
+                if let Some(problem) = problem {
+                    return Err(invalid("invalid-max-length", &problem));
+                }
+            }
 
             let result = RouteObject {
+                file: self.filename,
                 prefix,
-                origins: self.origins,
+                origins,
                 max_length: Cell::new(max_length),
+                maintainers: self.maintainers,
+                source: self.source,
             };
             Ok(result)
         }
     }
 
-    let mut objects: Vec<RouteObject> = Vec::new();
-    let mut warnings: Vec<String> = Vec::new();
-    let dir = read_dir(path.as_ref()).map_err(|e|
-        format!("Unable to read directory {}: {}", path.as_ref().display(), e)
-    )?;
-    for file_result in dir {
-        let file = file_result.map_err(|e|
-            format!("Unable to read directory file {}: {}", path.as_ref().display(), e)
-        )?.path();
-        let lines = read_lines(&file).map_err(|e|
-            format!("Unable to open file {}: {}", file.display(), e)
-        )?;
-        let filename = file.as_path().file_name().unwrap_or_default().to_str().unwrap_or_default().to_owned();
-        let mut object = RouteObjectBuilder::new(filename.to_owned());
-        for line in lines {
-            if let Some(result) = line.map_err(|e|
-                format!("Unable to read file line {}: {}", file.display(), e)
-            )?.split_once(':') {
-                match result.0.trim_end() {
-                    "route" => { object.prefix = Some(result.1.trim().to_owned()) }
-                    "route6" => { object.prefix = Some(result.1.trim().to_owned()) }
-                    "origin" => { object.origins.push(result.1.trim().to_owned()) }
-                    "max-length" => { object.max_length = Some(result.1.trim().to_owned()) }
-                    &_ => {}
-                }
-            }
-        }
-        match object.validate_and_build(expect_v6) {
-            Ok(result) => {
-                objects.push(result);
-            }
-            Err(err) => {
-                let error_message = format!("Error in file: {}: {}", filename, err);
-                warnings.push(error_message);
-            }
+    let invalid = |message: &str| Warning::in_file("duplicate-attribute", path, message);
+    for key in ["route", "route6", "max-length", "source"] {
+        object.single(key).map_err(|e| invalid(&e))?;
+    }
+    if let (Ok(Some(route)), Ok(Some(route6))) = (object.single("route"), object.single("route6")) {
+        return Err(invalid(&format!("object has both route (line {}) and route6 (line {}) attributes", route.line, route6.line)));
+    }
+
+    let mut builder = RouteObjectBuilder::new(path);
+    for attribute in &object.attributes {
+        let value = attribute.value.to_owned();
+        match attribute.key.as_str() {
+            "route" => { builder.prefix = Some(value) }
+            "route6" => { builder.prefix = Some(value) }
+            "origin" => { builder.origins.push(value) }
+            "max-length" => { builder.max_length = Some(value) }
+            "mnt-by" => { builder.maintainers.push(value) }
+            "source" => { builder.source = Some(value) }
+            &_ => {}
         }
-    };
-    Ok((objects, warnings))
+    }
+    builder.validate_and_build(expect_v6)
+}
+
+/// Parses an `origin` value, which must be `AS` followed by a 32-bit AS number that may originate routes
+/// or AS0.
+fn parse_origin(origin: &str) -> Result<u32, String> {
+    let digits = origin.strip_prefix("AS").ok_or_else(|| format!("Invalid origin field {}: missing AS prefix", origin))?;
+    if digits.is_empty() {
+        return Err(format!("Invalid origin field {}: missing AS number", origin));
+    }
+    if !digits.chars().all(|c| c.is_ascii_digit()) {
+        return Err(format!("Invalid origin field {}: not a number", origin));
+    }
+    let asn = digits.parse::<u32>().map_err(|_| format!("Invalid origin field {}: AS number exceeds 32 bits", origin))?;
+    if let Some(reason) = reserved_asn(asn) {
+        return Err(format!("Invalid origin field {}: {}", origin, reason));
+    }
+    Ok(asn)
+}
+
+/// Why an AS number must not originate routes (RFC 5398, RFC 6793, RFC 6996, RFC 7300).
+fn reserved_asn(asn: u32) -> Option<&'static str> {
+    match asn {
+        23456 => Some("AS23456 is reserved for AS_TRANS"),
+        64496..=64511 | 65536..=65551 => Some("AS number is reserved for documentation"),
+        64512..=65534 => Some("private 16-bit AS number"),
+        65535 | 4294967295 => Some("AS number is reserved"),
+        _ => None,
+    }
+}
+
+pub fn into_text(content: Vec<u8>, name: &str) -> io::Result<String> {
+    String::from_utf8(content).map_err(|e|
+        io::Error::new(io::ErrorKind::InvalidData, format!("Unable to read file line {}: {}", name, e))
+    )
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::MemorySource;
+
+    #[test]
+    fn warnings_refer_to_registry_paths() {
+        let mut source = MemorySource::new();
+        source.insert("data/route/172.20.0.0_24", "route: 172.20.0.0/24\norigin: AS4242420000\n");
+        source.insert("data/route/172.20.1.0_24", "route: 172.20.1.0/24\norigin: 4242420000\nbroken line\n");
+        let (objects, warnings) = read_route_objects(&source, "data/route", false).unwrap();
+        assert_eq!(objects.len(), 1);
+        assert_eq!(objects[0].file, "172.20.0.0_24");
+        let codes: Vec<(&str, &str)> = warnings.iter().map(|w| (w.code, w.object.as_str())).collect();
+        assert_eq!(codes, [("malformed-line", "data/route/172.20.1.0_24"), ("invalid-origin", "data/route/172.20.1.0_24")]);
+    }
+
+    /// Parses a route object whose file name matches its prefix, returning the warning code and message on failure.
+    fn route(content: &str) -> Result<RouteObject, (&'static str, String)> {
+        let object = parse_object(content.as_bytes()).0;
+        let prefix = object.get("route").or(object.get("route6")).unwrap_or("10.0.0.0/8");
+        let path = format!("data/route/{}", prefix.replace('/', "_"));
+        parse_route_object(&path, &object, prefix.contains(':')).map_err(|w| (w.code, w.message))
+    }
+
+    fn route_with_origin(origin: &str) -> Result<RouteObject, (&'static str, String)> {
+        route(&format!("route: 172.20.0.0/24\norigin: {}\n", origin))
+    }
+
+    fn route_with_max_length(prefix: &str, max_length: &str) -> Result<RouteObject, (&'static str, String)> {
+        route(&format!("route{}: {}\norigin: AS4242420000\nmax-length: {}\n", if prefix.contains(':') { "6" } else { "" }, prefix, max_length))
+    }
+
+    #[test]
+    fn valid_object() {
+        let object = route("route: 172.20.0.0/24\norigin: AS4242420000\norigin: AS4242420001\nmax-length: 28\nmnt-by: FOO-MNT\nsource: DN42\n").unwrap();
+        assert_eq!(object.file, "172.20.0.0_24");
+        assert_eq!(object.prefix, IpCidr::from_str("172.20.0.0/24").unwrap());
+        assert_eq!(object.origins, [4242420000, 4242420001]);
+        assert_eq!(object.max_length.get(), Some(28));
+        assert_eq!(object.maintainers, ["FOO-MNT"]);
+        assert_eq!(object.source.as_deref(), Some("DN42"));
+        assert!(route("route6: fd00::/48\norigin: AS4242420000\n").is_ok());
+    }
+
+    #[test]
+    fn origins() {
+        assert!(route_with_origin("AS4242420000").is_ok());
+        assert!(route_with_origin("AS4294967294").is_ok());
+        for (origin, reason) in [
+            ("4242420000", "missing AS prefix"),
+            ("as4242420000", "missing AS prefix"),
+            ("AS", "missing AS number"),
+            ("AS-FOO", "not a number"),
+            ("AS 1", "not a number"),
+            ("AS4294967296", "AS number exceeds 32 bits"),
+            ("AS23456", "AS23456 is reserved for AS_TRANS"),
+            ("AS64496", "AS number is reserved for documentation"),
+            ("AS65551", "AS number is reserved for documentation"),
+            ("AS64512", "private 16-bit AS number"),
+            ("AS65534", "private 16-bit AS number"),
+            ("AS65535", "AS number is reserved"),
+            ("AS4294967295", "AS number is reserved"),
+        ] {
+            let (code, message) = route_with_origin(origin).unwrap_err();
+            assert_eq!(code, "invalid-origin");
+            assert_eq!(message, format!("Error in file: data/route/172.20.0.0_24: Invalid origin field {}: {}", origin, reason));
+        }
+        let (code, message) = route("route: 172.20.0.0/24\norigin: AS4242420000\norigin: AS4242420000\n").unwrap_err();
+        assert_eq!((code, message.ends_with("Duplicate origin field AS4242420000")), ("duplicate-origin", true));
+        assert_eq!(route("route: 172.20.0.0/24\n").unwrap_err().0, "missing-origin");
+    }
+
+    #[test]
+    fn max_lengths() {
+        assert_eq!(route_with_max_length("172.20.0.0/24", "24").unwrap().max_length.get(), Some(24));
+        assert_eq!(route_with_max_length("172.20.0.0/24", "32").unwrap().max_length.get(), Some(32));
+        assert_eq!(route_with_max_length("fd00::/48", "128").unwrap().max_length.get(), Some(128));
+        for (prefix, max_length, problem) in [
+            ("172.20.0.0/24", "x", "Failed to parse max_length value as i32"),
+            ("172.20.0.0/24", "", "Failed to parse max_length value as i32"),
+            ("172.20.0.0/24", "-1", "max-length -1 is negative"),
+            ("172.20.0.0/24", "33", "max-length 33 exceeds the address length 32"),
+            ("fd00::/48", "129", "max-length 129 exceeds the address length 128"),
+            ("172.20.0.0/24", "23", "max-length 23 is shorter than the prefix length 24"),
+        ] {
+            let (code, message) = route_with_max_length(prefix, max_length).unwrap_err();
+            assert_eq!(code, "invalid-max-length");
+            assert!(message.ends_with(problem), "{}", message);
+        }
+        let (code, _) = route("route: 172.20.0.0/24\norigin: AS4242420000\nmax-length: 28\nmax-length: 29\n").unwrap_err();
+        assert_eq!(code, "duplicate-attribute");
+    }
+
+    #[test]
+    fn prefixes() {
+        let (code, message) = route("route: 172.20.0.1/24\norigin: AS4242420000\n").unwrap_err();
+        assert_eq!(code, "host-bits");
+        assert!(message.ends_with("Prefix 172.20.0.1/24 has host bits set"));
+        assert_eq!(route("route6: fd00::1/48\norigin: AS4242420000\n").unwrap_err().0, "host-bits");
+        assert_eq!(route("route: 172.20.0.256/24\norigin: AS4242420000\n").unwrap_err().0, "invalid-prefix");
+        assert_eq!(route("origin: AS4242420000\n").unwrap_err().0, "missing-prefix");
+        assert_eq!(route("route: 172.20.0.0/24\nroute6: fd00::/48\norigin: AS4242420000\n").unwrap_err().0, "duplicate-attribute");
+
+        let object = parse_object(b"route: 172.20.0.0/24\norigin: AS4242420000\n").0;
+        assert_eq!(parse_route_object("data/route/172.20.1.0_24", &object, false).unwrap_err().code, "filename-mismatch");
+        assert_eq!(parse_route_object("data/route6/172.20.0.0_24", &object, true).unwrap_err().code, "wrong-family");
+    }
 
-fn read_lines<P>(path: P) -> io::Result<io::Lines<io::BufReader<File>>>
-where
-    P: AsRef<Path>,
-{
-    let file = File::open(path)?;
-    Ok(io::BufReader::new(file).lines())
-}
\ No newline at end of file
+    #[test]
+    fn as0() {
+        let object = route_with_origin("AS0").unwrap();
+        assert_eq!(object.origins, [0]);
+        object.max_length.set(Some(24));
+        let vrps = object.get_vrps();
+        // An AS0 VRP makes every route for the prefix invalid, including one originated by AS0
+        assert_eq!(crate::vrp::validate(&vrps, &object.prefix, 0).0, crate::vrp::ValidationState::Invalid);
+
+        for content in ["origin: AS0\norigin: AS4242420000\n", "origin: AS4242420000\norigin: AS0\n"] {
+            let (code, message) = route(&format!("route: 172.20.0.0/24\n{}", content)).unwrap_err();
+            assert_eq!(code, "invalid-origin");
+            assert!(message.ends_with("origin AS0 cannot be combined with other origins"));
+        }
+        assert_eq!(route("route: 172.20.0.0/24\norigin: AS0\norigin: AS0\n").unwrap_err().0, "duplicate-origin");
+    }
+}
@@ -0,0 +1,211 @@
+use std::fs;
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::SystemTime;
+use crate::output::JSON_VALIDITY;
+use crate::{io_error, BoxResult};
+
+/// A JSON file holding the same VRPs is still replaced once less than this remains of its
+/// validity, so that it does not expire while the VRPs stay the same.
+const REFRESH_BEFORE_EXPIRY: u64 = JSON_VALIDITY / 2;
+
+/// Atomically replaces `path` with `content` unless the file already holds the
+/// same VRPs and does not expire soon, see `is_current`. Returns whether the file was written.
+pub fn publish(path: &Path, content: &str) -> io::Result<bool> {
+    if let Ok(existing) = fs::read_to_string(path) {
+        let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        if is_current(&existing, content, now) {
+            return Ok(false);
+        }
+    }
+    replace(path, content)?;
+    Ok(true)
+}
+
+/// Whether the published file `existing` holds the same VRPs as `content` and, for JSON, remains
+/// valid for at least `REFRESH_BEFORE_EXPIRY` after `now`.
+fn is_current(existing: &str, content: &str, now: u64) -> bool {
+    if strip_volatile(existing) != strip_volatile(content) {
+        return false;
+    }
+    match existing.starts_with('{') {
+        true => json::parse(existing).ok()
+            .and_then(|parsed| parsed["metadata"]["valid"].as_u64())
+            .is_some_and(|valid| valid >= now.saturating_add(REFRESH_BEFORE_EXPIRY)),
+        false => true,
+    }
+}
+
+/// Atomically replaces `path` with `content` by writing and syncing a temporary file next to it
+/// and renaming it, so that readers never see a partially written file.
+pub fn replace(path: &Path, content: &str) -> io::Result<()> {
+    let directory = match path.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let file_name = path.file_name().ok_or_else(||
+        io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid output path {}", path.display()))
+    )?;
+    let temp_path = directory.join(format!(".{}.tmp.{}", file_name.to_string_lossy(), std::process::id()));
+
+    let result = write_synced(&temp_path, content).and_then(|_| fs::rename(&temp_path, path));
+    if let Err(err) = result {
+        fs::remove_file(&temp_path).ok();
+        return Err(io_error(err, format!("Unable to write output file {}", path.display())));
+    }
+    sync_directory(&directory);
+    Ok(())
+}
+
+fn write_synced(path: &Path, content: &str) -> io::Result<()> {
+    let mut file = OpenOptions::new().write(true).create_new(true).open(path)?;
+    file.write_all(content.as_bytes())?;
+    file.sync_all()
+}
+
+#[cfg(unix)]
+fn sync_directory(directory: &Path) {
+    // Makes the rename itself durable; failure only weakens crash safety.
+    if let Ok(dir) = File::open(directory) {
+        dir.sync_all().ok();
+    }
+}
+
+#[cfg(not(unix))]
+fn sync_directory(_directory: &Path) {}
+
+/// Removes the header comments of the bird format and the generation and expiry time of the
+/// JSON format, which describe the run (time, commit) rather than the VRPs.
+fn strip_volatile(content: &str) -> String {
+    if content.starts_with('{') {
+        if let Ok(mut parsed) = json::parse(content) {
+            let metadata = &mut parsed["metadata"];
+            metadata.remove("generated");
+            metadata.remove("valid");
+            return parsed.dump();
+        }
+    }
+    content.lines()
+        .filter(|line| !line.starts_with('#'))
+        .collect::<Vec<&str>>()
+        .join("\n")
+}
+
+/// An exclusive lock held for the lifetime of the value. The lock is an flock(2) style lock on the
+/// open file, which is released when the process exits, so a crashed run leaves no stale lock.
+/// The file holds the owner's PID for information and is not removed.
+#[derive(Debug)]
+pub struct LockFile {
+    file: File,
+}
+
+impl LockFile {
+    pub fn acquire(path: &Path) -> io::Result<Self> {
+        let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)
+            .map_err(|e| io_error(e, format!("Unable to open lock file {}", path.display())))?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                let mut owner = String::new();
+                file.read_to_string(&mut owner).ok();
+                return Err(io::Error::new(io::ErrorKind::WouldBlock,
+                                          format!("Lock file {} is held by process {}", path.display(), owner.trim())));
+            }
+            Err(TryLockError::Error(err)) => return Err(io_error(err, format!("Unable to lock {}", path.display()))),
+        }
+        file.set_len(0)?;
+        writeln!(file, "{}", std::process::id())?;
+        Ok(LockFile { file })
+    }
+}
+
+impl Drop for LockFile {
+    fn drop(&mut self) {
+        // Removing the file would let a waiting process lock the unlinked file while another creates a new one
+        self.file.set_len(0).ok();
+    }
+}
+
+/// Runs a post-update hook through the system shell.
+pub fn run_hook(command: &str) -> BoxResult<()> {
+    #[cfg(unix)]
+    let status = Command::new("sh").arg("-c").arg(command).status();
+    #[cfg(not(unix))]
+    let status = Command::new("cmd").arg("/C").arg(command).status();
+
+    let status = status.map_err(|e| format!("Unable to run hook '{}': {}", command, e))?;
+    if !status.success() {
+        return Err(format!("Hook '{}' failed with {}", command, status).into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn volatile_headers() {
+        let bird = "# roa_wizard 0.1.10 - Kioubit.dn42\n# Created: 1\n# Commit: aaaa\nroute 172.20.0.0/24 max 28 as 4242420000;\n";
+        let newer = "# roa_wizard 0.1.11 - Kioubit.dn42\n# Created: 2\n# Commit: bbbb\nroute 172.20.0.0/24 max 28 as 4242420000;\n";
+        assert_eq!(strip_volatile(bird), strip_volatile(newer));
+        assert_ne!(strip_volatile(bird), strip_volatile(&newer.replace("max 28", "max 29")));
+
+        let json = r#"{"metadata":{"counts":1,"generated":1,"valid":604801},"roas":[{"prefix":"172.20.0.0/24","maxLength":28,"asn":"AS4242420000"}]}"#;
+        assert_eq!(strip_volatile(json), strip_volatile(&json.replace("\"generated\":1", "\"generated\":2")));
+        assert_eq!(strip_volatile(json), strip_volatile(&json.replace("604801", "604802")));
+        assert_ne!(strip_volatile(json), strip_volatile(&json.replace("AS4242420000", "AS4242420001")));
+        // Other metadata is compared
+        assert_ne!(strip_volatile(json), strip_volatile(&json.replace("\"counts\":1", "\"counts\":2")));
+    }
+
+    fn json_output(generated: u64) -> String {
+        format!(r#"{{"metadata":{{"counts":1,"generated":{},"valid":{}}},"roas":[{{"prefix":"172.20.0.0/24","maxLength":28,"asn":"4242420000"}}]}}"#,
+                generated, generated + JSON_VALIDITY)
+    }
+
+    #[test]
+    fn refresh_before_expiry() {
+        let published = json_output(1_000_000);
+        let expiry = 1_000_000 + JSON_VALIDITY;
+        assert!(is_current(&published, &json_output(1_000_100), 1_000_100));
+        assert!(is_current(&published, &json_output(1_000_100), expiry - REFRESH_BEFORE_EXPIRY));
+        // Rewritten with a new expiry time well before the published one is reached
+        assert!(!is_current(&published, &json_output(1_000_100), expiry - REFRESH_BEFORE_EXPIRY + 1));
+        assert!(!is_current(&published, &json_output(1_000_100), expiry + 1));
+        // Files without an expiry time are only replaced when the VRPs change
+        let bird = "# Created: 1\nroute 172.20.0.0/24 max 28 as 4242420000;\n";
+        assert!(is_current(bird, &bird.replace("Created: 1", "Created: 2"), u64::MAX));
+        assert!(!is_current(&json_output(1).replace(",\"valid\":604801", ""), &json_output(1), 1));
+    }
+
+    #[test]
+    fn publish_files() {
+        let path = std::env::temp_dir().join(format!("roa_wizard-{}-publish.json", std::process::id()));
+        let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs();
+        assert!(publish(&path, &json_output(now)).unwrap());
+        assert!(!publish(&path, &json_output(now + 60)).unwrap());
+        assert_eq!(fs::read_to_string(&path).unwrap(), json_output(now));
+
+        // The published file would expire soon
+        fs::write(&path, json_output(now - JSON_VALIDITY + 60)).unwrap();
+        assert!(publish(&path, &json_output(now)).unwrap());
+        assert_eq!(fs::read_to_string(&path).unwrap(), json_output(now));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn lock_file() {
+        let path = std::env::temp_dir().join(format!("roa_wizard-{}.lock", std::process::id()));
+        let lock = LockFile::acquire(&path).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), format!("{}\n", std::process::id()));
+        assert_eq!(LockFile::acquire(&path).unwrap_err().kind(), io::ErrorKind::WouldBlock);
+        drop(lock);
+        // A file left behind is no obstacle
+        drop(LockFile::acquire(&path).unwrap());
+        fs::remove_file(path).unwrap();
+    }
+}
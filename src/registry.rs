@@ -0,0 +1,352 @@
+//! All objects of the registry, indexed by primary key and attribute value.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+use cidr_utils::cidr::IpCidr;
+use crate::rpsl::{parse_object, RpslObject};
+use crate::source::RegistrySource;
+use crate::vrp::prefix_string;
+use crate::{BoxResult, Warning, Warnings};
+
+/// The object classes of the registry. Each is stored in the directory of the same name below `data/`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ObjectClass {
+    AsBlock,
+    AsSet,
+    AutNum,
+    Dns,
+    Inet6num,
+    Inetnum,
+    KeyCert,
+    Mntner,
+    Organisation,
+    Person,
+    Registry,
+    Role,
+    Route,
+    Route6,
+    RouteSet,
+    Schema,
+    TincKey,
+    TincKeyset,
+    /// A directory not known to this version.
+    Other(String),
+}
+
+impl ObjectClass {
+    pub fn as_str(&self) -> &str {
+        match self {
+            ObjectClass::AsBlock => "as-block",
+            ObjectClass::AsSet => "as-set",
+            ObjectClass::AutNum => "aut-num",
+            ObjectClass::Dns => "dns",
+            ObjectClass::Inet6num => "inet6num",
+            ObjectClass::Inetnum => "inetnum",
+            ObjectClass::KeyCert => "key-cert",
+            ObjectClass::Mntner => "mntner",
+            ObjectClass::Organisation => "organisation",
+            ObjectClass::Person => "person",
+            ObjectClass::Registry => "registry",
+            ObjectClass::Role => "role",
+            ObjectClass::Route => "route",
+            ObjectClass::Route6 => "route6",
+            ObjectClass::RouteSet => "route-set",
+            ObjectClass::Schema => "schema",
+            ObjectClass::TincKey => "tinc-key",
+            ObjectClass::TincKeyset => "tinc-keyset",
+            ObjectClass::Other(name) => name,
+        }
+    }
+
+    /// Classes whose file names are prefixes with `_` in place of `/`.
+    pub fn is_prefix(&self) -> bool {
+        matches!(self, ObjectClass::Inetnum | ObjectClass::Inet6num | ObjectClass::Route | ObjectClass::Route6)
+    }
+
+    /// The class stored in the directory `name`.
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "as-block" => ObjectClass::AsBlock,
+            "as-set" => ObjectClass::AsSet,
+            "aut-num" => ObjectClass::AutNum,
+            "dns" => ObjectClass::Dns,
+            "inet6num" => ObjectClass::Inet6num,
+            "inetnum" => ObjectClass::Inetnum,
+            "key-cert" => ObjectClass::KeyCert,
+            "mntner" => ObjectClass::Mntner,
+            "organisation" => ObjectClass::Organisation,
+            "person" => ObjectClass::Person,
+            "registry" => ObjectClass::Registry,
+            "role" => ObjectClass::Role,
+            "route" => ObjectClass::Route,
+            "route6" => ObjectClass::Route6,
+            "route-set" => ObjectClass::RouteSet,
+            "schema" => ObjectClass::Schema,
+            "tinc-key" => ObjectClass::TincKey,
+            "tinc-keyset" => ObjectClass::TincKeyset,
+            other => ObjectClass::Other(other.to_owned()),
+        }
+    }
+}
+
+impl fmt::Display for ObjectClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RegistryObject {
+    pub class: ObjectClass,
+    /// The primary key as given by the file name, e.g. `AS4242420000` or `172.20.0.0/24`.
+    pub key: String,
+    /// Path of the object file relative to the registry root.
+    pub path: String,
+    pub object: RpslObject,
+}
+
+impl RegistryObject {
+    /// The prefix of inetnum, inet6num, route and route6 objects.
+    pub fn prefix(&self) -> Option<IpCidr> {
+        match self.class.is_prefix() {
+            true => IpCidr::from_str(&self.key).ok(),
+            false => None,
+        }
+    }
+
+    /// The AS number of aut-num objects.
+    pub fn asn(&self) -> Option<u32> {
+        match self.class {
+            ObjectClass::AutNum => self.key.strip_prefix("AS")?.parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.object.get(key)
+    }
+
+    pub fn get_all<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.object.get_all(key)
+    }
+
+    pub fn maintainers(&self) -> impl Iterator<Item = &str> {
+        self.object.get_all("mnt-by")
+    }
+}
+
+/// Whether `path` is the file of a route or route6 object. Their malformed lines are reported when
+/// the route objects are read, see `get_roa_objects`.
+pub fn is_route_file(path: &str) -> bool {
+    path.starts_with("data/route/") || path.starts_with("data/route6/")
+}
+
+/// The objects of every class directory below `data/`. Lookups ignore ASCII case and the notation of prefixes.
+#[derive(Debug, Clone, Default)]
+pub struct RegistryDatabase {
+    objects: Vec<RegistryObject>,
+    by_key: HashMap<(ObjectClass, String), usize>,
+    by_class: HashMap<ObjectClass, Vec<usize>>,
+    /// Objects by attribute name and lower case value of single line attributes.
+    by_value: HashMap<(String, String), Vec<usize>>,
+}
+
+impl RegistryDatabase {
+    /// Reads all objects of the registry. Malformed lines and unreadable files are reported as warnings.
+    pub fn read(source: &dyn RegistrySource) -> BoxResult<(Self, Warnings)> {
+        let mut database = RegistryDatabase::default();
+        let mut warnings: Warnings = Vec::new();
+        let mut directories = source.list_directory("data")?;
+        directories.sort();
+        for directory in directories {
+            let path = format!("data/{}", directory);
+            let mut names = match source.list_directory(&path) {
+                Ok(names) => names,
+                // Files such as filter.txt
+                Err(_) if source.read_file(&path).is_ok() => continue,
+                Err(e) => {
+                    warnings.push(Warning::new("unreadable-directory", &path, format!("Error in directory: {}: {}", source.describe(&path), e)));
+                    continue;
+                }
+            };
+            names.sort();
+            let class = ObjectClass::from_name(&directory);
+            for name in names {
+                let file = format!("{}/{}", path, name);
+                let content = match source.read_file(&file) {
+                    Ok(content) => content,
+                    Err(e) => {
+                        warnings.push(Warning::new("unreadable-file", &file, format!("Error in file: {}: {}", source.describe(&file), e)));
+                        continue;
+                    }
+                };
+                let (object, diagnostics) = parse_object(&content);
+                for diagnostic in diagnostics {
+                    warnings.push(Warning::new(diagnostic.code, &file, format!("Error in file: {}: {}", source.describe(&file), diagnostic)));
+                }
+                let key = match class.is_prefix() {
+                    true => name.replace('_', "/"),
+                    false => name,
+                };
+                database.insert(RegistryObject { class: class.clone(), key, path: file, object });
+            }
+        }
+        Ok((database, warnings))
+    }
+
+    /// Adds an object. Lookups by primary key return the first object added for a key.
+    pub fn insert(&mut self, object: RegistryObject) {
+        let index = self.objects.len();
+        self.by_key.entry((object.class.clone(), lookup_key(&object.class, &object.key))).or_insert(index);
+        self.by_class.entry(object.class.clone()).or_default().push(index);
+        index_values(&mut self.by_value, index, &object);
+        self.objects.push(object);
+    }
+
+    /// The object of `class` with the primary key `key`, e.g. `(ObjectClass::Mntner, "FOO-MNT")`.
+    pub fn get(&self, class: &ObjectClass, key: &str) -> Option<&RegistryObject> {
+        self.by_key.get(&(class.clone(), lookup_key(class, key))).map(|i| &self.objects[*i])
+    }
+
+    /// Checks that the file name of every object matches its primary key and that no two files
+    /// hold the same object. Route objects are checked for the former when they are parsed.
+    pub fn check_keys(&self) -> Warnings {
+        let mut warnings: Warnings = Vec::new();
+        for (index, object) in self.objects.iter().enumerate() {
+            if let Err((code, err)) = check_key(object) {
+                warnings.push(Warning::in_file(code, &object.path, err));
+            }
+            let first = self.by_key[&(object.class.clone(), lookup_key(&object.class, &object.key))];
+            if first != index {
+                warnings.push(Warning::in_file("duplicate-object", &object.path, format!("duplicate of {}", self.objects[first].path)));
+            }
+        }
+        warnings
+    }
+
+    /// All objects of `class` in file name order.
+    pub fn objects(&self, class: &ObjectClass) -> impl Iterator<Item = &RegistryObject> {
+        self.by_class.get(class).into_iter().flatten().map(|i| &self.objects[*i])
+    }
+
+    /// All objects with an attribute `key` of value `value`, e.g. all objects with `mnt-by: FOO-MNT`.
+    pub fn find(&self, key: &str, value: &str) -> impl Iterator<Item = &RegistryObject> {
+        self.by_value.get(&(key.to_owned(), value.to_ascii_lowercase())).into_iter().flatten().map(|i| &self.objects[*i])
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &RegistryObject> {
+        self.objects.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+}
+
+/// The primary key as used for lookups: prefixes in canonical notation, other keys in lower case.
+fn lookup_key(class: &ObjectClass, key: &str) -> String {
+    match class.is_prefix().then(|| IpCidr::from_str(key).ok()).flatten() {
+        Some(prefix) => prefix_string(&prefix),
+        None => key.to_ascii_lowercase(),
+    }
+}
+
+/// Returns the warning code and message of a file name not matching the object.
+fn check_key(object: &RegistryObject) -> Result<(), (&'static str, String)> {
+    let expected_class = match object.class {
+        ObjectClass::Dns => "domain",
+        ref class => class.as_str(),
+    };
+    let class = object.object.class().ok_or(("empty-object", "empty object".to_owned()))?;
+    if class != expected_class {
+        return Err(("class-mismatch", format!("object class {} does not match the directory {}", class, object.class)));
+    }
+    let primary_key = object.object.primary_key().unwrap_or_default();
+    let expect_key = |key: &str| match object.key == key {
+        true => Ok(()),
+        false => Err(("key-mismatch", format!("file name does not match the primary key {}", key))),
+    };
+    let missing = |key: &str| ("missing-attribute", format!("missing {} attribute", key));
+    if object.class.is_prefix() {
+        let Some(prefix) = object.prefix() else {
+            // Reported as host-bits or invalid-prefix when route objects are parsed
+            if let ObjectClass::Route | ObjectClass::Route6 = object.class {
+                return Ok(());
+            }
+            return Err(("invalid-prefix", "file name is not a valid prefix".to_owned()));
+        };
+        if object.key != prefix_string(&prefix) {
+            return Err(("non-canonical-name", format!("file name is not in canonical notation {}", prefix_string(&prefix).replace('/', "_"))));
+        }
+    }
+    match object.class {
+        ObjectClass::Route | ObjectClass::Route6 => Ok(()),
+        ObjectClass::Inetnum | ObjectClass::Inet6num => {
+            let prefix = object.prefix().ok_or(("invalid-prefix", "file name is not a valid prefix".to_owned()))?;
+            let cidr = object.get("cidr").ok_or_else(|| missing("cidr"))?;
+            if IpCidr::from_str(cidr).ok() != Some(prefix) {
+                return Err(("key-mismatch", format!("cidr {} does not match the file name", cidr)));
+            }
+            let range = primary_key.split_once('-')
+                .and_then(|(first, last)| Some((IpAddr::from_str(first.trim()).ok()?, IpAddr::from_str(last.trim()).ok()?)));
+            if range != Some((prefix.first_address(), prefix.last_address())) {
+                return Err(("key-mismatch", format!("{} range {} does not match the prefix {}", object.class, primary_key, prefix_string(&prefix))));
+            }
+            Ok(())
+        }
+        ObjectClass::Person | ObjectClass::Role => expect_key(object.get("nic-hdl").ok_or_else(|| missing("nic-hdl"))?),
+        ObjectClass::AsBlock => {
+            let range: Vec<&str> = primary_key.split('-').map(str::trim).collect();
+            expect_key(&range.join("_"))
+        }
+        ObjectClass::Dns => {
+            if object.key != object.key.to_ascii_lowercase() {
+                return Err(("dns-case", "DNS file names must be lower case".to_owned()));
+            }
+            match primary_key.eq_ignore_ascii_case(&object.key) {
+                true => Ok(()),
+                false => Err(("key-mismatch", format!("file name does not match the domain {}", primary_key))),
+            }
+        }
+        _ => expect_key(primary_key),
+    }
+}
+
+fn index_values(by_value: &mut HashMap<(String, String), Vec<usize>>, index: usize, object: &RegistryObject) {
+    for attribute in &object.object.attributes {
+        if attribute.value.contains('\n') {
+            continue;
+        }
+        let indexes = by_value.entry((attribute.key.clone(), attribute.value.to_ascii_lowercase())).or_default();
+        if indexes.last() != Some(&index) {
+            indexes.push(index);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::MemorySource;
+
+    fn codes(warnings: &Warnings) -> Vec<(&str, &str)> {
+        warnings.iter().map(|w| (w.code, w.object.as_str())).collect()
+    }
+
+    #[test]
+    fn host_bits_in_file_names() {
+        let mut source = MemorySource::new();
+        source.insert("data/route/172.20.0.1_24", "route: 172.20.0.1/24\norigin: AS4242420000\n");
+        source.insert("data/inetnum/172.20.0.1_24", "inetnum: 172.20.0.0 - 172.20.0.255\ncidr: 172.20.0.1/24\n");
+        let (database, warnings) = RegistryDatabase::read(&source).unwrap();
+        assert!(warnings.is_empty());
+        // The route object is reported once, when it is parsed
+        assert_eq!(codes(&database.check_keys()), [("invalid-prefix", "data/inetnum/172.20.0.1_24")]);
+    }
+}
@@ -0,0 +1,217 @@
+//! Parser for RPSL objects (RFC 2622) as stored in the registry, one object per file.
+
+use std::fmt;
+
+/// Lines beyond this count, including comments and continuation lines, are ignored. Registry
+/// objects have far fewer.
+pub const MAX_LINES: usize = 10000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attribute {
+    /// The attribute name in lower case.
+    pub key: String,
+    /// The value without surrounding whitespace. Continuation lines are joined with `\n`.
+    pub value: String,
+    /// Line number of the attribute name, starting at 1.
+    pub line: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RpslObject {
+    /// The attributes in file order. The first one names the class and primary key.
+    pub attributes: Vec<Attribute>,
+}
+
+impl RpslObject {
+    /// The object class, e.g. `route6` or `aut-num`.
+    pub fn class(&self) -> Option<&str> {
+        self.attributes.first().map(|a| a.key.as_str())
+    }
+
+    pub fn primary_key(&self) -> Option<&str> {
+        self.attributes.first().map(|a| a.value.as_str())
+    }
+
+    /// The value of the first attribute named `key`.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.attributes.iter().find(|a| a.key == key).map(|a| a.value.as_str())
+    }
+
+    pub fn get_all<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.attributes.iter().filter(move |a| a.key == key).map(|a| a.value.as_str())
+    }
+
+    /// The attribute named `key` for attributes that may occur at most once.
+    pub fn single(&self, key: &str) -> Result<Option<&Attribute>, String> {
+        let mut matches = self.attributes.iter().filter(|a| a.key == key);
+        let first = matches.next();
+        if let (Some(first), Some(second)) = (first, matches.next()) {
+            return Err(format!("{} attribute given more than once (lines {} and {})", key, first.line, second.line));
+        }
+        Ok(first)
+    }
+}
+
+/// Formats the object as stored in the registry, with values starting at column 21.
+impl fmt::Display for RpslObject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for attribute in &self.attributes {
+            let mut lines = attribute.value.split('\n');
+            let first = format!("{:<19} {}", format!("{}:", attribute.key), lines.next().unwrap_or_default());
+            writeln!(f, "{}", first.trim_end())?;
+            for line in lines {
+                match line.is_empty() {
+                    true => writeln!(f, "+")?,
+                    false => writeln!(f, "{:20}{}", "", line)?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A line of an object file that could not be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub line: usize,
+    /// Stable identifier of the kind of problem, e.g. `malformed-line`.
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// Parses an object file. Lines starting with `#` or `%` are comments, lines starting with
+/// whitespace or `+` continue the previous attribute. Malformed lines are skipped and reported.
+pub fn parse_object(content: &[u8]) -> (RpslObject, Vec<Diagnostic>) {
+    let mut object = RpslObject::default();
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+    let mut blank_line: Option<usize> = None;
+    let content = content.strip_suffix(b"\n").unwrap_or(content);
+    for (index, line) in content.split(|b| *b == b'\n').enumerate() {
+        let number = index + 1;
+        if number > MAX_LINES {
+            diagnostics.push(Diagnostic {
+                line: number,
+                code: "too-many-lines",
+                message: format!("more than {} lines, ignoring the rest", MAX_LINES),
+            });
+            break;
+        }
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        let line = match std::str::from_utf8(line) {
+            Ok(line) => line.into(),
+            Err(_) => {
+                diagnostics.push(Diagnostic { line: number, code: "invalid-utf8", message: "invalid UTF-8".to_owned() });
+                String::from_utf8_lossy(line)
+            }
+        };
+
+        if line.starts_with(['#', '%']) {
+            continue;
+        }
+        if line.trim().is_empty() {
+            blank_line.get_or_insert(number);
+            continue;
+        }
+        // A blank line ends an object, but files hold a single object
+        if let Some(blank) = blank_line.take() {
+            diagnostics.push(Diagnostic { line: blank, code: "blank-line", message: "blank line within object".to_owned() });
+        }
+
+        if let Some(continuation) = line.strip_prefix('+').or_else(|| line.strip_prefix([' ', '\t'])) {
+            match object.attributes.last_mut() {
+                Some(attribute) => {
+                    attribute.value.push('\n');
+                    attribute.value.push_str(continuation.trim());
+                }
+                None => diagnostics.push(Diagnostic {
+                    line: number,
+                    code: "orphan-continuation",
+                    message: "continuation line without attribute".to_owned(),
+                }),
+            }
+            continue;
+        }
+
+        let valid_key = |key: &str| !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        match line.split_once(':') {
+            Some((key, value)) if valid_key(key.trim_end()) => object.attributes.push(Attribute {
+                key: key.trim_end().to_ascii_lowercase(),
+                value: value.trim().to_owned(),
+                line: number,
+            }),
+            _ => diagnostics.push(Diagnostic { line: number, code: "malformed-line", message: format!("malformed line '{}'", line.trim_end()) }),
+        }
+    }
+    (object, diagnostics)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attributes(object: &RpslObject) -> Vec<(&str, &str, usize)> {
+        object.attributes.iter().map(|a| (a.key.as_str(), a.value.as_str(), a.line)).collect()
+    }
+
+    fn codes(diagnostics: &[Diagnostic]) -> Vec<(&str, usize)> {
+        diagnostics.iter().map(|d| (d.code, d.line)).collect()
+    }
+
+    #[test]
+    fn crlf_and_comments() {
+        let (object, diagnostics) = parse_object(b"route:  172.20.0.0/24\r\n# comment\r\n% comment\r\nOrigin: AS4242420000 \r\n");
+        assert!(diagnostics.is_empty());
+        assert_eq!(attributes(&object), [("route", "172.20.0.0/24", 1), ("origin", "AS4242420000", 4)]);
+        assert_eq!(object.class(), Some("route"));
+        assert_eq!(object.primary_key(), Some("172.20.0.0/24"));
+    }
+
+    #[test]
+    fn continuation_lines() {
+        let (object, diagnostics) = parse_object(b"remarks: first\n  second\n+\n\tthird\nmnt-by: FOO-MNT\n");
+        assert!(diagnostics.is_empty());
+        assert_eq!(attributes(&object), [("remarks", "first\nsecond\n\nthird", 1), ("mnt-by", "FOO-MNT", 5)]);
+        assert_eq!(object.to_string(), "remarks:            first\n                    second\n+\n                    third\nmnt-by:             FOO-MNT\n");
+        assert_eq!(parse_object(object.to_string().as_bytes()).0, object);
+    }
+
+    #[test]
+    fn malformed_lines() {
+        let (object, diagnostics) = parse_object(b" orphan\nroute: 172.20.0.0/24\nno colon\n\nbad key: x\norigin: AS4242420000\ndescr: \xff\n");
+        assert_eq!(codes(&diagnostics), [("orphan-continuation", 1), ("malformed-line", 3), ("blank-line", 4), ("malformed-line", 5), ("invalid-utf8", 7)]);
+        assert_eq!(object.get_all("route").count(), 1);
+        assert_eq!(object.get("origin"), Some("AS4242420000"));
+        assert_eq!(object.get("descr"), Some("\u{fffd}"));
+        // Blank lines at the end are not reported
+        assert!(parse_object(b"route: 172.20.0.0/24\n\n\n").1.is_empty());
+    }
+
+    #[test]
+    fn single_attributes() {
+        let (object, _) = parse_object(b"route: 172.20.0.0/24\norigin: AS1\norigin: AS2\n");
+        assert_eq!(object.single("route").unwrap().map(|a| a.line), Some(1));
+        assert!(object.single("max-length").unwrap().is_none());
+        assert_eq!(object.single("origin").unwrap_err(), "origin attribute given more than once (lines 2 and 3)");
+    }
+
+    #[test]
+    fn line_limit() {
+        let mut content = b"remarks: x\n".to_vec();
+        content.extend(b"+\n".repeat(MAX_LINES - 1));
+        let (object, diagnostics) = parse_object(&content);
+        assert!(diagnostics.is_empty());
+        assert_eq!(object.attributes[0].value.len(), 1 + MAX_LINES - 1);
+
+        // Continuation lines count towards the limit
+        content.extend(b"+\n".repeat(10));
+        let (object, diagnostics) = parse_object(&content);
+        assert_eq!(codes(&diagnostics), [("too-many-lines", MAX_LINES + 1)]);
+        assert_eq!(object.attributes[0].value.len(), 1 + MAX_LINES - 1);
+    }
+}
@@ -0,0 +1,324 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use crate::baseline::Baseline;
+use crate::config::{Config, SourceKind};
+use crate::import::read_roa_file;
+use crate::merge::{merge_sources, render_conflicts, Conflict, MergedVrps};
+use crate::publish::{publish, run_hook, LockFile};
+use crate::safety::SafetyChecks;
+use crate::slurm::Slurm;
+use crate::snapshot::SnapshotStore;
+use crate::source::{self, RegistrySource};
+use crate::parse::RouteObject;
+use crate::{exit_code_for_error, get_vrps_and_objects, render_with_sources, BoxResult, Family, SourceSelection, Vrp, Warning, Warnings, write_output, EXIT_HOOK_FAILURE, EXIT_IO_ERROR, EXIT_LOCKED, EXIT_SAFETY_CHECK, EXIT_STRICT_FAILURE};
+
+/// Settings given on the command line that extend the config file.
+#[derive(Debug, Clone, Default)]
+pub struct RunOptions {
+    pub strict: bool,
+    pub slurm_files: Vec<PathBuf>,
+    pub lock_file: Option<PathBuf>,
+    /// Takes precedence over the snapshot store of the config file.
+    pub snapshot_store: Option<PathBuf>,
+    /// Takes precedence over the revision of the config file.
+    pub revision: Option<String>,
+    /// Added to the safety checks of the config file, `max_drop_percent` takes precedence.
+    pub safety: SafetyChecks,
+    /// Takes precedence over the baseline file of the config file.
+    pub baseline: Option<PathBuf>,
+}
+
+#[derive(Debug, Default)]
+pub struct RunSummary {
+    pub vrp_count: usize,
+    /// Warnings not accepted by the baseline, followed by safety checks that could not be done.
+    pub warnings: Warnings,
+    /// Baseline entries that no longer occur.
+    pub stale_baseline: Vec<String>,
+    /// VRPs dropped or overlapping other origins when merging several sources.
+    pub conflicts: Vec<Conflict>,
+    pub changed: Vec<PathBuf>,
+}
+
+#[derive(Debug)]
+pub enum RunError {
+    Locked(String),
+    Registry(Box<dyn Error + Send + Sync>),
+    Strict,
+    Safety(Vec<String>),
+    Publish(Vec<String>),
+    Hook(Vec<String>),
+}
+
+impl RunError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            RunError::Locked(_) => EXIT_LOCKED,
+            RunError::Registry(err) => exit_code_for_error(err.as_ref()),
+            RunError::Strict => EXIT_STRICT_FAILURE,
+            RunError::Safety(_) => EXIT_SAFETY_CHECK,
+            RunError::Publish(_) => EXIT_IO_ERROR,
+            RunError::Hook(_) => EXIT_HOOK_FAILURE,
+        }
+    }
+}
+
+impl fmt::Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RunError::Locked(err) => write!(f, "{}", err),
+            RunError::Registry(err) => write!(f, "{}", err),
+            RunError::Strict => write!(f, "Warnings occurred and strict mode is enabled"),
+            RunError::Safety(errors) => write!(f, "Refusing to publish: {}", errors.join("; ")),
+            RunError::Publish(errors) | RunError::Hook(errors) => write!(f, "{}", errors.join("; ")),
+        }
+    }
+}
+
+/// Parses the registry once, publishes every configured output and runs the
+/// post-update hooks of the outputs that changed. `summary` is filled in as the run
+/// progresses, so that its warnings can be reported when the run fails.
+pub fn run_config(config: &Config, options: &RunOptions, summary: &mut RunSummary) -> Result<(), RunError> {
+    let _lock = match options.lock_file.as_ref().or(config.lock_file.as_ref()) {
+        Some(path) => Some(LockFile::acquire(path).map_err(|e| RunError::Locked(e.to_string()))?),
+        None => None,
+    };
+
+    let family = config.outputs.iter().fold(config.outputs[0].family, |f, o| f.union(o.family));
+    let mut slurm_files = config.slurm_files.clone();
+    slurm_files.extend(options.slurm_files.iter().cloned());
+    let revision = options.revision.as_deref().or(config.revision.as_deref());
+    let source = source::open(&config.registry, revision).map_err(RunError::Registry)?;
+    let mut object_sources = ObjectSources::new();
+    let (vrps, mut warnings, merged) = match config.sources.len() {
+        1 => {
+            let (vrps, objects, warnings) = get_vrps_and_objects(source.as_ref(), family, &config.filters, &slurm_files)
+                .map_err(RunError::Registry)?;
+            add_object_sources(&mut object_sources, &objects);
+            (vrps, warnings, None)
+        }
+        _ => {
+            let (merged, warnings) = read_sources(config, source.as_ref(), family, &slurm_files, &mut object_sources)
+                .map_err(RunError::Registry)?;
+            (merged.vrps.clone(), warnings, Some(merged))
+        }
+    };
+    if let Some(path) = options.baseline.as_ref().or(config.baseline.as_ref()) {
+        summary.stale_baseline = Baseline::read(path).map_err(RunError::Registry)?.filter(&mut warnings);
+    }
+    let selected: Vec<Cow<[Vrp]>> = config.outputs.iter()
+        .map(|output| match output.sources.is_empty() {
+            true => Cow::Borrowed(vrps.as_slice()),
+            false => Cow::Owned(select_sources(&vrps, &object_sources, &output.sources)),
+        })
+        .collect();
+    summary.vrp_count = vrps.len();
+    summary.warnings = warnings;
+    summary.conflicts = merged.iter().flat_map(|m| m.conflicts.iter().cloned()).collect();
+    if (config.strict || options.strict) && !summary.warnings.is_empty() {
+        return Err(RunError::Strict);
+    }
+
+    let mut safety = config.safety.clone();
+    safety.max_drop_percent = options.safety.max_drop_percent.or(safety.max_drop_percent);
+    safety.must_exist.extend(options.safety.must_exist.iter().cloned());
+    // Nothing is published unless every output passes, keeping the outputs consistent
+    let mut safety_failures: Vec<String> = Vec::new();
+    for (output, vrps) in config.outputs.iter().zip(&selected) {
+        let (failures, warnings) = safety.check(vrps, output.family, output.path.as_deref());
+        for failure in failures {
+            if !safety_failures.contains(&failure) {
+                safety_failures.push(failure);
+            }
+        }
+        summary.warnings.extend(warnings);
+    }
+    if !safety_failures.is_empty() {
+        return Err(RunError::Safety(safety_failures));
+    }
+
+    let mut hooks: Vec<&str> = Vec::new();
+    let mut publish_errors: Vec<String> = Vec::new();
+    let commit_hash = source.commit_hash();
+    for (output, vrps) in config.outputs.iter().zip(&selected) {
+        let rendered = render_with_sources(vrps, output.format, output.family, commit_hash.clone(), merged.as_ref().map(|m| &m.sources));
+        let result = match &output.path {
+            Some(path) => publish(path, &rendered).map(|changed| changed.then_some(path)),
+            None => write_output(None, &rendered).map(|_| None),
+        };
+        match result {
+            Ok(Some(path)) => {
+                summary.changed.push(path.clone());
+                if let Some(hook) = &output.on_change {
+                    if !hooks.contains(&hook.as_str()) {
+                        hooks.push(hook);
+                    }
+                }
+            }
+            Ok(None) => {}
+            Err(err) => publish_errors.push(err.to_string()),
+        }
+    }
+
+    if let (Some(path), Some(merged)) = (&config.conflict_report, &merged) {
+        if let Err(err) = publish(path, &render_conflicts(&merged.conflicts)) {
+            publish_errors.push(err.to_string());
+        }
+    }
+
+    if let Some(store) = options.snapshot_store.as_ref().or(config.snapshot_store.as_ref()) {
+        if let Err(err) = append_snapshot(store, &vrps, family, source.as_ref()) {
+            publish_errors.push(err.to_string());
+        }
+    }
+
+    // Hooks of outputs that did change still run so that they are not left out of sync
+    let hook_errors: Vec<String> = hooks.iter().filter_map(|hook| run_hook(hook).err().map(|e| e.to_string())).collect();
+    if !publish_errors.is_empty() {
+        return Err(RunError::Publish(publish_errors));
+    }
+    if !hook_errors.is_empty() {
+        return Err(RunError::Hook(hook_errors));
+    }
+    Ok(())
+}
+
+/// Reads the VRPs of every configured source and merges them by precedence. SLURM exceptions
+/// apply to the merged VRPs, VRPs added by them are attributed to the source `slurm`.
+fn read_sources(config: &Config, main: &dyn RegistrySource, family: Family, slurm_files: &[PathBuf], object_sources: &mut ObjectSources) -> BoxResult<(MergedVrps, Warnings)> {
+    let slurm = Slurm::read_files(slurm_files)?;
+    let mut sets: Vec<(String, Vec<Vrp>)> = Vec::new();
+    let mut warnings: Warnings = Vec::new();
+    for entry in &config.sources {
+        let result = match &entry.kind {
+            SourceKind::Main => get_vrps_and_objects(main, family, &config.filters, &[]),
+            SourceKind::Registry { path, revision, filters } => source::open(path, revision.as_deref())
+                .and_then(|source| get_vrps_and_objects(source.as_ref(), family, filters, &[])),
+            SourceKind::Vrps(path) => read_roa_file(path)
+                .map(|vrps| (vrps.into_iter().filter(|v| family.contains(&v.prefix)).collect(), Vec::new(), Vec::new())),
+        };
+        let (vrps, objects, source_warnings) = result?;
+        if let SourceKind::Vrps(_) = entry.kind {
+            for vrp in &vrps {
+                object_sources.entry(vrp.clone()).or_default().push(Some(entry.name.clone()));
+            }
+        }
+        add_object_sources(object_sources, &objects);
+        warnings.extend(source_warnings.into_iter().map(|w|
+            Warning::new(w.code, format!("{}:{}", entry.name, w.object), format!("{}: {}", entry.name, w.message))
+        ));
+        sets.push((entry.name.clone(), vrps));
+    }
+
+    let mut merged = merge_sources(&sets);
+    if !slurm.is_empty() {
+        slurm.apply(&mut merged.vrps);
+        merged.vrps.retain(|v| family.contains(&v.prefix));
+        for vrp in &merged.vrps {
+            if !merged.sources.contains_key(vrp) {
+                merged.sources.insert(vrp.clone(), "slurm".to_owned());
+            }
+        }
+    }
+    Ok((merged, warnings))
+}
+
+/// The `source:` attributes of the route objects each VRP comes from. VRPs of ROA files are
+/// attributed to the name of their source in the config.
+type ObjectSources = HashMap<Vrp, Vec<Option<String>>>;
+
+fn add_object_sources(object_sources: &mut ObjectSources, objects: &[RouteObject]) {
+    for object in objects {
+        for vrp in object.get_vrps() {
+            object_sources.entry(vrp).or_default().push(object.source.clone());
+        }
+    }
+}
+
+/// The VRPs with a selected source. VRPs of SLURM assertions have no source and are always selected.
+fn select_sources(vrps: &[Vrp], object_sources: &ObjectSources, selection: &SourceSelection) -> Vec<Vrp> {
+    vrps.iter()
+        .filter(|v| object_sources.get(v).is_none_or(|sources| sources.iter().any(|s| selection.matches(s.as_deref()))))
+        .cloned()
+        .collect()
+}
+
+/// Records the VRPs of a generation run in the snapshot store at `store`.
+pub fn append_snapshot(store: &Path, vrps: &[Vrp], family: Family, source: &dyn RegistrySource) -> BoxResult<bool> {
+    let time = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map_or(0, |d| d.as_secs() as i64);
+    SnapshotStore::new(store).append(vrps, family, source.commit_hash().as_deref(), time)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+    use std::fs;
+    use crate::config::read_config;
+
+    fn write_registry(directory: &Path) {
+        let _ = fs::remove_dir_all(directory);
+        for dir in ["data/route", "data/route6"] {
+            fs::create_dir_all(directory.join(dir)).unwrap();
+        }
+        fs::write(directory.join("data/filter.txt"), "1 permit 172.20.0.0/14 21 29\n99 deny 0.0.0.0/0 0 32\n").unwrap();
+        fs::write(directory.join("data/filter6.txt"), "1 permit fd00::/8 44 64\n99 deny ::/0 0 128\n").unwrap();
+        fs::write(directory.join("data/route/172.20.0.0_24"),
+            "route: 172.20.0.0/24\norigin: AS4242420000\nmnt-by: FOO-MNT\nsource: DN42\n").unwrap();
+        fs::write(directory.join("data/route/172.20.1.0_24"),
+            "route: 172.20.1.0/24\norigin: AS4242420001\nmnt-by: BAR-MNT\nsource: NEONETWORK\n").unwrap();
+        fs::write(directory.join("data/route6/fd00:1::_48"),
+            "route6: fd00:1::/48\norigin: AS4242420000\nmnt-by: FOO-MNT\nsource: DN42\n").unwrap();
+    }
+
+    #[test]
+    fn several_outputs() {
+        let directory = std::env::temp_dir().join(format!("roa_wizard-{}-run", std::process::id()));
+        write_registry(&directory.join("registry"));
+        let hook_log = directory.join("hook.log");
+        let hook = format!("echo run >> '{}'", hook_log.display());
+        let config_file = directory.join("config.json");
+        fs::write(&config_file, json::stringify(json::object! {
+            "registry": "registry",
+            "outputs": [
+                {"format": "bird", "family": "v4", "path": "roa4.conf", "on_change": hook.as_str()},
+                {"format": "bird", "family": "v6", "path": "roa6.conf", "on_change": hook.as_str()},
+                {"format": "json", "path": "roa.json"},
+                {"format": "bird", "family": "v4", "path": "neo.conf", "include_sources": ["NEONETWORK"]},
+            ],
+        })).unwrap();
+        let config = read_config(&config_file).unwrap();
+
+        let mut summary = RunSummary::default();
+        run_config(&config, &RunOptions::default(), &mut summary).unwrap();
+        assert_eq!(summary.vrp_count, 3);
+        assert!(summary.warnings.is_empty());
+        assert_eq!(summary.changed.len(), 4);
+        let read = |name: &str| fs::read_to_string(directory.join(name)).unwrap();
+        let routes = |name: &str| read(name).lines().filter(|l| l.starts_with("route ")).map(str::to_owned).collect::<BTreeSet<_>>();
+        assert_eq!(routes("roa4.conf"), BTreeSet::from(["route 172.20.0.0/24 max 29 as 4242420000;".to_owned(), "route 172.20.1.0/24 max 29 as 4242420001;".to_owned()]));
+        assert_eq!(routes("roa6.conf"), BTreeSet::from(["route fd00:1::/48 max 64 as 4242420000;".to_owned()]));
+        assert_eq!(routes("neo.conf"), BTreeSet::from(["route 172.20.1.0/24 max 29 as 4242420001;".to_owned()]));
+        assert_eq!(json::parse(&read("roa.json")).unwrap()["roas"].len(), 3);
+        // The hook shared by both outputs runs once
+        assert_eq!(read("hook.log"), "run\n");
+
+        // Nothing changed, so nothing is rewritten and no hook runs
+        let mut summary = RunSummary::default();
+        run_config(&config, &RunOptions::default(), &mut summary).unwrap();
+        assert!(summary.changed.is_empty());
+        assert_eq!(read("hook.log"), "run\n");
+
+        // Only the outputs affected by a change are rewritten
+        fs::remove_file(directory.join("registry/data/route6/fd00:1::_48")).unwrap();
+        let mut summary = RunSummary::default();
+        run_config(&config, &RunOptions::default(), &mut summary).unwrap();
+        assert_eq!(summary.changed, [directory.join("roa6.conf"), directory.join("roa.json")]);
+        assert_eq!(read("hook.log"), "run\nrun\n");
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
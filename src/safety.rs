@@ -0,0 +1,205 @@
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use cidr_utils::cidr::IpCidr;
+use crate::import::{parse_asn, parse_prefix, read_roa_file};
+use crate::vrp::{prefix_string, Vrp};
+use crate::{BoxResult, Family, Warning, Warnings};
+
+/// Guardrails evaluated before an output replaces the previously published file.
+#[derive(Debug, Clone, Default)]
+pub struct SafetyChecks {
+    /// Refuse to publish if the number of VRPs drops by more than this percentage.
+    pub max_drop_percent: Option<f64>,
+    /// VRPs that have to be present in every output of their address family.
+    pub must_exist: Vec<VrpSpec>,
+}
+
+/// A VRP that has to exist, written as `<prefix> [max <length>] [as] <asn>`.
+/// Without a max length any max length matches.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VrpSpec {
+    pub prefix: IpCidr,
+    pub max_length: Option<u8>,
+    pub asn: u32,
+}
+
+impl VrpSpec {
+    fn matches(&self, vrp: &Vrp) -> bool {
+        self.prefix == vrp.prefix && self.asn == vrp.asn && self.max_length.is_none_or(|m| m == vrp.max_length)
+    }
+}
+
+impl FromStr for VrpSpec {
+    type Err = Box<dyn std::error::Error + Send + Sync>;
+    fn from_str(s: &str) -> BoxResult<Self> {
+        let mut tokens = s.split_whitespace();
+        let prefix = parse_prefix(tokens.next().ok_or("empty VRP")?)?;
+        let mut max_length: Option<u8> = None;
+        let mut asn: Option<u32> = None;
+        while let Some(token) = tokens.next() {
+            match token {
+                "max" => {
+                    let value = tokens.next().ok_or("missing value after 'max'")?;
+                    max_length = Some(value.parse().map_err(|_| format!("invalid max length {}", value))?);
+                }
+                "as" => asn = Some(parse_asn(tokens.next().ok_or("missing value after 'as'")?)?),
+                _ => asn = Some(parse_asn(token)?),
+            }
+        }
+        Ok(VrpSpec {
+            prefix,
+            max_length,
+            asn: asn.ok_or(format!("missing AS number in '{}'", s))?,
+        })
+    }
+}
+
+impl fmt::Display for VrpSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", prefix_string(&self.prefix))?;
+        if let Some(max_length) = self.max_length {
+            write!(f, " max {}", max_length)?;
+        }
+        write!(f, " as AS{}", self.asn)
+    }
+}
+
+impl SafetyChecks {
+    pub fn is_empty(&self) -> bool {
+        self.max_drop_percent.is_none() && self.must_exist.is_empty()
+    }
+
+    /// Checks the VRPs of one output against the checks and the file currently published at `previous`.
+    /// Returns a list of reasons why the output must not be published and warnings about checks
+    /// that could not be done.
+    pub fn check(&self, vrps: &[Vrp], family: Family, previous: Option<&Path>) -> (Vec<String>, Warnings) {
+        let mut failures: Vec<String> = Vec::new();
+        let mut warnings: Warnings = Vec::new();
+        let selected: Vec<&Vrp> = vrps.iter().filter(|v| family.contains(&v.prefix)).collect();
+
+        for spec in self.must_exist.iter().filter(|s| family.contains(&s.prefix)) {
+            if !selected.iter().any(|v| spec.matches(v)) {
+                failures.push(format!("required VRP {} is missing", spec));
+            }
+        }
+
+        if let (Some(max_drop), Some(previous)) = (self.max_drop_percent, previous) {
+            if previous.exists() {
+                match read_roa_file(previous) {
+                    Ok(previous_vrps) => {
+                        let old_count = previous_vrps.len();
+                        let new_count = selected.len();
+                        if new_count < old_count {
+                            let drop = (old_count - new_count) as f64 * 100.0 / old_count as f64;
+                            if drop > max_drop {
+                                failures.push(format!("VRP count dropped from {} to {} ({:.1}% > {}%) compared to {}",
+                                                      old_count, new_count, drop, max_drop, previous.display()));
+                            }
+                        }
+                    }
+                    Err(err) => warnings.push(Warning::new("safety-check-skipped", previous.display().to_string(),
+                                                           format!("Skipping VRP count check: {}", err))),
+                }
+            }
+        }
+        (failures, warnings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn vrps(count: usize) -> Vec<Vrp> {
+        (0..count).map(|i| Vrp { prefix: parse_prefix(&format!("172.20.{}.0/24", i)).unwrap(), max_length: 24, asn: 4242420000 }).collect()
+    }
+
+    /// A previously published bird file with `count` VRPs.
+    fn previous(name: &str, count: usize) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("roa_wizard-{}-{}", std::process::id(), name));
+        let content: String = vrps(count).iter().map(|v| format!("route {} max {} as {};\n", prefix_string(&v.prefix), v.max_length, v.asn)).collect();
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    fn drop_check(max_drop_percent: f64) -> SafetyChecks {
+        SafetyChecks { max_drop_percent: Some(max_drop_percent), must_exist: Vec::new() }
+    }
+
+    #[test]
+    fn vrp_specs() {
+        let spec: VrpSpec = "172.20.0.0/24 max 29 AS4242420000".parse().unwrap();
+        assert_eq!(spec, VrpSpec { prefix: parse_prefix("172.20.0.0/24").unwrap(), max_length: Some(29), asn: 4242420000 });
+        assert_eq!(spec.to_string(), "172.20.0.0/24 max 29 as AS4242420000");
+        assert_eq!("fd00::/48 as 4242420000".parse::<VrpSpec>().unwrap().to_string(), "fd00::/48 as AS4242420000");
+        assert_eq!("172.20.0.0/24 4242420000".parse::<VrpSpec>().unwrap().max_length, None);
+        for (text, error) in [
+            ("", "empty VRP"),
+            ("172.20.0.0/24", "missing AS number in '172.20.0.0/24'"),
+            ("172.20.0.0/24 max", "missing value after 'max'"),
+            ("172.20.0.0/24 max x AS1", "invalid max length x"),
+            ("172.20.0.0/24 as", "missing value after 'as'"),
+            ("172.20.0.0/24 ASX", "invalid AS number ASX"),
+        ] {
+            assert_eq!(text.parse::<VrpSpec>().unwrap_err().to_string(), error);
+        }
+        assert!("172.20.0.1/24 AS1".parse::<VrpSpec>().is_err());
+    }
+
+    #[test]
+    fn must_exist() {
+        let checks = SafetyChecks {
+            max_drop_percent: None,
+            must_exist: ["172.20.1.0/24 AS4242420000", "172.20.2.0/24 max 24 AS4242420000", "172.20.3.0/24 max 28 AS4242420000",
+                         "172.20.4.0/24 AS4242420001", "fd00::/48 AS4242420000"].iter().map(|s| s.parse().unwrap()).collect(),
+        };
+        let (failures, warnings) = checks.check(&vrps(4), Family::V4, None);
+        assert_eq!(failures, ["required VRP 172.20.3.0/24 max 28 as AS4242420000 is missing",
+                              "required VRP 172.20.4.0/24 as AS4242420001 is missing"]);
+        assert!(warnings.is_empty());
+        // Only the VRPs of the output's family are required
+        assert_eq!(checks.check(&vrps(4), Family::V6, None).0, ["required VRP fd00::/48 as AS4242420000 is missing"]);
+        assert!(SafetyChecks::default().is_empty());
+        assert!(!checks.is_empty());
+    }
+
+    #[test]
+    fn vrp_count_drop() {
+        let path = previous("safety-previous", 10);
+        for (count, max_drop, passes) in [(10, 0.0, true), (12, 0.0, true), (9, 10.0, true), (8, 10.0, false), (9, 5.0, false), (0, 99.0, false)] {
+            let (failures, warnings) = drop_check(max_drop).check(&vrps(count), Family::V4, Some(&path));
+            assert_eq!(failures.is_empty(), passes, "{} VRPs with {}%: {:?}", count, max_drop, failures);
+            assert!(warnings.is_empty());
+        }
+        let (failures, _) = drop_check(10.0).check(&vrps(8), Family::V4, Some(&path));
+        assert_eq!(failures, [format!("VRP count dropped from 10 to 8 (20.0% > 10%) compared to {}", path.display())]);
+        // VRPs of the other family do not count
+        assert!(!drop_check(10.0).check(&vrps(10), Family::V6, Some(&path)).0.is_empty());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn no_previous_file() {
+        // Nothing was published yet
+        let missing = std::env::temp_dir().join(format!("roa_wizard-{}-safety-missing", std::process::id()));
+        assert_eq!(drop_check(0.0).check(&vrps(0), Family::V4, Some(&missing)), (Vec::new(), Vec::new()));
+        assert_eq!(drop_check(0.0).check(&vrps(0), Family::V4, None), (Vec::new(), Vec::new()));
+
+        let empty = previous("safety-empty", 0);
+        assert_eq!(drop_check(0.0).check(&vrps(0), Family::V4, Some(&empty)), (Vec::new(), Vec::new()));
+        fs::remove_file(&empty).unwrap();
+
+        // An unreadable file skips the check with a warning
+        let invalid = previous("safety-invalid", 0);
+        fs::write(&invalid, "not a ROA file\n").unwrap();
+        let (failures, warnings) = drop_check(0.0).check(&vrps(0), Family::V4, Some(&invalid));
+        assert!(failures.is_empty());
+        assert_eq!(warnings.iter().map(|w| (w.code, w.object.clone())).collect::<Vec<_>>(),
+                   [("safety-check-skipped", invalid.display().to_string())]);
+        assert!(warnings[0].message.starts_with("Skipping VRP count check: Unable to parse ROA file"));
+        fs::remove_file(&invalid).unwrap();
+    }
+}
@@ -0,0 +1,203 @@
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use cidr_utils::cidr::IpCidr;
+use json::JsonValue;
+use crate::{io_error, BoxResult};
+use crate::vrp::{prefix_covers, Vrp};
+
+/// Local exceptions to the registry data as described in RFC 8416 (SLURM).
+#[derive(Debug, Default)]
+pub struct Slurm {
+    prefix_filters: Vec<PrefixFilter>,
+    prefix_assertions: Vec<Vrp>,
+}
+
+#[derive(Debug)]
+struct PrefixFilter {
+    prefix: Option<IpCidr>,
+    asn: Option<u32>,
+}
+
+impl PrefixFilter {
+    fn matches(&self, vrp: &Vrp) -> bool {
+        if let Some(prefix) = &self.prefix {
+            if !prefix_covers(prefix, &vrp.prefix) {
+                return false;
+            }
+        }
+        if let Some(asn) = self.asn {
+            if asn != vrp.asn {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+impl Slurm {
+    pub fn read_files(files: &[impl AsRef<Path>]) -> BoxResult<Self> {
+        let mut result = Slurm::default();
+        for file in files {
+            let mut slurm = read_slurm(file.as_ref())?;
+            result.prefix_filters.append(&mut slurm.prefix_filters);
+            result.prefix_assertions.append(&mut slurm.prefix_assertions);
+        }
+        Ok(result)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.prefix_filters.is_empty() && self.prefix_assertions.is_empty()
+    }
+
+    /// Removes all VRPs matched by a prefix filter and then adds the locally asserted VRPs.
+    pub fn apply(&self, vrps: &mut Vec<Vrp>) {
+        vrps.retain(|vrp| !self.prefix_filters.iter().any(|f| f.matches(vrp)));
+        for assertion in &self.prefix_assertions {
+            if !vrps.contains(assertion) {
+                vrps.push(assertion.clone());
+            }
+        }
+    }
+}
+
+pub fn read_slurm(file: &Path) -> BoxResult<Slurm> {
+    let content = fs::read_to_string(file).map_err(|e|
+        io_error(e, format!("Unable to read SLURM file {}", file.display()))
+    )?;
+    parse_slurm(&content).map_err(|e| format!("Invalid SLURM file {}: {}", file.display(), e).into())
+}
+
+fn parse_slurm(content: &str) -> BoxResult<Slurm> {
+    let root = json::parse(content)?;
+    if root["slurmVersion"].as_u8() != Some(1) {
+        return Err("unsupported or missing slurmVersion".into());
+    }
+    let mut result = Slurm::default();
+    for entry in root["validationOutputFilters"]["prefixFilters"].members() {
+        let filter = PrefixFilter {
+            prefix: parse_optional_prefix(&entry["prefix"])?,
+            asn: parse_optional_asn(&entry["asn"])?,
+        };
+        if filter.prefix.is_none() && filter.asn.is_none() {
+            return Err("prefix filter without prefix and asn".into());
+        }
+        result.prefix_filters.push(filter);
+    }
+    for entry in root["locallyAddedAssertions"]["prefixAssertions"].members() {
+        let prefix = parse_optional_prefix(&entry["prefix"])?.ok_or("prefix assertion without prefix")?;
+        let asn = parse_optional_asn(&entry["asn"])?.ok_or("prefix assertion without asn")?;
+        let max_length = match &entry["maxPrefixLength"] {
+            JsonValue::Null => prefix.network_length(),
+            value => value.as_u8().ok_or("invalid maxPrefixLength")?,
+        };
+        let family_max = if prefix.is_ipv4() { 32 } else { 128 };
+        if max_length < prefix.network_length() || max_length > family_max {
+            return Err(format!("invalid maxPrefixLength {} for prefix {}", max_length, prefix).into());
+        }
+        result.prefix_assertions.push(Vrp { prefix, max_length, asn });
+    }
+    Ok(result)
+}
+
+fn parse_optional_prefix(value: &JsonValue) -> BoxResult<Option<IpCidr>> {
+    match value {
+        JsonValue::Null => Ok(None),
+        value => {
+            let text = value.as_str().ok_or("prefix is not a string")?;
+            Ok(Some(IpCidr::from_str(text).map_err(|e| format!("invalid prefix {}: {}", text, e))?))
+        }
+    }
+}
+
+fn parse_optional_asn(value: &JsonValue) -> BoxResult<Option<u32>> {
+    match value {
+        JsonValue::Null => Ok(None),
+        value => Ok(Some(value.as_u32().ok_or("invalid asn")?)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vrp(prefix: &str, max_length: u8, asn: u32) -> Vrp {
+        Vrp { prefix: IpCidr::from_str(prefix).unwrap(), max_length, asn }
+    }
+
+    fn applied(slurm: &str, mut vrps: Vec<Vrp>) -> Vec<String> {
+        parse_slurm(slurm).unwrap().apply(&mut vrps);
+        vrps.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn prefix_filters() {
+        let vrps = vec![
+            vrp("172.20.0.0/24", 24, 4242420000),
+            vrp("172.20.0.128/25", 25, 4242420001),
+            vrp("172.20.1.0/24", 24, 4242420001),
+            vrp("fd00:1::/48", 48, 4242420001),
+        ];
+        // A prefix filter also removes more-specifics, but not the less-specific
+        let slurm = r#"{"slurmVersion": 1, "validationOutputFilters": {"prefixFilters": [{"prefix": "172.20.0.128/25"}]}}"#;
+        assert_eq!(applied(slurm, vrps.clone()).len(), 3);
+        let slurm = r#"{"slurmVersion": 1, "validationOutputFilters": {"prefixFilters": [{"prefix": "172.20.0.0/16"}]}}"#;
+        assert_eq!(applied(slurm, vrps.clone()), ["fd00:1::/48 max 48 as AS4242420001"]);
+        let slurm = r#"{"slurmVersion": 1, "validationOutputFilters": {"prefixFilters": [{"asn": 4242420001}]}}"#;
+        assert_eq!(applied(slurm, vrps.clone()), ["172.20.0.0/24 max 24 as AS4242420000"]);
+        // Prefix and asn must both match
+        let slurm = r#"{"slurmVersion": 1, "validationOutputFilters": {"prefixFilters": [
+            {"prefix": "172.20.0.0/24", "asn": 4242420001, "comment": "ignored"}]}}"#;
+        assert_eq!(applied(slurm, vrps.clone()), [
+            "172.20.0.0/24 max 24 as AS4242420000",
+            "172.20.1.0/24 max 24 as AS4242420001",
+            "fd00:1::/48 max 48 as AS4242420001",
+        ]);
+    }
+
+    #[test]
+    fn prefix_assertions() {
+        let slurm = r#"{"slurmVersion": 1, "locallyAddedAssertions": {"prefixAssertions": [
+            {"prefix": "172.20.0.0/24", "asn": 4242420000},
+            {"prefix": "172.20.1.0/24", "asn": 4242420000, "maxPrefixLength": 28},
+            {"prefix": "fd00:1::/48", "asn": 4242420001, "maxPrefixLength": 64}]}}"#;
+        // Already present VRPs are not added twice
+        assert_eq!(applied(slurm, vec![vrp("172.20.0.0/24", 24, 4242420000)]), [
+            "172.20.0.0/24 max 24 as AS4242420000",
+            "172.20.1.0/24 max 28 as AS4242420000",
+            "fd00:1::/48 max 64 as AS4242420001",
+        ]);
+        // Filters are applied before the assertions
+        let slurm = r#"{"slurmVersion": 1,
+            "validationOutputFilters": {"prefixFilters": [{"asn": 4242420000}]},
+            "locallyAddedAssertions": {"prefixAssertions": [{"prefix": "172.20.0.0/24", "asn": 4242420000}]}}"#;
+        assert_eq!(applied(slurm, vec![vrp("172.20.0.0/16", 24, 4242420000)]), ["172.20.0.0/24 max 24 as AS4242420000"]);
+        assert!(parse_slurm(r#"{"slurmVersion": 1}"#).unwrap().is_empty());
+    }
+
+    #[test]
+    fn invalid_files() {
+        for (content, message) in [
+            (r#"{}"#, "unsupported or missing slurmVersion"),
+            (r#"{"slurmVersion": 2}"#, "unsupported or missing slurmVersion"),
+            (r#"{"slurmVersion": 1, "validationOutputFilters": {"prefixFilters": [{"comment": "x"}]}}"#,
+             "prefix filter without prefix and asn"),
+            (r#"{"slurmVersion": 1, "validationOutputFilters": {"prefixFilters": [{"prefix": "172.20.0.0/33"}]}}"#,
+             "invalid prefix 172.20.0.0/33"),
+            (r#"{"slurmVersion": 1, "validationOutputFilters": {"prefixFilters": [{"asn": "AS1"}]}}"#, "invalid asn"),
+            (r#"{"slurmVersion": 1, "locallyAddedAssertions": {"prefixAssertions": [{"prefix": "172.20.0.0/24"}]}}"#,
+             "prefix assertion without asn"),
+            (r#"{"slurmVersion": 1, "locallyAddedAssertions": {"prefixAssertions": [{"asn": 1}]}}"#,
+             "prefix assertion without prefix"),
+            (r#"{"slurmVersion": 1, "locallyAddedAssertions": {"prefixAssertions": [
+                {"prefix": "172.20.0.0/24", "asn": 1, "maxPrefixLength": 23}]}}"#,
+             "invalid maxPrefixLength 23 for prefix 172.20.0.0/24"),
+            (r#"{"slurmVersion": 1, "locallyAddedAssertions": {"prefixAssertions": [
+                {"prefix": "fd00::/8", "asn": 1, "maxPrefixLength": 129}]}}"#,
+             "invalid maxPrefixLength 129 for prefix fd00::/8"),
+        ] {
+            let error = parse_slurm(content).unwrap_err().to_string();
+            assert!(error.starts_with(message), "{}: {}", content, error);
+        }
+    }
+}
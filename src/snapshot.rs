@@ -0,0 +1,254 @@
+use std::collections::BTreeSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use crate::import::{parse_asn, parse_prefix};
+use crate::vrp::{prefix_string, Vrp};
+use crate::{io_error, BoxResult, Family};
+
+const LOG_FILE: &str = "vrps.log";
+
+/// An append-only archive of generated VRP sets.
+///
+/// Each snapshot is stored as the VRPs added and removed compared to the state before it,
+/// restricted to the address family it was generated for:
+///
+/// ```text
+/// S <unix time> <v4|v6|both> <commit or ->
+/// + <prefix> <max length> <asn>
+/// - <prefix> <max length> <asn>
+/// E <number of VRPs after the snapshot>
+/// ```
+///
+/// A snapshot without its `E` line, e.g. after a crash while appending, is ignored.
+#[derive(Debug, Clone)]
+pub struct SnapshotStore {
+    directory: PathBuf,
+}
+
+/// The VRPs as of a point in time.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    /// Time of the last snapshot taken at or before the requested time.
+    pub time: i64,
+    pub commit: Option<String>,
+    pub vrps: Vec<Vrp>,
+}
+
+impl SnapshotStore {
+    /// The store in `directory`, which is created by the first snapshot.
+    pub fn new(directory: impl AsRef<Path>) -> Self {
+        SnapshotStore { directory: directory.as_ref().to_path_buf() }
+    }
+
+    fn log_file(&self) -> PathBuf {
+        self.directory.join(LOG_FILE)
+    }
+
+    /// Appends the VRPs of `family` if they differ from the latest snapshot. Returns whether a snapshot was added.
+    pub fn append(&self, vrps: &[Vrp], family: Family, commit: Option<&str>, time: i64) -> BoxResult<bool> {
+        let (latest, valid_length) = self.replay(i64::MAX)?;
+        let state: BTreeSet<Vrp> = latest.as_ref().map(|s| s.vrps.iter().cloned().collect()).unwrap_or_default();
+        let new: BTreeSet<&Vrp> = vrps.iter().filter(|v| family.contains(&v.prefix)).collect();
+        let old: BTreeSet<&Vrp> = state.iter().filter(|v| family.contains(&v.prefix)).collect();
+        let added: Vec<&&Vrp> = new.difference(&old).collect();
+        let removed: Vec<&&Vrp> = old.difference(&new).collect();
+        if latest.is_some() && added.is_empty() && removed.is_empty() {
+            return Ok(false);
+        }
+
+        // Queries rely on snapshots being ordered by time
+        let time = latest.map_or(time, |l| time.max(l.time));
+        let mut record = format!("S {} {} {}\n", time, family, commit.unwrap_or("-"));
+        for (sign, list) in [('+', &added), ('-', &removed)] {
+            for vrp in list {
+                record.push_str(&format!("{} {} {} {}\n", sign, prefix_string(&vrp.prefix), vrp.max_length, vrp.asn));
+            }
+        }
+        record.push_str(&format!("E {}\n", state.len() + added.len() - removed.len()));
+
+        fs::create_dir_all(&self.directory).map_err(|e|
+            io_error(e, format!("Unable to create snapshot store {}", self.directory.display()))
+        )?;
+        let log_file = self.log_file();
+        let context = || format!("Unable to append to snapshot store {}", log_file.display());
+        let mut file = OpenOptions::new().create(true).append(true).open(&log_file).map_err(|e| io_error(e, context()))?;
+        // Drop an incomplete snapshot left behind by an interrupted run
+        file.set_len(valid_length).map_err(|e| io_error(e, context()))?;
+        file.write_all(record.as_bytes()).and_then(|_| file.sync_data()).map_err(|e| io_error(e, context()))?;
+        Ok(true)
+    }
+
+    /// Returns the VRPs as of `time`, or `None` if no snapshot was taken until then.
+    pub fn state_at(&self, time: i64) -> BoxResult<Option<Snapshot>> {
+        self.replay(time).map(|(snapshot, _)| snapshot)
+    }
+
+    /// Applies all complete snapshots taken at or before `until`. Also returns the length of the
+    /// log up to the last complete snapshot.
+    fn replay(&self, until: i64) -> BoxResult<(Option<Snapshot>, u64)> {
+        let log_file = self.log_file();
+        let file = match File::open(&log_file) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok((None, 0)),
+            Err(e) => return Err(io_error(e, format!("Unable to read snapshot store {}", log_file.display())).into()),
+        };
+
+        struct Pending {
+            time: i64,
+            commit: Option<String>,
+            added: Vec<Vrp>,
+            removed: Vec<Vrp>,
+        }
+        let mut state: BTreeSet<Vrp> = BTreeSet::new();
+        let mut latest: Option<(i64, Option<String>)> = None;
+        let mut pending: Option<Pending> = None;
+        let mut position: u64 = 0;
+        let mut valid_length: u64 = 0;
+        let mut reader = BufReader::new(file);
+        let mut line = String::new();
+        for number in 1.. {
+            line.clear();
+            let read = reader.read_line(&mut line).map_err(|e|
+                io_error(e, format!("Unable to read snapshot store {}", log_file.display()))
+            )?;
+            if read == 0 || !line.ends_with('\n') {
+                break;
+            }
+            position += read as u64;
+            let corrupt = |reason: &str| format!("Corrupt snapshot store {} line {}: {}", log_file.display(), number, reason);
+            let mut fields = line.split_whitespace();
+            match (fields.next(), &mut pending) {
+                (Some("S"), _) => {
+                    let time = fields.next().and_then(|t| t.parse::<i64>().ok()).ok_or_else(|| corrupt("invalid time"))?;
+                    if time > until {
+                        break;
+                    }
+                    let commit = fields.nth(1).filter(|c| *c != "-").map(str::to_owned);
+                    pending = Some(Pending { time, commit, added: Vec::new(), removed: Vec::new() });
+                }
+                (Some(sign @ ("+" | "-")), Some(snapshot)) => {
+                    let vrp = parse_vrp(fields).map_err(|e| corrupt(&e.to_string()))?;
+                    if sign == "+" { snapshot.added.push(vrp) } else { snapshot.removed.push(vrp) };
+                }
+                (Some("E"), Some(_)) => {
+                    let snapshot = pending.take().expect("matched above");
+                    for vrp in &snapshot.removed {
+                        state.remove(vrp);
+                    }
+                    state.extend(snapshot.added);
+                    let count = fields.next().and_then(|c| c.parse::<usize>().ok()).ok_or_else(|| corrupt("invalid count"))?;
+                    if count != state.len() {
+                        return Err(corrupt(&format!("expected {} VRPs but found {}", count, state.len())).into());
+                    }
+                    latest = Some((snapshot.time, snapshot.commit));
+                    valid_length = position;
+                }
+                _ => return Err(corrupt("unexpected line").into()),
+            }
+        }
+
+        let snapshot = latest.map(|(time, commit)| Snapshot { time, commit, vrps: state.into_iter().collect() });
+        Ok((snapshot, valid_length))
+    }
+}
+
+fn parse_vrp<'a>(mut fields: impl Iterator<Item = &'a str>) -> BoxResult<Vrp> {
+    let prefix = parse_prefix(fields.next().ok_or("missing prefix")?)?;
+    let max_length = fields.next().and_then(|m| m.parse::<u8>().ok()).ok_or("invalid max length")?;
+    let asn = parse_asn(fields.next().ok_or("missing AS number")?)?;
+    Ok(Vrp { prefix, max_length, asn })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use cidr_utils::cidr::IpCidr;
+
+    fn vrp(prefix: &str, asn: u32) -> Vrp {
+        let prefix = IpCidr::from_str(prefix).unwrap();
+        Vrp { prefix, max_length: prefix.network_length(), asn }
+    }
+
+    fn store(name: &str) -> SnapshotStore {
+        let directory = std::env::temp_dir().join(format!("roa_wizard-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&directory);
+        SnapshotStore::new(directory)
+    }
+
+    fn vrps_at(store: &SnapshotStore, time: i64) -> Option<(i64, Vec<Vrp>)> {
+        store.state_at(time).unwrap().map(|s| (s.time, s.vrps))
+    }
+
+    #[test]
+    fn append_and_replay() {
+        let store = store("snapshot-replay");
+        let (a, b, c) = (vrp("172.20.0.0/24", 1), vrp("172.20.1.0/24", 2), vrp("fd00::/48", 3));
+        assert!(store.state_at(i64::MAX).unwrap().is_none());
+        assert!(store.append(&[b.clone(), a.clone()], Family::Both, Some("abc"), 100).unwrap());
+        // Unchanged VRPs are not stored again
+        assert!(!store.append(&[a.clone(), b.clone()], Family::Both, None, 200).unwrap());
+        assert!(store.append(&[a.clone(), c.clone()], Family::Both, None, 300).unwrap());
+
+        assert_eq!(fs::read_to_string(store.log_file()).unwrap(), "S 100 both abc\n+ 172.20.0.0/24 24 1\n+ 172.20.1.0/24 24 2\nE 2\n\
+            S 300 both -\n+ fd00::/48 48 3\n- 172.20.1.0/24 24 2\nE 2\n");
+        assert_eq!(vrps_at(&store, 99), None);
+        assert_eq!(vrps_at(&store, 100), Some((100, vec![a.clone(), b.clone()])));
+        assert_eq!(vrps_at(&store, 299), Some((100, vec![a.clone(), b.clone()])));
+        assert_eq!(vrps_at(&store, 300), Some((300, vec![a.clone(), c.clone()])));
+        assert_eq!(vrps_at(&store, i64::MAX), Some((300, vec![a.clone(), c.clone()])));
+        assert_eq!(store.state_at(100).unwrap().unwrap().commit.as_deref(), Some("abc"));
+        assert_eq!(store.state_at(300).unwrap().unwrap().commit, None);
+        fs::remove_dir_all(&store.directory).unwrap();
+    }
+
+    #[test]
+    fn families_and_time_order() {
+        let store = store("snapshot-families");
+        let (a, b, c) = (vrp("172.20.0.0/24", 1), vrp("172.20.1.0/24", 2), vrp("fd00::/48", 3));
+        store.append(&[a.clone(), c.clone()], Family::Both, None, 100).unwrap();
+        // A v4 snapshot keeps the v6 VRPs and ignores v6 VRPs passed to it
+        store.append(&[b.clone(), vrp("fd00:1::/48", 4)], Family::V4, None, 200).unwrap();
+        assert_eq!(vrps_at(&store, 200), Some((200, vec![b.clone(), c.clone()])));
+        // Snapshots stay ordered by time if the clock goes backwards
+        store.append(std::slice::from_ref(&a), Family::V4, None, 150).unwrap();
+        assert_eq!(vrps_at(&store, 199), Some((100, vec![a.clone(), c.clone()])));
+        assert_eq!(vrps_at(&store, 200), Some((200, vec![a.clone(), c.clone()])));
+        fs::remove_dir_all(&store.directory).unwrap();
+    }
+
+    #[test]
+    fn incomplete_snapshot() {
+        let store = store("snapshot-crash");
+        let (a, b) = (vrp("172.20.0.0/24", 1), vrp("172.20.1.0/24", 2));
+        store.append(std::slice::from_ref(&a), Family::Both, None, 100).unwrap();
+        let complete = fs::read_to_string(store.log_file()).unwrap();
+        // A crash while appending leaves a snapshot without its E line, maybe within a line
+        let mut file = OpenOptions::new().append(true).open(store.log_file()).unwrap();
+        file.write_all(b"S 200 both -\n+ 172.20.1.0/24 24 2\n- 172.20").unwrap();
+        assert_eq!(vrps_at(&store, i64::MAX), Some((100, vec![a.clone()])));
+
+        assert!(store.append(&[a.clone(), b.clone()], Family::Both, None, 300).unwrap());
+        assert_eq!(fs::read_to_string(store.log_file()).unwrap(), format!("{}S 300 both -\n+ 172.20.1.0/24 24 2\nE 2\n", complete));
+        assert_eq!(vrps_at(&store, i64::MAX), Some((300, vec![a, b])));
+        fs::remove_dir_all(&store.directory).unwrap();
+    }
+
+    #[test]
+    fn corrupt_log() {
+        let store = store("snapshot-corrupt");
+        fs::create_dir_all(&store.directory).unwrap();
+        for (content, reason) in [
+            ("S 100 both -\n+ 172.20.0.0/24 24 1\nE 2\n", "line 3: expected 2 VRPs but found 1"),
+            ("S x both -\nE 0\n", "line 1: invalid time"),
+            ("+ 172.20.0.0/24 24 1\n", "line 1: unexpected line"),
+            ("S 100 both -\n+ 172.20.0.0/24 x 1\nE 1\n", "line 2: invalid max length"),
+        ] {
+            fs::write(store.log_file(), content).unwrap();
+            let error = store.state_at(i64::MAX).unwrap_err().to_string();
+            assert!(error.ends_with(reason), "{}", error);
+        }
+        fs::remove_dir_all(&store.directory).unwrap();
+    }
+}
@@ -0,0 +1,551 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use crate::git::{find_git_dir, GitRepository, ObjectId, TreeEntry};
+use crate::inflate::gunzip;
+use crate::tar::read_tar;
+use crate::{io_error, BoxResult};
+
+/// Where registry files are read from. Paths are relative to the registry root and use `/` as separator.
+pub trait RegistrySource: Send + Sync + fmt::Debug {
+    /// Returns the names of all entries of a directory.
+    fn list_directory(&self, path: &str) -> io::Result<Vec<String>>;
+
+    fn read_file(&self, path: &str) -> io::Result<Vec<u8>>;
+
+    /// The commit the registry content corresponds to, if it is known.
+    fn commit_hash(&self) -> Option<String> {
+        None
+    }
+
+    /// A human readable location of a registry file for messages.
+    fn describe(&self, path: &str) -> String;
+}
+
+/// Opens the registry at `path`: the tree of `revision` if one is given, a `.tar` or `.tar.gz`
+/// archive if `path` is a file and a checkout on disk otherwise.
+pub fn open(path: impl AsRef<Path>, revision: Option<&str>) -> BoxResult<Box<dyn RegistrySource>> {
+    let path = path.as_ref();
+    if let Some(revision) = revision {
+        return Ok(Box::new(GitSource::open(path, revision)?));
+    }
+    if path.is_file() {
+        return Ok(Box::new(TarSource::open(path)?));
+    }
+    Ok(Box::new(DirectorySource::new(path)))
+}
+
+/// Registry files are small, larger ones are refused instead of read into memory.
+pub const MAX_FILE_SIZE: u64 = 1024 * 1024;
+
+fn not_found(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, message)
+}
+
+fn not_regular(name: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, format!("{} is not a regular file", name))
+}
+
+fn too_large(name: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{} exceeds the size limit of {} bytes", name, MAX_FILE_SIZE))
+}
+
+/// A checkout of the registry on disk.
+#[derive(Debug, Clone)]
+pub struct DirectorySource {
+    root: PathBuf,
+}
+
+impl DirectorySource {
+    pub fn new(root: impl AsRef<Path>) -> Self {
+        DirectorySource { root: root.as_ref().to_path_buf() }
+    }
+
+    /// Refuses `path` if it resolves to a location outside the registry root through a symlink.
+    fn check_within_root(&self, path: &Path) -> io::Result<()> {
+        // Missing files are reported when they are opened
+        let (Ok(root), Ok(target)) = (fs::canonicalize(&self.root), fs::canonicalize(path)) else {
+            return Ok(());
+        };
+        if !target.starts_with(&root) {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied,
+                format!("{} is a symlink pointing outside the registry", path.display())));
+        }
+        Ok(())
+    }
+}
+
+impl RegistrySource for DirectorySource {
+    fn list_directory(&self, path: &str) -> io::Result<Vec<String>> {
+        let directory = self.root.join(path);
+        self.check_within_root(&directory)?;
+        let dir = fs::read_dir(&directory).map_err(|e|
+            io_error(e, format!("Unable to read directory {}", directory.display()))
+        )?;
+        let mut names = Vec::new();
+        for entry in dir {
+            let entry = entry.map_err(|e|
+                io_error(e, format!("Unable to read directory file {}", directory.display()))
+            )?;
+            names.push(entry.file_name().to_string_lossy().into_owned());
+        }
+        Ok(names)
+    }
+
+    fn read_file(&self, path: &str) -> io::Result<Vec<u8>> {
+        let file = self.root.join(path);
+        let open_error = |e| io_error(e, format!("Unable to open file {}", file.display()));
+        let mut metadata = fs::symlink_metadata(&file).map_err(open_error)?;
+        if metadata.file_type().is_symlink() {
+            self.check_within_root(&file)?;
+            metadata = fs::metadata(&file).map_err(open_error)?;
+        }
+        // Reading FIFOs or devices could block or never end
+        if !metadata.is_file() {
+            return Err(not_regular(file.display().to_string()));
+        }
+        if metadata.len() > MAX_FILE_SIZE {
+            return Err(too_large(file.display().to_string()));
+        }
+        let mut content = Vec::with_capacity(metadata.len() as usize);
+        fs::File::open(&file).and_then(|f| f.take(MAX_FILE_SIZE + 1).read_to_end(&mut content)).map_err(open_error)?;
+        if content.len() as u64 > MAX_FILE_SIZE {
+            return Err(too_large(file.display().to_string()));
+        }
+        Ok(content)
+    }
+
+    fn commit_hash(&self) -> Option<String> {
+        // The registry may be a subdirectory of the work tree
+        let root = fs::canonicalize(&self.root).ok()?;
+        let work_tree = root.ancestors().find(|dir| find_git_dir(dir).is_ok())?;
+        let repository = GitRepository::open(work_tree).ok()?;
+        repository.resolve("HEAD").ok().map(|id| id.to_hex())
+    }
+
+    fn describe(&self, path: &str) -> String {
+        self.root.join(path).display().to_string()
+    }
+}
+
+/// The tree of a commit in a git object database, read without a checkout.
+#[derive(Debug, Clone)]
+pub struct GitSource {
+    pub(crate) repository: Arc<GitRepository>,
+    pub(crate) commit: ObjectId,
+    pub(crate) tree: ObjectId,
+}
+
+impl GitSource {
+    /// Opens the git repository at `path` and resolves `revision` (commit, tag, branch, ...) in it.
+    pub fn open(path: impl AsRef<Path>, revision: &str) -> BoxResult<Self> {
+        let repository = GitRepository::open(path.as_ref())?;
+        let commit = repository.resolve(revision)
+            .map_err(|e| format!("Unable to resolve revision {} in {}: {}", revision, path.as_ref().display(), e))?;
+        let tree = repository.read_commit(&commit)?.tree;
+        Ok(GitSource { repository: Arc::new(repository), commit, tree })
+    }
+
+    fn entry(&self, path: &str) -> io::Result<TreeEntry> {
+        self.repository.find_path(&self.tree, path).map_err(io::Error::other)?
+            .ok_or_else(|| not_found(format!("{} does not exist", self.describe(path))))
+    }
+}
+
+impl RegistrySource for GitSource {
+    fn list_directory(&self, path: &str) -> io::Result<Vec<String>> {
+        let entry = self.entry(path)?;
+        if !entry.is_tree() {
+            return Err(io::Error::other(format!("{} is not a directory", self.describe(path))));
+        }
+        let entries = self.repository.read_tree(&entry.id).map_err(io::Error::other)?;
+        Ok(entries.entries.iter().map(|e| e.name.clone()).collect())
+    }
+
+    fn read_file(&self, path: &str) -> io::Result<Vec<u8>> {
+        let entry = self.entry(path)?;
+        // Symlinks, submodules and directories
+        if entry.mode & 0o170000 != 0o100000 {
+            return Err(not_regular(self.describe(path)));
+        }
+        let blob = self.repository.read_blob(&entry.id, MAX_FILE_SIZE as usize)
+            .map_err(|e| io::Error::other(format!("Unable to open file {}: {}", self.describe(path), e)))?
+            .ok_or_else(|| too_large(self.describe(path)))?;
+        // Blobs are only shared while cached as delta bases
+        Ok(Arc::try_unwrap(blob).map_or_else(|blob| blob.1.clone(), |blob| blob.1))
+    }
+
+    fn commit_hash(&self) -> Option<String> {
+        Some(self.commit.to_hex())
+    }
+
+    fn describe(&self, path: &str) -> String {
+        format!("{}:{}", self.commit.to_hex(), path)
+    }
+}
+
+/// Files in `directory` replace or add to the files of `base`. Empty files delete the base file.
+/// The result does not correspond to any commit, so it has no commit hash.
+#[derive(Debug)]
+pub struct OverlaySource {
+    base: Box<dyn RegistrySource>,
+    directory: PathBuf,
+}
+
+impl OverlaySource {
+    pub fn new(base: Box<dyn RegistrySource>, directory: impl AsRef<Path>) -> Self {
+        OverlaySource { base, directory: directory.as_ref().to_path_buf() }
+    }
+}
+
+impl RegistrySource for OverlaySource {
+    fn list_directory(&self, path: &str) -> io::Result<Vec<String>> {
+        let overlay = self.directory.join(path);
+        if !overlay.is_dir() {
+            return self.base.list_directory(path);
+        }
+        let mut names = match self.base.list_directory(path) {
+            Ok(names) => names,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        for name in DirectorySource::new(&self.directory).list_directory(path)? {
+            let deleted = fs::metadata(overlay.join(&name)).is_ok_and(|m| m.is_file() && m.len() == 0);
+            if deleted {
+                names.retain(|n| *n != name);
+            } else if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        Ok(names)
+    }
+
+    fn read_file(&self, path: &str) -> io::Result<Vec<u8>> {
+        match fs::metadata(self.directory.join(path)) {
+            Ok(metadata) if metadata.is_file() && metadata.len() == 0 => {
+                Err(not_found(format!("{} was deleted by the overlay", self.describe(path))))
+            }
+            Ok(_) => DirectorySource::new(&self.directory).read_file(path),
+            Err(_) => self.base.read_file(path),
+        }
+    }
+
+    fn describe(&self, path: &str) -> String {
+        match self.directory.join(path).exists() {
+            true => self.directory.join(path).display().to_string(),
+            false => self.base.describe(path),
+        }
+    }
+}
+
+/// Registry files held in memory, e.g. for embedding the library in a web service.
+/// Directories exist implicitly as long as they contain a file.
+#[derive(Debug, Clone, Default)]
+pub struct MemorySource {
+    files: BTreeMap<String, Vec<u8>>,
+    commit: Option<String>,
+}
+
+impl MemorySource {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds or replaces the file at `path`, e.g. `data/route/172.20.0.0_16`.
+    pub fn insert(&mut self, path: impl Into<String>, content: impl Into<Vec<u8>>) {
+        self.files.insert(path.into(), content.into());
+    }
+
+    pub fn set_commit_hash(&mut self, commit: Option<String>) {
+        self.commit = commit;
+    }
+}
+
+impl RegistrySource for MemorySource {
+    fn list_directory(&self, path: &str) -> io::Result<Vec<String>> {
+        let prefix = format!("{}/", path.trim_end_matches('/'));
+        let mut names: Vec<String> = Vec::new();
+        for file in self.files.range(prefix.clone()..).map(|(p, _)| p).take_while(|p| p.starts_with(&prefix)) {
+            let name = file[prefix.len()..].split('/').next().unwrap_or_default();
+            if names.last().is_none_or(|n| n != name) {
+                names.push(name.to_owned());
+            }
+        }
+        if names.is_empty() {
+            return Err(not_found(format!("{} does not exist", self.describe(path))));
+        }
+        Ok(names)
+    }
+
+    fn read_file(&self, path: &str) -> io::Result<Vec<u8>> {
+        self.files.get(path).cloned().ok_or_else(|| not_found(format!("{} does not exist", self.describe(path))))
+    }
+
+    fn commit_hash(&self) -> Option<String> {
+        self.commit.clone()
+    }
+
+    fn describe(&self, path: &str) -> String {
+        path.to_owned()
+    }
+}
+
+/// Archives are held in memory, larger ones are refused. The dn42 registry is far below this size.
+pub const MAX_ARCHIVE_SIZE: usize = 1024 * 1024 * 1024;
+
+/// A snapshot of the registry in a `.tar` or `.tar.gz` archive, e.g. from `git archive`.
+/// The archive may contain the registry in a single top level directory.
+#[derive(Debug, Clone)]
+pub struct TarSource {
+    name: String,
+    files: MemorySource,
+}
+
+impl TarSource {
+    pub fn open(path: impl AsRef<Path>) -> BoxResult<Self> {
+        let path = path.as_ref();
+        let open_error = |e| io_error(e, format!("Unable to read archive {}", path.display()));
+        let mut data: Vec<u8> = Vec::new();
+        fs::File::open(path).and_then(|f| f.take(MAX_ARCHIVE_SIZE as u64 + 1).read_to_end(&mut data)).map_err(open_error)?;
+        if data.len() > MAX_ARCHIVE_SIZE {
+            return Err(format!("Invalid archive {}: larger than {} bytes", path.display(), MAX_ARCHIVE_SIZE).into());
+        }
+        Self::from_bytes(&data, path.display().to_string())
+    }
+
+    /// Reads an archive from memory. Compressed archives are recognized by their content and must
+    /// not exceed `MAX_ARCHIVE_SIZE` once decompressed.
+    pub fn from_bytes(data: &[u8], name: impl Into<String>) -> BoxResult<Self> {
+        let name = name.into();
+        let invalid = |e| format!("Invalid archive {}: {}", name, e);
+        let decompressed;
+        let data = match data.starts_with(&[0x1f, 0x8b]) {
+            true => {
+                decompressed = gunzip(data, MAX_ARCHIVE_SIZE).map_err(invalid)?;
+                &decompressed
+            }
+            false => data,
+        };
+        let archive = read_tar(data, MAX_FILE_SIZE as usize).map_err(invalid)?;
+
+        let mut paths: Vec<(String, Vec<u8>)> = archive.files.into_iter()
+            .map(|(path, content)| (path.trim_start_matches("./").to_owned(), content))
+            .collect();
+        // Strip a directory all files are in, as created by `git archive --prefix` or `tar` of a checkout
+        if !paths.iter().any(|(p, _)| p.starts_with("data/")) {
+            if let Some(top) = paths.first().and_then(|(p, _)| p.split_once('/')).map(|(t, _)| format!("{}/", t)) {
+                if paths.iter().all(|(p, _)| p.starts_with(&top)) {
+                    for (path, _) in &mut paths {
+                        path.replace_range(..top.len(), "");
+                    }
+                }
+            }
+        }
+
+        let mut files = MemorySource::new();
+        for (path, content) in paths {
+            files.insert(path, content);
+        }
+        files.set_commit_hash(archive.commit);
+        Ok(TarSource { name, files })
+    }
+}
+
+impl RegistrySource for TarSource {
+    fn list_directory(&self, path: &str) -> io::Result<Vec<String>> {
+        self.files.list_directory(path).map_err(|e| io::Error::new(e.kind(), format!("{} does not exist", self.describe(path))))
+    }
+
+    fn read_file(&self, path: &str) -> io::Result<Vec<u8>> {
+        let content = self.files.read_file(path).map_err(|e| io::Error::new(e.kind(), format!("{} does not exist", self.describe(path))))?;
+        if content.len() as u64 > MAX_FILE_SIZE {
+            return Err(too_large(self.describe(path)));
+        }
+        Ok(content)
+    }
+
+    fn commit_hash(&self) -> Option<String> {
+        self.files.commit_hash()
+    }
+
+    fn describe(&self, path: &str) -> String {
+        format!("{}:{}", self.name, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff::{RegistryDiff, VrpChange, VrpSet};
+    use crate::tar::fixtures::{archive, entry, gzip};
+    use crate::vrp::Vrp;
+    use crate::{Family, FilterOverrides};
+
+    fn route(prefix: &str, origin: &str) -> String {
+        format!("route: {}\norigin: {}\nmnt-by: FOO-MNT\nsource: DN42\n", prefix, origin)
+    }
+
+    fn base() -> MemorySource {
+        let mut source = MemorySource::new();
+        source.insert("data/filter.txt", "1 permit 172.20.0.0/14 21 29\n99 deny 0.0.0.0/0 0 32\n");
+        source.insert("data/route/172.20.0.0_24", route("172.20.0.0/24", "AS4242420000"));
+        source.insert("data/route/172.20.1.0_24", route("172.20.1.0/24", "AS4242420001"));
+        source.insert("data/route/172.20.2.0_24", route("172.20.2.0/24", "AS4242420002"));
+        source.set_commit_hash(Some("0123456789abcdef0123456789abcdef01234567".to_owned()));
+        source
+    }
+
+    /// An overlay that changes the origin of 172.20.0.0/24, deletes 172.20.1.0/24 and adds 172.20.3.0/24.
+    fn overlay(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("roa_wizard-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(directory.join("data/route")).unwrap();
+        fs::write(directory.join("data/route/172.20.0.0_24"), route("172.20.0.0/24", "AS4242420009")).unwrap();
+        fs::write(directory.join("data/route/172.20.1.0_24"), "").unwrap();
+        fs::write(directory.join("data/route/172.20.3.0_24"), route("172.20.3.0/24", "AS4242420003")).unwrap();
+        directory
+    }
+
+    fn sorted(mut names: Vec<String>) -> Vec<String> {
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn overlay_files() {
+        let directory = overlay("overlay-files");
+        let source = OverlaySource::new(Box::new(base()), &directory);
+        assert_eq!(sorted(source.list_directory("data/route").unwrap()), ["172.20.0.0_24", "172.20.2.0_24", "172.20.3.0_24"]);
+        // The overlay takes precedence, base files are read where it has none
+        assert_eq!(source.read_file("data/route/172.20.0.0_24").unwrap(), route("172.20.0.0/24", "AS4242420009").into_bytes());
+        assert_eq!(source.read_file("data/route/172.20.2.0_24").unwrap(), route("172.20.2.0/24", "AS4242420002").into_bytes());
+        // An empty file deletes the base file
+        let error = source.read_file("data/route/172.20.1.0_24").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+        assert!(error.to_string().ends_with("was deleted by the overlay"), "{}", error);
+        assert!(source.read_file("data/route/172.20.4.0_24").is_err());
+
+        assert_eq!(source.describe("data/route/172.20.0.0_24"), directory.join("data/route/172.20.0.0_24").display().to_string());
+        assert_eq!(source.describe("data/route/172.20.2.0_24"), "data/route/172.20.2.0_24");
+        assert_eq!(source.commit_hash(), None);
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn overlay_directories() {
+        let directory = overlay("overlay-directories");
+        // Directories only in the overlay or only in the base
+        fs::create_dir_all(directory.join("data/route6")).unwrap();
+        fs::write(directory.join("data/route6/fd00:1::_48"), "route6: fd00:1::/48\norigin: AS4242420000\nsource: DN42\n").unwrap();
+        let source = OverlaySource::new(Box::new(base()), &directory);
+        assert_eq!(source.list_directory("data/route6").unwrap(), ["fd00:1::_48"]);
+        assert_eq!(sorted(source.list_directory("data").unwrap()), ["filter.txt", "route", "route6"]);
+        assert!(source.list_directory("data/inetnum").is_err());
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn overlay_filter_file() {
+        let directory = overlay("overlay-filter");
+        fs::write(directory.join("data/filter.txt"), "1 permit 172.20.0.0/14 21 24\n99 deny 0.0.0.0/0 0 32\n").unwrap();
+        let source = OverlaySource::new(Box::new(base()), &directory);
+        let (set, warnings) = VrpSet::read(&source, Family::V4, &FilterOverrides::default(), &[]).unwrap();
+        assert!(warnings.is_empty());
+        assert!(set.vrps.iter().all(|v| v.max_length == 24));
+        assert_eq!(set.vrps.len(), 3);
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn overlay_diff() {
+        let directory = overlay("overlay-diff");
+        let base = base();
+        let (old, _) = VrpSet::read(&base, Family::V4, &FilterOverrides::default(), &[]).unwrap();
+        let source = OverlaySource::new(Box::new(base), &directory);
+        let (new, _) = VrpSet::read(&source, Family::V4, &FilterOverrides::default(), &[]).unwrap();
+        let diff = RegistryDiff::compare("base".to_owned(), &old, "overlay".to_owned(), &new);
+        let vrp = |prefix: &str, asn: u32| Vrp { prefix: prefix.parse().unwrap(), max_length: 29, asn };
+        let mut changes: Vec<VrpChange> = diff.changes().cloned().collect();
+        changes.sort_by_key(|c| c.prefix());
+        assert_eq!(changes, [
+            VrpChange::Origin { vrp: vrp("172.20.0.0/24", 4242420009), previous_origins: vec![4242420000] },
+            VrpChange::Removed(vrp("172.20.1.0/24", 4242420001)),
+            VrpChange::Added(vrp("172.20.3.0/24", 4242420003)),
+        ]);
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn memory_source() {
+        let source = base();
+        assert_eq!(source.list_directory("data").unwrap(), ["filter.txt", "route"]);
+        assert_eq!(source.list_directory("data/route/").unwrap(), ["172.20.0.0_24", "172.20.1.0_24", "172.20.2.0_24"]);
+        assert_eq!(source.list_directory("data/route").unwrap().len(), 3);
+        // Directories only exist as prefixes of file paths
+        assert_eq!(source.list_directory("data/rout").unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert_eq!(source.read_file("data/route/172.20.2.0_24").unwrap(), route("172.20.2.0/24", "AS4242420002").into_bytes());
+        assert_eq!(source.read_file("data/route").unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert_eq!(source.commit_hash().as_deref(), Some("0123456789abcdef0123456789abcdef01234567"));
+
+        let mut source = source;
+        source.insert("data/route/172.20.2.0_24", "replaced");
+        assert_eq!(source.read_file("data/route/172.20.2.0_24").unwrap(), b"replaced");
+        source.set_commit_hash(None);
+        assert_eq!(source.commit_hash(), None);
+    }
+
+    #[test]
+    fn tar_source() {
+        let files: [(&str, &[u8]); 2] = [("data/filter.txt", b"1 permit 172.20.0.0/14 21 29\n"), ("data/route/172.20.0.0_24", b"route")];
+        let plain = archive(&files);
+        // A top level directory is stripped, as is a leading `./`
+        let prefixed = archive(&[("./registry/data/filter.txt", files[0].1), ("./registry/data/route/172.20.0.0_24", files[1].1)]);
+        // Concatenated gzip members
+        let members = [gzip(&plain[..512]), gzip(&plain[512..])].concat();
+        for data in [plain.clone(), gzip(&plain), prefixed, members] {
+            let source = TarSource::from_bytes(&data, "registry.tar").unwrap();
+            assert_eq!(source.list_directory("data/route").unwrap(), ["172.20.0.0_24"]);
+            assert_eq!(source.read_file("data/route/172.20.0.0_24").unwrap(), b"route");
+            assert_eq!(source.commit_hash(), None);
+        }
+        let source = TarSource::from_bytes(&plain, "registry.tar").unwrap();
+        let error = source.read_file("data/route/172.20.1.0_24").unwrap_err();
+        assert_eq!(error.to_string(), "registry.tar:data/route/172.20.1.0_24 does not exist");
+
+        // The commit id `git archive` stores for the archived commit
+        let global = entry("pax_global_header", b'g', b"52 comment=0123456789abcdef0123456789abcdef01234567\n");
+        let source = TarSource::from_bytes(&[global, plain.clone()].concat(), "registry.tar").unwrap();
+        assert_eq!(source.commit_hash().as_deref(), Some("0123456789abcdef0123456789abcdef01234567"));
+
+        let error = TarSource::from_bytes(&gzip(&plain)[..40], "registry.tar.gz").unwrap_err().to_string();
+        assert!(error.starts_with("Invalid archive registry.tar.gz: "), "{}", error);
+        assert!(TarSource::from_bytes(&plain[..600], "registry.tar").is_err());
+    }
+
+    #[test]
+    fn tar_source_file_size() {
+        let large = vec![b'#'; MAX_FILE_SIZE as usize + 10];
+        let data = gzip(&archive(&[("data/route/172.20.0.0_24", &large), ("data/route/172.20.1.0_24", &large[..MAX_FILE_SIZE as usize])]));
+        let source = TarSource::from_bytes(&data, "registry.tar.gz").unwrap();
+        let error = source.read_file("data/route/172.20.0.0_24").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(error.to_string(), format!("registry.tar.gz:data/route/172.20.0.0_24 exceeds the size limit of {} bytes", MAX_FILE_SIZE));
+        // Only as much of a large file is kept as is needed to tell that it is too large
+        assert_eq!(source.files.read_file("data/route/172.20.0.0_24").unwrap().len(), MAX_FILE_SIZE as usize + 1);
+        assert_eq!(source.read_file("data/route/172.20.1.0_24").unwrap().len(), MAX_FILE_SIZE as usize);
+    }
+
+    #[test]
+    fn open_archive() {
+        let path = std::env::temp_dir().join(format!("roa_wizard-{}-registry.tar.gz", std::process::id()));
+        fs::write(&path, gzip(&archive(&[("data/route/172.20.0.0_24", b"route")]))).unwrap();
+        let source = open(&path, None).unwrap();
+        assert_eq!(source.read_file("data/route/172.20.0.0_24").unwrap(), b"route");
+        fs::remove_file(&path).unwrap();
+        let error = TarSource::open(&path).unwrap_err().to_string();
+        assert!(error.starts_with(&format!("Unable to read archive {}", path.display())), "{}", error);
+    }
+}
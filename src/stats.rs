@@ -0,0 +1,249 @@
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::str::FromStr;
+use json::JsonValue;
+use crate::date::month_of;
+use crate::git::{Commit, ObjectId};
+use crate::history::RegistryHistory;
+use crate::source::GitSource;
+use crate::vrp::address_value;
+use crate::{BoxResult, Family, FilterOverrides};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatsFormat {
+    Csv,
+    Json,
+}
+
+impl FromStr for StatsFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(StatsFormat::Csv),
+            "json" => Ok(StatsFormat::Json),
+            _ => Err(format!("unknown statistics format '{}' (expected csv or json)", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FamilyStats {
+    /// Valid route or route6 objects, whether or not the filter set accepts them.
+    pub route_objects: usize,
+    pub vrps: usize,
+    /// Distinct origins of the valid route objects.
+    pub origins: usize,
+    /// Distinct maintainers of the valid route objects.
+    pub maintainers: usize,
+    /// Address space covered by VRPs: IPv4 addresses or IPv6 /64 networks.
+    pub address_space: u128,
+}
+
+#[derive(Debug, Clone)]
+pub struct MonthStats {
+    pub year: i64,
+    pub month: i64,
+    /// The last commit of the month, or of an earlier month if there was none.
+    pub commit: ObjectId,
+    pub v4: Option<FamilyStats>,
+    pub v6: Option<FamilyStats>,
+}
+
+/// Computes statistics for the end of every month along the first-parent history of `revision`.
+pub fn growth_statistics(path: &Path, revision: &str, family: Family, filters: &FilterOverrides) -> BoxResult<Vec<MonthStats>> {
+    let GitSource { repository, commit, .. } = GitSource::open(path, revision)?;
+
+    // The newest commit of every month, newest month first
+    let mut samples: Vec<(i64, Commit)> = Vec::new();
+    let mut next = Some(commit);
+    while let Some(id) = next {
+        let commit = repository.read_commit(&id)?;
+        let (year, month) = month_of(commit.committer.time);
+        let index = year * 12 + month - 1;
+        next = commit.parents.first().copied();
+        if samples.last().is_none_or(|(last, _)| index < *last) {
+            samples.push((index, commit));
+        }
+    }
+
+    let mut state = RegistryHistory::new(repository.clone(), family, filters, Box::new(|_| true));
+    let mut result: Vec<MonthStats> = Vec::new();
+    while let Some((index, commit)) = samples.pop() {
+        state.update(&commit)?;
+        let stats = MonthStats {
+            year: index / 12,
+            month: index % 12 + 1,
+            commit: commit.id,
+            v4: (family != Family::V6).then(|| family_stats(&state, false)),
+            v6: (family != Family::V4).then(|| family_stats(&state, true)),
+        };
+        // Months without commits keep the state of the previous month
+        let until = samples.last().map_or(index + 1, |(next, _)| *next);
+        for index in index..until {
+            result.push(MonthStats { year: index / 12, month: index % 12 + 1, ..stats.clone() });
+        }
+    }
+    Ok(result)
+}
+
+fn family_stats(state: &RegistryHistory, is_v6: bool) -> FamilyStats {
+    let mut stats = FamilyStats::default();
+    let mut origins: BTreeSet<u32> = BTreeSet::new();
+    let mut maintainers: BTreeSet<&str> = BTreeSet::new();
+    for object in state.objects(is_v6) {
+        stats.route_objects += 1;
+        origins.extend(&object.origins);
+        maintainers.extend(object.maintainers.iter().map(String::as_str));
+    }
+    stats.origins = origins.len();
+    stats.maintainers = maintainers.len();
+
+    let accepted = state.accepted_objects(is_v6);
+    stats.vrps = accepted.iter().map(|o| o.origins.len()).sum();
+    let mut ranges: Vec<(u128, u128)> = accepted.iter()
+        .map(|o| (address_value(o.prefix.first_address()), address_value(o.prefix.last_address())))
+        .collect();
+    ranges.sort();
+    let mut covered: u128 = 0;
+    let mut current: Option<(u128, u128)> = None;
+    for (first, last) in ranges {
+        current = match current {
+            Some((start, end)) if first <= end.saturating_add(1) => Some((start, end.max(last))),
+            Some((start, end)) => {
+                covered = covered.saturating_add(end - start).saturating_add(1);
+                Some((first, last))
+            }
+            None => Some((first, last)),
+        };
+    }
+    if let Some((start, end)) = current {
+        covered = covered.saturating_add(end - start).saturating_add(1);
+    }
+    stats.address_space = if is_v6 { covered >> 64 } else { covered };
+    stats
+}
+
+pub fn render_statistics(stats: &[MonthStats], format: StatsFormat) -> String {
+    match format {
+        StatsFormat::Csv => {
+            let mut result = "month,commit,family,route_objects,vrps,origins,maintainers,address_space\n".to_owned();
+            for month in stats {
+                for (family, family_stats) in [("v4", &month.v4), ("v6", &month.v6)] {
+                    if let Some(s) = family_stats {
+                        result.push_str(&format!("{:04}-{:02},{},{},{},{},{},{},{}\n", month.year, month.month, month.commit.to_hex(),
+                                                 family, s.route_objects, s.vrps, s.origins, s.maintainers, s.address_space));
+                    }
+                }
+            }
+            result
+        }
+        StatsFormat::Json => {
+            let mut data = JsonValue::new_array();
+            for month in stats {
+                let mut entry = JsonValue::new_object();
+                entry["month"] = format!("{:04}-{:02}", month.year, month.month).into();
+                entry["commit"] = month.commit.to_hex().into();
+                for (family, family_stats) in [("v4", &month.v4), ("v6", &month.v6)] {
+                    if let Some(s) = family_stats {
+                        let mut object = JsonValue::new_object();
+                        object["routeObjects"] = s.route_objects.into();
+                        object["vrps"] = s.vrps.into();
+                        object["origins"] = s.origins.into();
+                        object["maintainers"] = s.maintainers.into();
+                        object["addressSpace"] = (s.address_space.min(u64::MAX as u128) as u64).into();
+                        entry[family] = object;
+                    }
+                }
+                data.push(entry).expect("Error converting data to JSON");
+            }
+            data.dump()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::date::parse_time;
+    use crate::git::fixtures::{TestRepository, BASE};
+
+    const FILTER: &str = "1 permit 172.20.0.0/14 21 29\n99 deny 0.0.0.0/0 0 32\n";
+    const FILTER6: &str = "1 permit fd00::/8 44 64\n99 deny ::/0 0 128\n";
+
+    fn route(prefix: &str, origin: &str, maintainer: &str) -> String {
+        let key = if prefix.contains(':') { "route6" } else { "route" };
+        format!("{}: {}\norigin: {}\nmnt-by: {}\nsource: DN42\n", key, prefix, origin, maintainer)
+    }
+
+    /// Two commits in January, none in February and two in March.
+    fn history(name: &str) -> (TestRepository, Vec<String>) {
+        let mut repository = TestRepository::new(name);
+        let filter = repository.blob(FILTER);
+        let filter6 = repository.blob(FILTER6);
+        let half = repository.blob(&route("172.20.0.128/25", "AS4242420001", "BAR-MNT"));
+        let v6 = repository.blob(&route("fd00:1::/48", "AS4242420000", "FOO-MNT"));
+        let small_v6 = repository.blob(&route("fd00:2::/63", "AS4242420002", "FOO-MNT"));
+        let rejected = repository.blob(&route("10.0.0.0/24", "AS4242420003", "BAZ-MNT"));
+        let base = [("data/filter.txt", filter.as_str()), ("data/filter6.txt", &filter6), ("data/route/172.20.0.0_24", BASE),
+                    ("data/route6/fd00:1::_48", &v6)];
+        let with = |files: &[(&'static str, &str)]| -> Vec<(&str, String)> {
+            base.iter().chain(files).map(|(path, id)| (*path, id.to_string())).collect()
+        };
+        let trees = [
+            with(&[]),
+            with(&[("data/route/172.20.0.128_25", &half)]),
+            with(&[("data/route/172.20.0.128_25", &half), ("data/route6/fd00:2::_63", &small_v6), ("data/route/10.0.0.0_24", &rejected)]),
+            with(&[("data/route6/fd00:2::_63", &small_v6), ("data/route/10.0.0.0_24", &rejected)]),
+        ];
+        let mut commits: Vec<String> = Vec::new();
+        for (files, time) in trees.iter().zip(["2024-01-05", "2024-01-20", "2024-03-10", "2024-03-25"]) {
+            let files: Vec<(&str, &str)> = files.iter().map(|(path, id)| (*path, id.as_str())).collect();
+            let tree = repository.tree(&files);
+            let commit = repository.commit(&tree, commits.last().map(String::as_str), parse_time(time).unwrap(), time);
+            commits.push(commit);
+        }
+        (repository, commits)
+    }
+
+    fn stats(route_objects: usize, vrps: usize, origins: usize, maintainers: usize, address_space: u128) -> FamilyStats {
+        FamilyStats { route_objects, vrps, origins, maintainers, address_space }
+    }
+
+    #[test]
+    fn monthly_samples() {
+        let (repository, commits) = history("stats-monthly");
+        let result = growth_statistics(&repository.path, &commits[3], Family::Both, &FilterOverrides::default()).unwrap();
+        let months: Vec<(i64, i64, String)> = result.iter().map(|m| (m.year, m.month, m.commit.to_hex())).collect();
+        // The last commit of every month, February keeps the state of January
+        assert_eq!(months, [(2024, 1, commits[1].clone()), (2024, 2, commits[1].clone()), (2024, 3, commits[3].clone())]);
+
+        // Overlapping prefixes are counted once
+        assert_eq!(result[0].v4, Some(stats(2, 2, 2, 2, 256)));
+        assert_eq!(result[1].v4, result[0].v4);
+        // Objects rejected by the filter set count as route objects, but not as VRPs or address space
+        assert_eq!(result[2].v4, Some(stats(2, 1, 2, 2, 256)));
+        // IPv6 address space in /64 networks
+        assert_eq!(result[0].v6, Some(stats(1, 1, 1, 1, 1 << 16)));
+        assert_eq!(result[2].v6, Some(stats(2, 2, 2, 1, (1 << 16) + 2)));
+
+        let result = growth_statistics(&repository.path, &commits[2], Family::V6, &FilterOverrides::default()).unwrap();
+        assert_eq!(result.len(), 3);
+        assert_eq!(result[2].commit.to_hex(), commits[2]);
+        assert!(result.iter().all(|m| m.v4.is_none() && m.v6.is_some()));
+    }
+
+    #[test]
+    fn render() {
+        let (repository, commits) = history("stats-render");
+        let result = growth_statistics(&repository.path, &commits[0], Family::Both, &FilterOverrides::default()).unwrap();
+        assert_eq!(render_statistics(&result, StatsFormat::Csv), format!(
+            "month,commit,family,route_objects,vrps,origins,maintainers,address_space\n\
+             2024-01,{0},v4,1,1,1,1,256\n2024-01,{0},v6,1,1,1,1,65536\n", commits[0]));
+        let data = json::parse(&render_statistics(&result, StatsFormat::Json)).unwrap();
+        assert_eq!(data[0]["month"], "2024-01");
+        assert_eq!(data[0]["commit"], commits[0].as_str());
+        assert_eq!(data[0]["v4"]["routeObjects"], 1);
+        assert_eq!(data[0]["v6"]["addressSpace"], 65536);
+        assert!(StatsFormat::from_str("xml").is_err());
+    }
+}
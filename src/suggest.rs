@@ -0,0 +1,241 @@
+//! Route objects that would make observed announcements valid, for announcements whose prefix
+//! and origin are held by the same maintainer.
+
+use std::cell::Cell;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::PathBuf;
+use cidr_utils::cidr::{IpCidr, IpInet};
+use crate::announcements::Announcement;
+use crate::parse::RouteObject;
+use crate::registry::{is_route_file, ObjectClass, RegistryDatabase, RegistryObject};
+use crate::rpsl::{Attribute, RpslObject};
+use crate::source::RegistrySource;
+use crate::vrp::{prefix_string, validate, ValidationState};
+use crate::{apply_filter_sets, get_vrps, BoxResult, Family, FilterOverrides, Warnings};
+
+/// A new route or route6 object, or an existing one with additional origins.
+#[derive(Debug, Clone)]
+pub struct Suggestion {
+    /// Path of the object file relative to the registry root, e.g. `data/route/172.20.0.0_24`.
+    pub path: String,
+    pub object: RpslObject,
+    pub existing: bool,
+    /// The announcements the object makes valid.
+    pub announcements: Vec<Announcement>,
+}
+
+#[derive(Debug, Default)]
+pub struct SuggestionReport {
+    pub suggestions: Vec<Suggestion>,
+    /// Announcements that are not valid and for which no object is suggested, with the reason.
+    pub skipped: Vec<(Announcement, String)>,
+    pub valid_count: usize,
+}
+
+/// The network an announced prefix lies in, and the announcements of the prefix with the maintainers
+/// shared by the network and the aut-num object of their origin.
+type Candidate<'a> = (&'a RegistryObject, Vec<(Announcement, Vec<String>)>);
+
+/// Suggests the route objects that make announcements of the family valid which are NotFound or
+/// Invalid. An object is only suggested if the prefix lies within an inetnum or inet6num object
+/// sharing a maintainer with the aut-num object of the origin, and the filter set permits it.
+pub fn suggest_route_objects(source: &dyn RegistrySource, announcements: &[Announcement], family: Family, filters: &FilterOverrides, slurm_files: &[PathBuf]) -> BoxResult<(SuggestionReport, Warnings)> {
+    let (vrps, mut warnings) = get_vrps(source, family, filters, slurm_files)?;
+    let (database, database_warnings) = RegistryDatabase::read(source)?;
+    warnings.extend(database_warnings.into_iter().filter(|w| !is_route_file(&w.object)));
+    let networks: HashMap<IpCidr, &RegistryObject> = database.objects(&ObjectClass::Inetnum).chain(database.objects(&ObjectClass::Inet6num))
+        .filter_map(|o| Some((o.prefix()?, o)))
+        .collect();
+
+    let mut report = SuggestionReport::default();
+    let mut candidates: BTreeMap<IpCidr, Candidate> = BTreeMap::new();
+    for announcement in announcements.iter().filter(|a| family.contains(&a.prefix)) {
+        if validate(&vrps, &announcement.prefix, announcement.origin).0 == ValidationState::Valid {
+            report.valid_count += 1;
+            continue;
+        }
+        match shared_maintainers(&database, &networks, announcement) {
+            Ok((network, maintainers)) => {
+                candidates.entry(announcement.prefix).or_insert((network, Vec::new())).1.push((announcement.clone(), maintainers));
+            }
+            Err(reason) => report.skipped.push((announcement.clone(), reason)),
+        }
+    }
+
+    // Announcements the filter set would not accept an object for
+    for is_v6 in [false, true] {
+        let mut objects: Vec<RouteObject> = candidates.keys().filter(|p| p.is_ipv6() == is_v6).map(|prefix| RouteObject {
+            file: String::new(),
+            prefix: *prefix,
+            origins: Vec::new(),
+            max_length: Cell::new(None),
+            maintainers: Vec::new(),
+            source: None,
+        }).collect();
+        if objects.is_empty() {
+            continue;
+        }
+        apply_filter_sets(&mut objects, is_v6, source, filters)?;
+        let permitted: BTreeSet<IpCidr> = objects.iter().map(|o| o.prefix).collect();
+        candidates.retain(|prefix, (_, entries)| {
+            if prefix.is_ipv6() != is_v6 || permitted.contains(prefix) {
+                return true;
+            }
+            report.skipped.extend(entries.drain(..).map(|(a, _)| (a, "the filter set does not permit the prefix".to_owned())));
+            false
+        });
+    }
+
+    for (prefix, (network, entries)) in candidates {
+        let class = if prefix.is_ipv6() { ObjectClass::Route6 } else { ObjectClass::Route };
+        let existing = database.get(&class, &prefix_string(&prefix));
+        let mut object = match existing {
+            Some(existing) => existing.object.clone(),
+            None => new_object(&class, &prefix, network),
+        };
+        let mut announcements: Vec<Announcement> = Vec::new();
+        // A new object is maintained by the maintainers shared by all of its announcements
+        let mut shared: Option<Vec<String>> = None;
+        for (announcement, maintainers) in entries {
+            let origin = format!("AS{}", announcement.origin);
+            if object.get_all("origin").any(|o| o == origin) {
+                // The object already authorises the origin, but is rejected for another reason
+                report.skipped.push((announcement, format!("{} lists the origin already, see the check command", existing.map_or("", |e| &e.path))));
+                continue;
+            }
+            if let Some(existing) = existing.filter(|e| !e.maintainers().any(|m| maintainers.iter().any(|s| s.eq_ignore_ascii_case(m)))) {
+                let reason = format!("{} is maintained by {}", existing.path, existing.maintainers().collect::<Vec<&str>>().join(", "));
+                report.skipped.push((announcement, reason));
+                continue;
+            }
+            if existing.is_none() {
+                let common: Vec<String> = match &shared {
+                    Some(shared) => shared.iter().filter(|m| maintainers.iter().any(|n| n.eq_ignore_ascii_case(m))).cloned().collect(),
+                    None => maintainers.clone(),
+                };
+                if common.is_empty() {
+                    let reason = format!("the maintainers shared with {} ({}) differ from those of the other origins of the prefix ({})",
+                                         origin, maintainers.join(", "), shared.as_deref().unwrap_or_default().join(", "));
+                    report.skipped.push((announcement, reason));
+                    continue;
+                }
+                shared = Some(common);
+            }
+            let position = object.attributes.iter().rposition(|a| a.key == "origin").map_or(1, |i| i + 1);
+            object.attributes.insert(position, attribute("origin", &origin));
+            announcements.push(announcement);
+        }
+        if announcements.is_empty() {
+            continue;
+        }
+        for maintainer in shared.unwrap_or_default() {
+            let position = object.attributes.iter().rposition(|a| a.key == "mnt-by" || a.key == "origin").map_or(1, |i| i + 1);
+            object.attributes.insert(position, attribute("mnt-by", &maintainer));
+        }
+        let path = match existing {
+            Some(existing) => existing.path.clone(),
+            None => format!("data/{}/{}", class, prefix_string(&prefix).replace('/', "_")),
+        };
+        report.suggestions.push(Suggestion { path, object, existing: existing.is_some(), announcements });
+    }
+    report.skipped.sort();
+    Ok((report, warnings))
+}
+
+/// The most specific inetnum or inet6num object containing the announced prefix and the maintainers
+/// it shares with the aut-num object of the origin.
+fn shared_maintainers<'a>(database: &RegistryDatabase, networks: &HashMap<IpCidr, &'a RegistryObject>, announcement: &Announcement) -> Result<(&'a RegistryObject, Vec<String>), String> {
+    let prefix = &announcement.prefix;
+    let network = (0..=prefix.network_length()).rev()
+        .find_map(|length| networks.get(&IpInet::new(prefix.first_address(), length).ok()?.network()))
+        .ok_or_else(|| format!("{} is not within an inetnum or inet6num object", prefix_string(prefix)))?;
+    let aut_num = database.get(&ObjectClass::AutNum, &format!("AS{}", announcement.origin))
+        .ok_or_else(|| format!("AS{} has no aut-num object", announcement.origin))?;
+    let maintainers: Vec<String> = network.maintainers()
+        .filter(|m| aut_num.maintainers().any(|a| a.eq_ignore_ascii_case(m)))
+        .map(str::to_owned)
+        .collect();
+    if maintainers.is_empty() {
+        let list = |o: &RegistryObject| o.maintainers().collect::<Vec<&str>>().join(", ");
+        return Err(format!("{} ({}) and {} ({}) share no maintainer", network.path, list(network), aut_num.path, list(aut_num)));
+    }
+    Ok((network, maintainers))
+}
+
+/// A route object for `prefix` without origins and maintainers, with the source of its network.
+fn new_object(class: &ObjectClass, prefix: &IpCidr, network: &RegistryObject) -> RpslObject {
+    let mut object = RpslObject::default();
+    object.attributes.push(attribute(class.as_str(), &prefix_string(prefix)));
+    object.attributes.push(attribute("source", network.get("source").unwrap_or("DN42")));
+    object
+}
+
+fn attribute(key: &str, value: &str) -> Attribute {
+    Attribute { key: key.to_owned(), value: value.to_owned(), line: 0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::announcements::parse_announcements;
+    use crate::source::MemorySource;
+
+    fn registry() -> MemorySource {
+        let mut source = MemorySource::new();
+        source.insert("data/filter.txt", "1 permit 172.20.0.0/14 16 28\n");
+        source.insert("data/inetnum/172.20.4.0_22", "inetnum: 172.20.4.0 - 172.20.7.255\ncidr: 172.20.4.0/22\nmnt-by: FOO-MNT\nmnt-by: BAR-MNT\nsource: DN42\n");
+        source.insert("data/aut-num/AS4242420007", "aut-num: AS4242420007\nmnt-by: BAR-MNT\nmnt-by: FOO-MNT\nsource: DN42\n");
+        source.insert("data/aut-num/AS4242420008", "aut-num: AS4242420008\nmnt-by: BAR-MNT\nsource: DN42\n");
+        source.insert("data/aut-num/AS4242420009", "aut-num: AS4242420009\nmnt-by: FOO-MNT\nsource: DN42\n");
+        source.insert("data/aut-num/AS4242420010", "aut-num: AS4242420010\nmnt-by: BAZ-MNT\nsource: DN42\n");
+        source.insert("data/route/172.20.5.0_24", "route: 172.20.5.0/24\norigin: AS4242420009\nmnt-by: FOO-MNT\nsource: DN42\n");
+        source
+    }
+
+    fn suggest(announcements: &str) -> SuggestionReport {
+        let announcements = parse_announcements(announcements.as_bytes()).unwrap();
+        suggest_route_objects(&registry(), &announcements, Family::V4, &FilterOverrides::default(), &[]).unwrap().0
+    }
+
+    fn skipped(report: &SuggestionReport) -> Vec<(String, &str)> {
+        report.skipped.iter().map(|(a, reason)| (a.to_string(), reason.as_str())).collect()
+    }
+
+    #[test]
+    fn new_object_maintainers() {
+        let report = suggest("172.20.4.0/24 AS4242420007\n172.20.4.0/24 AS4242420008\n172.20.4.0/24 AS4242420009\n");
+        assert_eq!(report.suggestions.len(), 1);
+        let suggestion = &report.suggestions[0];
+        assert_eq!(suggestion.path, "data/route/172.20.4.0_24");
+        assert!(!suggestion.existing);
+        // Only the maintainer shared by both suggested origins
+        assert_eq!(suggestion.object.to_string(), "route:              172.20.4.0/24\norigin:             AS4242420007\n\
+            origin:             AS4242420008\nmnt-by:             BAR-MNT\nsource:             DN42\n");
+        assert_eq!(skipped(&report), [("172.20.4.0/24 AS4242420009".to_owned(),
+            "the maintainers shared with AS4242420009 (FOO-MNT) differ from those of the other origins of the prefix (BAR-MNT)")]);
+    }
+
+    #[test]
+    fn existing_object() {
+        let report = suggest("172.20.5.0/24 AS4242420009\n172.20.5.0/24 AS4242420007\n172.20.5.0/24 AS4242420008\n");
+        assert_eq!(report.valid_count, 1);
+        assert_eq!(report.suggestions.len(), 1);
+        let suggestion = &report.suggestions[0];
+        assert!(suggestion.existing);
+        assert_eq!(suggestion.object.get_all("origin").collect::<Vec<&str>>(), ["AS4242420009", "AS4242420007"]);
+        assert_eq!(suggestion.object.get_all("mnt-by").collect::<Vec<&str>>(), ["FOO-MNT"]);
+        assert_eq!(skipped(&report), [("172.20.5.0/24 AS4242420008".to_owned(), "data/route/172.20.5.0_24 is maintained by FOO-MNT")]);
+    }
+
+    #[test]
+    fn skipped_announcements() {
+        let report = suggest("10.0.0.0/8 AS4242420007\n172.20.6.0/24 AS4242420010\n172.20.6.0/24 AS4242420011\n172.20.7.0/29 AS4242420007\n");
+        assert!(report.suggestions.is_empty());
+        assert_eq!(skipped(&report), [
+            ("10.0.0.0/8 AS4242420007".to_owned(), "10.0.0.0/8 is not within an inetnum or inet6num object"),
+            ("172.20.6.0/24 AS4242420010".to_owned(), "data/inetnum/172.20.4.0_22 (FOO-MNT, BAR-MNT) and data/aut-num/AS4242420010 (BAZ-MNT) share no maintainer"),
+            ("172.20.6.0/24 AS4242420011".to_owned(), "AS4242420011 has no aut-num object"),
+            ("172.20.7.0/29 AS4242420007".to_owned(), "the filter set does not permit the prefix"),
+        ]);
+    }
+}
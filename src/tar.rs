@@ -0,0 +1,243 @@
+//! Reader for tar archives in ustar, GNU and pax format as produced by `tar` and `git archive`.
+
+use crate::BoxResult;
+
+const BLOCK_SIZE: usize = 512;
+
+/// The regular files of an archive and the commit id `git archive` stores in the pax global header.
+#[derive(Debug)]
+pub struct TarArchive {
+    pub files: Vec<(String, Vec<u8>)>,
+    pub commit: Option<String>,
+}
+
+/// Reads the entries of an archive. Files larger than `max_file_size` are cut off after
+/// `max_file_size + 1` bytes, which is enough to tell that they are too large.
+pub fn read_tar(data: &[u8], max_file_size: usize) -> BoxResult<TarArchive> {
+    let mut archive = TarArchive { files: Vec::new(), commit: None };
+    let mut long_name: Option<String> = None;
+    let mut position = 0;
+    while position + BLOCK_SIZE <= data.len() {
+        let header = &data[position..position + BLOCK_SIZE];
+        // The archive ends with zero blocks
+        if header.iter().all(|b| *b == 0) {
+            return Ok(archive);
+        }
+        verify_checksum(header).map_err(|e| format!("{} at offset {}", e, position))?;
+        let size = parse_number(&header[124..136]).ok_or_else(|| format!("invalid entry size at offset {}", position))?;
+        let start = position + BLOCK_SIZE;
+        let content = usize::try_from(size).ok()
+            .and_then(|size| data.get(start..start.checked_add(size)?))
+            .ok_or("truncated tar archive")?;
+        position = start + content.len().div_ceil(BLOCK_SIZE) * BLOCK_SIZE;
+
+        match header[156] {
+            // GNU long name of the next entry
+            b'L' => long_name = Some(text(content)),
+            // pax extended header of the next entry
+            b'x' => {
+                if let Some(path) = pax_records(content)?.into_iter().find(|(k, _)| k == "path").map(|(_, v)| v) {
+                    long_name = Some(path);
+                }
+            }
+            b'g' => {
+                if let Some(comment) = pax_records(content)?.into_iter().find(|(k, _)| k == "comment").map(|(_, v)| v) {
+                    archive.commit = Some(comment);
+                }
+            }
+            b'0' | 0 | b'7' => {
+                let name = long_name.take().unwrap_or_else(|| {
+                    let name = text(&header[0..100]);
+                    match &header[257..262] == b"ustar" && header[345] != 0 {
+                        true => format!("{}/{}", text(&header[345..500]), name),
+                        false => name,
+                    }
+                });
+                archive.files.push((name, content[..content.len().min(max_file_size.saturating_add(1))].to_vec()));
+            }
+            // Directories, links and devices carry no registry data
+            _ => long_name = None,
+        }
+    }
+    Err("truncated tar archive".into())
+}
+
+fn verify_checksum(header: &[u8]) -> BoxResult<()> {
+    let expected = parse_number(&header[148..156]).ok_or("invalid tar header")?;
+    let sum: u64 = header.iter().enumerate()
+        .map(|(i, b)| if (148..156).contains(&i) { b' ' as u64 } else { *b as u64 })
+        .sum();
+    if sum != expected {
+        return Err("tar header checksum mismatch".into());
+    }
+    Ok(())
+}
+
+/// Parses an octal number field, or a base-256 one as used by GNU tar for large values.
+fn parse_number(field: &[u8]) -> Option<u64> {
+    if field[0] & 0x80 != 0 {
+        return field[1..].iter().try_fold((field[0] & 0x7f) as u64, |n, b| n.checked_mul(256).map(|n| n + *b as u64));
+    }
+    let digits = text(field);
+    let digits = digits.trim_matches(|c: char| c == ' ' || c == '\0');
+    if digits.is_empty() {
+        return Some(0);
+    }
+    u64::from_str_radix(digits, 8).ok()
+}
+
+fn text(field: &[u8]) -> String {
+    let end = field.iter().position(|b| *b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+/// Parses the `<length> <key>=<value>\n` records of a pax header.
+fn pax_records(content: &[u8]) -> BoxResult<Vec<(String, String)>> {
+    let mut records = Vec::new();
+    let mut rest = content;
+    while !rest.is_empty() && rest[0] != 0 {
+        let invalid = || "invalid pax header";
+        let space = rest.iter().position(|b| *b == b' ').ok_or_else(invalid)?;
+        let length: usize = std::str::from_utf8(&rest[..space]).ok().and_then(|l| l.parse().ok()).ok_or_else(invalid)?;
+        let record = rest.get(space + 1..length).and_then(|r| r.strip_suffix(b"\n")).ok_or_else(invalid)?;
+        let record = String::from_utf8_lossy(record);
+        let (key, value) = record.split_once('=').ok_or_else(invalid)?;
+        records.push((key.to_owned(), value.to_owned()));
+        rest = &rest[length..];
+    }
+    Ok(records)
+}
+
+/// Archives for tests.
+#[cfg(test)]
+pub(crate) mod fixtures {
+    use crate::inflate::crc32;
+
+    /// A ustar header and the padded content of one entry.
+    pub fn entry(name: &str, kind: u8, content: &[u8]) -> Vec<u8> {
+        let mut header = [0u8; 512];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        header[100..108].copy_from_slice(b"0000644\0");
+        header[124..136].copy_from_slice(format!("{:011o}\0", content.len()).as_bytes());
+        header[136..148].copy_from_slice(b"00000000000\0");
+        header[156] = kind;
+        header[257..265].copy_from_slice(b"ustar\x0000");
+        header[148..156].copy_from_slice(b"        ");
+        let sum: u32 = header.iter().map(|b| *b as u32).sum();
+        header[148..156].copy_from_slice(format!("{:06o}\0 ", sum).as_bytes());
+        let mut data = header.to_vec();
+        data.extend_from_slice(content);
+        data.resize(data.len().div_ceil(512) * 512, 0);
+        data
+    }
+
+    /// An archive of regular files, terminated by two zero blocks.
+    pub fn archive(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut data: Vec<u8> = files.iter().flat_map(|(name, content)| entry(name, b'0', content)).collect();
+        data.resize(data.len() + 1024, 0);
+        data
+    }
+
+    /// `data` as gzip member of stored deflate blocks.
+    pub fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut result = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff];
+        let chunks: Vec<&[u8]> = data.chunks(0xffff).collect();
+        for (i, chunk) in chunks.iter().enumerate() {
+            let length = (chunk.len() as u16).to_le_bytes();
+            result.extend_from_slice(&[(i + 1 == chunks.len()) as u8, length[0], length[1], !length[0], !length[1]]);
+            result.extend_from_slice(chunk);
+        }
+        if data.is_empty() {
+            result.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+        }
+        result.extend_from_slice(&crc32(data).to_le_bytes());
+        result.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::fixtures::{archive, entry};
+
+    fn pax(records: &[(&str, &str)]) -> Vec<u8> {
+        let mut data: Vec<u8> = Vec::new();
+        for (key, value) in records {
+            let record = format!(" {}={}\n", key, value);
+            // The length includes its own digits
+            let mut length = record.len() + 1;
+            while length != record.len() + length.to_string().len() {
+                length += 1;
+            }
+            data.extend_from_slice(format!("{}{}", length, record).as_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn regular_files() {
+        let data = archive(&[("data/filter.txt", b"1 permit 172.20.0.0/14 21 29\n"), ("data/route/172.20.0.0_24", b""), ("README.md", &[b'x'; 600])]);
+        let archive = read_tar(&data, usize::MAX).unwrap();
+        assert_eq!(archive.files, [
+            ("data/filter.txt".to_owned(), b"1 permit 172.20.0.0/14 21 29\n".to_vec()),
+            ("data/route/172.20.0.0_24".to_owned(), Vec::new()),
+            ("README.md".to_owned(), vec![b'x'; 600]),
+        ]);
+        assert_eq!(archive.commit, None);
+        assert!(read_tar(&[0; 1024], usize::MAX).unwrap().files.is_empty());
+    }
+
+    #[test]
+    fn long_names_and_headers() {
+        let long_name = format!("data/route/{}", "a".repeat(120));
+        let mut data = entry("pax_global_header", b'g', &pax(&[("comment", "0123456789abcdef0123456789abcdef01234567")]));
+        data.extend(entry("data/", b'5', b""));
+        data.extend(entry("././@LongLink", b'L', format!("{}\0", long_name).as_bytes()));
+        data.extend(entry("data/route/aaaa", b'0', b"gnu"));
+        data.extend(entry("PaxHeader", b'x', &pax(&[("mtime", "0"), ("path", "data/route6/fd00::_8")])));
+        data.extend(entry("data/route6/fd00", b'0', b"pax"));
+        // The long name only applies to the next entry
+        data.extend(entry("data/link", b'2', b""));
+        data.extend(entry("data/short", b'0', b"short"));
+        data.extend([0; 1024]);
+        let archive = read_tar(&data, usize::MAX).unwrap();
+        let names: Vec<&str> = archive.files.iter().map(|(n, _)| n.as_str()).collect();
+        assert_eq!(names, [long_name.as_str(), "data/route6/fd00::_8", "data/short"]);
+        assert_eq!(archive.commit.as_deref(), Some("0123456789abcdef0123456789abcdef01234567"));
+    }
+
+    #[test]
+    fn size_limit() {
+        let data = archive(&[("small", b"0123"), ("large", &[b'x'; 5000])]);
+        let archive = read_tar(&data, 4).unwrap();
+        assert_eq!(archive.files[0].1, b"0123");
+        assert_eq!(archive.files[1].1, b"xxxxx");
+    }
+
+    #[test]
+    fn invalid_archives() {
+        let data = archive(&[("data/filter.txt", b"content")]);
+        // Missing end of archive blocks, truncated content and truncated headers
+        assert_eq!(read_tar(&data[..1024], usize::MAX).unwrap_err().to_string(), "truncated tar archive");
+        assert!(read_tar(&data[..600], usize::MAX).is_err());
+        assert!(read_tar(&data[..100], usize::MAX).is_err());
+        let mut corrupt = data.clone();
+        corrupt[0] = b'D';
+        assert_eq!(read_tar(&corrupt, usize::MAX).unwrap_err().to_string(), "tar header checksum mismatch at offset 0");
+        let mut corrupt = data.clone();
+        corrupt[124..136].copy_from_slice(b"77777777777\0");
+        assert!(read_tar(&corrupt, usize::MAX).is_err());
+        let bad_pax = [entry("PaxHeader", b'x', b"99 path=x\n"), archive(&[("a", b"")])].concat();
+        assert_eq!(read_tar(&bad_pax, usize::MAX).unwrap_err().to_string(), "invalid pax header");
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(parse_number(b"0000644\0"), Some(0o644));
+        assert_eq!(parse_number(b"   12 \0"), Some(10));
+        assert_eq!(parse_number(b"\0\0\0\0"), Some(0));
+        assert_eq!(parse_number(&[0x80, 0, 0, 1, 0]), Some(256));
+        assert_eq!(parse_number(b"0009"), None);
+    }
+}
@@ -0,0 +1,98 @@
+use std::fmt;
+use std::net::IpAddr;
+use cidr_utils::cidr::IpCidr;
+use json::JsonValue;
+
+/// A validated ROA payload: a prefix that may be originated by `asn`
+/// with a prefix length of up to `max_length`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Vrp {
+    pub prefix: IpCidr,
+    pub max_length: u8,
+    pub asn: u32,
+}
+
+impl Vrp {
+    pub fn get_bird_format(&self) -> String {
+        format!("route {prefix} max {max_length} as {origin};\n", prefix = self.get_prefix_string(),
+                max_length = self.max_length, origin = self.asn)
+    }
+
+    pub fn get_json_object(&self) -> JsonValue {
+        let mut data = JsonValue::new_object();
+        data["prefix"] = self.get_prefix_string().into();
+        data["maxLength"] = self.max_length.into();
+        data["asn"] = self.asn.to_string().into();
+        data
+    }
+
+    pub fn get_prefix_string(&self) -> String {
+        prefix_string(&self.prefix)
+    }
+}
+
+impl fmt::Display for Vrp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} max {} as AS{}", self.get_prefix_string(), self.max_length, self.asn)
+    }
+}
+
+/// Formats a prefix, always including the prefix length (`IpCidr` omits it for host routes).
+pub fn prefix_string(prefix: &IpCidr) -> String {
+    if prefix.is_host_address() {
+        return if prefix.is_ipv4() {
+            prefix.to_string() + "/32"
+        } else {
+            prefix.to_string() + "/128"
+        };
+    }
+    prefix.to_string()
+}
+
+/// Returns true if `inner` lies entirely within `outer`.
+pub fn prefix_covers(outer: &IpCidr, inner: &IpCidr) -> bool {
+    outer.is_ipv4() == inner.is_ipv4()
+        && outer.network_length() <= inner.network_length()
+        && outer.contains(&inner.first_address())
+}
+
+/// The numeric value of an address, for comparing address ranges.
+pub(crate) fn address_value(address: IpAddr) -> u128 {
+    match address {
+        IpAddr::V4(address) => u32::from(address) as u128,
+        IpAddr::V6(address) => u128::from(address),
+    }
+}
+
+/// Route origin validation state as defined in RFC 6811.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValidationState {
+    Valid,
+    Invalid,
+    NotFound,
+}
+
+impl fmt::Display for ValidationState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationState::Valid => write!(f, "valid"),
+            ValidationState::Invalid => write!(f, "invalid"),
+            ValidationState::NotFound => write!(f, "not-found"),
+        }
+    }
+}
+
+/// Validates a route for `prefix` originated by `asn` against the VRPs (RFC 6811).
+/// AS0 VRPs cover prefixes without matching any route, so they can only make routes invalid
+/// (RFC 6483, RFC 7607). Also returns the VRPs covering the prefix.
+pub fn validate<'a>(vrps: &'a [Vrp], prefix: &IpCidr, asn: u32) -> (ValidationState, Vec<&'a Vrp>) {
+    let covering: Vec<&Vrp> = vrps.iter().filter(|v| prefix_covers(&v.prefix, prefix)).collect();
+    let state = if covering.is_empty() {
+        ValidationState::NotFound
+    } else if covering.iter().any(|v| v.asn == asn && v.asn != 0 && prefix.network_length() <= v.max_length) {
+        ValidationState::Valid
+    } else {
+        ValidationState::Invalid
+    };
+    (state, covering)
+}
@@ -0,0 +1,434 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+use crate::config::{read_config, Config, SourceKind};
+use crate::git::find_git_dir;
+use crate::run::{run_config, RunError, RunOptions, RunSummary};
+use crate::{BoxResult, FilterOverrides};
+
+#[cfg(target_os = "linux")]
+mod inotify;
+#[cfg(unix)]
+mod signal;
+
+#[cfg(unix)]
+use signal::install_reload_handler;
+
+static RELOAD_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone)]
+pub struct WatchOptions {
+    /// Quiet period required after the last change before regenerating.
+    pub debounce: Duration,
+    /// Interval between scans when polling is used.
+    pub poll_interval: Duration,
+    /// Use polling even if inotify is available.
+    pub force_polling: bool,
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        WatchOptions {
+            debounce: Duration::from_secs(2),
+            poll_interval: Duration::from_secs(5),
+            force_polling: false,
+        }
+    }
+}
+
+/// A directory to watch, optionally restricted to some file names within it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct WatchTarget {
+    directory: PathBuf,
+    names: Option<Vec<OsString>>,
+}
+
+impl WatchTarget {
+    fn matches(&self, name: &OsString) -> bool {
+        self.names.as_ref().is_none_or(|names| names.contains(name))
+    }
+}
+
+/// What happened while watching, for the caller to report.
+#[derive(Debug)]
+pub enum WatchEvent<'a> {
+    /// The outputs were regenerated, `result` holds the error if the run failed.
+    Regenerated {
+        summary: &'a RunSummary,
+        result: Result<(), &'a RunError>,
+        outputs: usize,
+        elapsed: Duration,
+    },
+    Reloaded(&'a Path),
+    /// The config file could not be read again, the previous configuration is kept.
+    ReloadFailed(&'a (dyn Error + Send + Sync)),
+    /// inotify is not available, changes are detected by polling.
+    Polling(&'a io::Error),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Event {
+    Changed,
+    Reload,
+}
+
+/// What to do after the watcher reported something.
+#[derive(Debug, PartialEq, Eq)]
+enum Action {
+    Regenerate,
+    Reload,
+    /// The watcher misses changes and has to be created again, which regenerates as well.
+    Recreate,
+}
+
+trait Watcher {
+    /// Blocks until a change was observed, a reload was requested or the timeout expired.
+    fn wait(&mut self, timeout: Option<Duration>) -> Option<Event>;
+
+    /// Whether watched directories were removed or created since the watcher was created,
+    /// so that it has to be created again to observe all changes.
+    fn is_stale(&self) -> bool {
+        false
+    }
+}
+
+/// Regenerates and republishes all outputs of the config whenever the registry changes.
+/// The config file is reloaded on SIGHUP. Only returns if the initial config can not be read.
+pub fn watch(config_path: &Path, run_options: &RunOptions, options: &WatchOptions, report: &mut dyn FnMut(WatchEvent)) -> BoxResult<()> {
+    let mut config = read_config(config_path)?;
+    install_reload_handler();
+
+    loop {
+        // Changes made before the watcher exists are picked up by regenerating afterwards
+        let targets = watch_targets(&config, run_options);
+        let mut watcher = create_watcher(&targets, options, report);
+        regenerate(&config, run_options, report);
+        loop {
+            match next_action(watcher.as_mut(), options.debounce) {
+                Action::Regenerate => regenerate(&config, run_options, report),
+                Action::Reload => {
+                    config = reload_config(config_path, config, report);
+                    break;
+                }
+                Action::Recreate => break,
+            }
+        }
+    }
+}
+
+/// Blocks until the watcher reports a change or a reload request. Changes are only acted on once
+/// no further change followed within `debounce`, so that bursts such as a git pull settle first.
+fn next_action(watcher: &mut dyn Watcher, debounce: Duration) -> Action {
+    loop {
+        match watcher.wait(None) {
+            Some(Event::Changed) => {
+                let mut reload = false;
+                while let Some(event) = watcher.wait(Some(debounce)) {
+                    reload |= event == Event::Reload;
+                }
+                return match (reload, watcher.is_stale()) {
+                    (true, _) => Action::Reload,
+                    (false, true) => Action::Recreate,
+                    (false, false) => Action::Regenerate,
+                };
+            }
+            Some(Event::Reload) => return Action::Reload,
+            None => {}
+        }
+    }
+}
+
+fn regenerate(config: &Config, run_options: &RunOptions, report: &mut dyn FnMut(WatchEvent)) {
+    let start = Instant::now();
+    let mut summary = RunSummary::default();
+    let result = run_config(config, run_options, &mut summary);
+    report(WatchEvent::Regenerated { summary: &summary, result: result.as_ref().map(|_| ()), outputs: config.outputs.len(), elapsed: start.elapsed() });
+}
+
+fn reload_config(config_path: &Path, current: Config, report: &mut dyn FnMut(WatchEvent)) -> Config {
+    match read_config(config_path) {
+        Ok(config) => {
+            report(WatchEvent::Reloaded(config_path));
+            config
+        }
+        Err(err) => {
+            report(WatchEvent::ReloadFailed(err.as_ref()));
+            current
+        }
+    }
+}
+
+fn watch_targets(config: &Config, run_options: &RunOptions) -> Vec<WatchTarget> {
+    let mut targets: Vec<WatchTarget> = Vec::new();
+    let mut files: Vec<PathBuf> = Vec::new();
+    for source in &config.sources {
+        match &source.kind {
+            SourceKind::Main => {
+                let revision = run_options.revision.is_some() || config.revision.is_some();
+                registry_targets(&config.registry, revision, &config.filters, &mut targets, &mut files);
+            }
+            SourceKind::Registry { path, revision, filters } => {
+                registry_targets(path, revision.is_some(), filters, &mut targets, &mut files);
+            }
+            SourceKind::Vrps(path) => files.push(path.clone()),
+        }
+    }
+    files.extend(config.slurm_files.iter().cloned());
+
+    for file in files {
+        let (Some(parent), Some(name)) = (file.parent(), file.file_name()) else {
+            continue;
+        };
+        let parent = if parent.as_os_str().is_empty() { Path::new(".") } else { parent };
+        match targets.iter_mut().find(|t| t.directory == parent) {
+            Some(target) => {
+                if let Some(names) = &mut target.names {
+                    names.push(name.to_owned());
+                }
+            }
+            None => targets.push(WatchTarget { directory: parent.to_path_buf(), names: Some(vec![name.to_owned()]) }),
+        }
+    }
+    targets
+}
+
+/// Adds the directories and files whose changes affect the content of a registry.
+fn registry_targets(registry: &Path, revision: bool, filters: &FilterOverrides, targets: &mut Vec<WatchTarget>, files: &mut Vec<PathBuf>) {
+    let data = registry.join("data");
+    let mut directories: Vec<PathBuf> = Vec::new();
+    let mut checkout = false;
+    if revision {
+        // A revision only changes when refs are updated, e.g. by a fetch
+        if let Ok(git_dir) = find_git_dir(registry) {
+            let refs = git_dir.join("refs");
+            // Refs with a `/` in their name are stored in subdirectories
+            for name in ["heads", "tags", "remotes"] {
+                add_directory_tree(&refs.join(name), &mut directories);
+            }
+            files.extend(["HEAD", "packed-refs", "FETCH_HEAD"].iter().map(|n| git_dir.join(n)));
+        }
+    } else if registry.is_file() {
+        // An archive is replaced as a whole
+        files.push(registry.to_path_buf());
+    } else {
+        directories.push(data.join("route"));
+        directories.push(data.join("route6"));
+        checkout = true;
+    }
+    if filters.filter_v4.is_some() || checkout {
+        files.push(filters.filter_v4.clone().unwrap_or_else(|| data.join("filter.txt")));
+    }
+    if filters.filter_v6.is_some() || checkout {
+        files.push(filters.filter_v6.clone().unwrap_or_else(|| data.join("filter6.txt")));
+    }
+    for directory in directories {
+        if !targets.iter().any(|t| t.directory == directory) {
+            targets.push(WatchTarget { directory, names: None });
+        }
+    }
+}
+
+fn add_directory_tree(directory: &Path, directories: &mut Vec<PathBuf>) {
+    directories.push(directory.to_path_buf());
+    for entry in fs::read_dir(directory).into_iter().flatten().filter_map(Result::ok) {
+        if entry.file_type().is_ok_and(|t| t.is_dir()) {
+            add_directory_tree(&entry.path(), directories);
+        }
+    }
+}
+
+fn create_watcher(targets: &[WatchTarget], options: &WatchOptions, report: &mut dyn FnMut(WatchEvent)) -> Box<dyn Watcher> {
+    #[cfg(target_os = "linux")]
+    if !options.force_polling {
+        match inotify::InotifyWatcher::new(targets) {
+            Ok(watcher) => return Box::new(watcher),
+            Err(err) => report(WatchEvent::Polling(&err)),
+        }
+    }
+    Box::new(PollWatcher::new(targets, options.poll_interval))
+}
+
+/// Size and modification time of every watched file, by path.
+type FileStates = BTreeMap<PathBuf, (u64, Option<SystemTime>)>;
+
+fn scan(targets: &[WatchTarget]) -> FileStates {
+    let mut states = FileStates::new();
+    for target in targets {
+        let Ok(dir) = fs::read_dir(&target.directory) else {
+            continue;
+        };
+        for entry in dir.filter_map(Result::ok).filter(|entry| target.matches(&entry.file_name())) {
+            if let Ok(metadata) = entry.metadata() {
+                states.insert(entry.path(), (metadata.len(), metadata.modified().ok()));
+            }
+        }
+    }
+    states
+}
+
+/// Detects changes by periodically comparing the size and modification time of all watched files.
+struct PollWatcher {
+    targets: Vec<WatchTarget>,
+    interval: Duration,
+    states: FileStates,
+}
+
+impl PollWatcher {
+    fn new(targets: &[WatchTarget], interval: Duration) -> Self {
+        PollWatcher { targets: targets.to_vec(), interval, states: scan(targets) }
+    }
+}
+
+impl Watcher for PollWatcher {
+    fn wait(&mut self, timeout: Option<Duration>) -> Option<Event> {
+        let start = Instant::now();
+        let mut next_scan = start + self.interval.min(timeout.unwrap_or(self.interval));
+        loop {
+            if RELOAD_REQUESTED.swap(false, Ordering::SeqCst) {
+                return Some(Event::Reload);
+            }
+            let now = Instant::now();
+            if now >= next_scan {
+                let states = scan(&self.targets);
+                if states != self.states {
+                    self.states = states;
+                    return Some(Event::Changed);
+                }
+                next_scan = now + self.interval;
+            }
+            if let Some(timeout) = timeout {
+                if now.duration_since(start) >= timeout {
+                    return None;
+                }
+            }
+            thread::sleep(Duration::from_millis(200));
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn install_reload_handler() {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    /// Reports scripted events and records the timeouts it was called with.
+    struct ScriptedWatcher {
+        events: VecDeque<Option<Event>>,
+        timeouts: Vec<Option<Duration>>,
+        stale: bool,
+    }
+
+    impl ScriptedWatcher {
+        fn new(events: &[Option<Event>]) -> Self {
+            ScriptedWatcher { events: events.iter().copied().collect(), timeouts: Vec::new(), stale: false }
+        }
+    }
+
+    impl Watcher for ScriptedWatcher {
+        fn wait(&mut self, timeout: Option<Duration>) -> Option<Event> {
+            self.timeouts.push(timeout);
+            self.events.pop_front().expect("waited for more events than scripted")
+        }
+
+        fn is_stale(&self) -> bool {
+            self.stale
+        }
+    }
+
+    const DEBOUNCE: Duration = Duration::from_secs(2);
+
+    #[test]
+    fn debounce() {
+        // A burst of changes results in one regeneration once it settled
+        let mut watcher = ScriptedWatcher::new(&[Some(Event::Changed), Some(Event::Changed), Some(Event::Changed), None]);
+        assert_eq!(next_action(&mut watcher, DEBOUNCE), Action::Regenerate);
+        assert_eq!(watcher.timeouts, [None, Some(DEBOUNCE), Some(DEBOUNCE), Some(DEBOUNCE)]);
+        assert!(watcher.events.is_empty());
+
+        // Waiting without a change, e.g. after an interrupted wait, keeps waiting
+        let mut watcher = ScriptedWatcher::new(&[None, Some(Event::Changed), None]);
+        assert_eq!(next_action(&mut watcher, DEBOUNCE), Action::Regenerate);
+        assert_eq!(watcher.timeouts, [None, None, Some(DEBOUNCE)]);
+    }
+
+    #[test]
+    fn reload_and_recreate() {
+        let mut watcher = ScriptedWatcher::new(&[Some(Event::Reload)]);
+        assert_eq!(next_action(&mut watcher, DEBOUNCE), Action::Reload);
+
+        // A reload requested during a burst is done once the burst settled
+        let mut watcher = ScriptedWatcher::new(&[Some(Event::Changed), Some(Event::Reload), Some(Event::Changed), None]);
+        assert_eq!(next_action(&mut watcher, DEBOUNCE), Action::Reload);
+        assert!(watcher.events.is_empty());
+
+        let mut watcher = ScriptedWatcher::new(&[Some(Event::Changed), None]);
+        watcher.stale = true;
+        assert_eq!(next_action(&mut watcher, DEBOUNCE), Action::Recreate);
+        let mut watcher = ScriptedWatcher::new(&[Some(Event::Changed), Some(Event::Reload), None]);
+        watcher.stale = true;
+        assert_eq!(next_action(&mut watcher, DEBOUNCE), Action::Reload);
+    }
+
+    fn directory(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("roa_wizard-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(directory.join("route")).unwrap();
+        directory
+    }
+
+    #[test]
+    fn scan_states() {
+        let directory = directory("watch-scan");
+        fs::write(directory.join("route/172.20.0.0_24"), "route").unwrap();
+        fs::write(directory.join("filter.txt"), "filter").unwrap();
+        fs::write(directory.join("README.md"), "readme").unwrap();
+        let targets = [
+            WatchTarget { directory: directory.join("route"), names: None },
+            WatchTarget { directory: directory.clone(), names: Some(vec!["filter.txt".into(), "filter6.txt".into()]) },
+            WatchTarget { directory: directory.join("missing"), names: None },
+        ];
+        let states = scan(&targets);
+        let paths: Vec<&PathBuf> = states.keys().collect();
+        assert_eq!(paths, [&directory.join("filter.txt"), &directory.join("route/172.20.0.0_24")]);
+        assert_eq!(states[&directory.join("filter.txt")].0, 6);
+
+        // Files that are not watched do not matter
+        fs::write(directory.join("README.md"), "changed readme").unwrap();
+        fs::create_dir_all(directory.join("missing")).unwrap();
+        assert_eq!(scan(&targets), states);
+
+        // Changed, added and removed files
+        fs::write(directory.join("filter.txt"), "changed filter").unwrap();
+        let changed = scan(&targets);
+        assert_ne!(changed, states);
+        fs::write(directory.join("route/172.20.1.0_24"), "route").unwrap();
+        let added = scan(&targets);
+        assert_ne!(added, changed);
+        fs::remove_file(directory.join("route/172.20.1.0_24")).unwrap();
+        assert_eq!(scan(&targets), changed);
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn poll_watcher() {
+        let directory = directory("watch-poll");
+        let targets = [WatchTarget { directory: directory.join("route"), names: None }];
+        let mut watcher = PollWatcher::new(&targets, Duration::ZERO);
+        assert_eq!(watcher.wait(Some(Duration::ZERO)), None);
+        fs::write(directory.join("route/172.20.0.0_24"), "route").unwrap();
+        assert_eq!(watcher.wait(Some(Duration::ZERO)), Some(Event::Changed));
+        // The change is only reported once
+        assert_eq!(watcher.wait(Some(Duration::ZERO)), None);
+        assert!(!watcher.is_stale());
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
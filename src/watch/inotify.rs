@@ -0,0 +1,150 @@
+//! Watching directories with inotify, calling the C library directly.
+
+use std::ffi::{CString, OsString};
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::os::raw::{c_char, c_int, c_short, c_ulong};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+use super::{Event, WatchTarget, Watcher, RELOAD_REQUESTED};
+
+const IN_NONBLOCK: c_int = 0o4000;
+const IN_CLOEXEC: c_int = 0o2000000;
+const IN_MODIFY: u32 = 0x2;
+const IN_ATTRIB: u32 = 0x4;
+const IN_CLOSE_WRITE: u32 = 0x8;
+const IN_MOVED_FROM: u32 = 0x40;
+const IN_MOVED_TO: u32 = 0x80;
+const IN_CREATE: u32 = 0x100;
+const IN_DELETE: u32 = 0x200;
+const IN_DELETE_SELF: u32 = 0x400;
+const IN_MOVE_SELF: u32 = 0x800;
+const IN_Q_OVERFLOW: u32 = 0x4000;
+const IN_IGNORED: u32 = 0x8000;
+const IN_ISDIR: u32 = 0x4000_0000;
+const POLLIN: c_short = 0x1;
+const EVENT_HEADER_SIZE: usize = 16;
+
+#[repr(C)]
+struct PollFd {
+    fd: c_int,
+    events: c_short,
+    revents: c_short,
+}
+
+extern "C" {
+    fn inotify_init1(flags: c_int) -> c_int;
+    fn inotify_add_watch(fd: c_int, pathname: *const c_char, mask: u32) -> c_int;
+    fn poll(fds: *mut PollFd, nfds: c_ulong, timeout: c_int) -> c_int;
+}
+
+pub struct InotifyWatcher {
+    file: File,
+    watches: Vec<(c_int, WatchTarget)>,
+    stale: bool,
+}
+
+impl InotifyWatcher {
+    pub fn new(targets: &[WatchTarget]) -> io::Result<Self> {
+        let fd = unsafe { inotify_init1(IN_NONBLOCK | IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // Closes the descriptor when dropped
+        let file = unsafe { File::from_raw_fd(fd) };
+        let mask = IN_MODIFY | IN_ATTRIB | IN_CLOSE_WRITE | IN_MOVED_FROM | IN_MOVED_TO | IN_CREATE | IN_DELETE | IN_DELETE_SELF | IN_MOVE_SELF;
+        let mut watches = Vec::new();
+        for target in targets {
+            // A missing directory is watched for in the closest existing parent
+            let mut target = target.clone();
+            while !target.directory.is_dir() {
+                let (Some(parent), Some(name)) = (target.directory.parent(), target.directory.file_name()) else {
+                    break;
+                };
+                target = WatchTarget { names: Some(vec![name.to_owned()]), directory: parent.to_path_buf() };
+            }
+            let path = CString::new(target.directory.as_os_str().as_bytes())
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            let wd = unsafe { inotify_add_watch(fd, path.as_ptr(), mask) };
+            if wd < 0 {
+                let err = io::Error::last_os_error();
+                return Err(io::Error::new(err.kind(), format!("Unable to watch {}: {}", target.directory.display(), err)));
+            }
+            watches.push((wd, target));
+        }
+        Ok(InotifyWatcher { file, watches, stale: false })
+    }
+
+    /// Reads all queued events and returns whether any of them is relevant.
+    fn drain(&mut self) -> bool {
+        let mut relevant = false;
+        let mut buffer = [0u8; 16384];
+        while let Ok(length) = self.file.read(&mut buffer) {
+            if length == 0 {
+                break;
+            }
+            let mut offset = 0;
+            while offset + EVENT_HEADER_SIZE <= length {
+                let wd = c_int::from_ne_bytes(buffer[offset..offset + 4].try_into().unwrap());
+                let mask = u32::from_ne_bytes(buffer[offset + 4..offset + 8].try_into().unwrap());
+                let name_length = u32::from_ne_bytes(buffer[offset + 12..offset + 16].try_into().unwrap()) as usize;
+                let name_end = (offset + EVENT_HEADER_SIZE + name_length).min(length);
+                let name: Vec<u8> = buffer[offset + EVENT_HEADER_SIZE..name_end].iter().copied().take_while(|b| *b != 0).collect();
+                offset = name_end;
+
+                // Events were lost
+                if mask & IN_Q_OVERFLOW != 0 {
+                    self.stale = true;
+                    relevant = true;
+                    continue;
+                }
+                let Some((_, target)) = self.watches.iter().find(|(w, _)| *w == wd) else {
+                    continue;
+                };
+                let matches = target.matches(&OsString::from_vec(name));
+                // The watch descriptor is gone, or a directory to watch may have appeared
+                if mask & (IN_DELETE_SELF | IN_MOVE_SELF | IN_IGNORED) != 0 || (mask & IN_ISDIR != 0 && mask & (IN_CREATE | IN_MOVED_TO) != 0) {
+                    self.stale = true;
+                    relevant = true;
+                } else if matches {
+                    relevant = true;
+                }
+            }
+        }
+        relevant
+    }
+}
+
+impl Watcher for InotifyWatcher {
+    fn wait(&mut self, timeout: Option<Duration>) -> Option<Event> {
+        let deadline = timeout.map(|t| Instant::now() + t);
+        loop {
+            if RELOAD_REQUESTED.swap(false, Ordering::SeqCst) {
+                return Some(Event::Reload);
+            }
+            let remaining = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return None;
+                    }
+                    (deadline - now).as_millis().min(c_int::MAX as u128) as c_int
+                }
+                None => -1,
+            };
+            let mut fds = PollFd { fd: self.file.as_raw_fd(), events: POLLIN, revents: 0 };
+            // Interrupted by a signal when a reload is requested
+            let ready = unsafe { poll(&mut fds, 1, remaining) };
+            if ready > 0 && self.drain() {
+                return Some(Event::Changed);
+            }
+        }
+    }
+
+    fn is_stale(&self) -> bool {
+        self.stale
+    }
+}
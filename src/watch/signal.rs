@@ -0,0 +1,20 @@
+use std::os::raw::c_int;
+use std::sync::atomic::Ordering;
+use super::RELOAD_REQUESTED;
+
+const SIGHUP: c_int = 1;
+
+extern "C" fn handle_sighup(_: c_int) {
+    RELOAD_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+extern "C" {
+    fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> usize;
+}
+
+/// Requests a reload of the config file on SIGHUP.
+pub fn install_reload_handler() {
+    unsafe {
+        signal(SIGHUP, handle_sighup);
+    }
+}